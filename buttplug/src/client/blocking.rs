@@ -0,0 +1,324 @@
+// Buttplug Rust Source Code File - See https://buttplug.io for more info.
+//
+// Copyright 2016-2020 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+
+//! Synchronous wrappers around [ButtplugClient] and [ButtplugClientDevice].
+//!
+//! For applications that can't run an executor themselves. The client still
+//! runs its event loop on the library's [async_manager] runtime, but every
+//! method here blocks the calling thread until the server replies, and events
+//! are read through an iterator or callback instead of a stream.
+//!
+//! As these methods block, they should never be called from inside a future.
+
+use super::{
+  device::{ButtplugClientDevice, LinearCommand, RotateCommand, VibrateCommand},
+  ButtplugClient,
+  ButtplugClientError,
+  ButtplugClientEvent,
+  ButtplugClientReconnectPolicy,
+};
+use crate::{
+  connector::ButtplugConnector,
+  core::messages::{
+    ButtplugCurrentSpecClientMessage,
+    ButtplugCurrentSpecServerMessage,
+    ButtplugMessageSpecVersion,
+    MessageAttributesMap,
+  },
+  device::Endpoint,
+  util::async_manager,
+};
+use async_channel::{unbounded, Receiver, Sender};
+use futures::{Stream, StreamExt};
+use std::{
+  sync::{Arc, Mutex},
+  time::Duration,
+};
+
+type ButtplugClientEventCallback = Arc<Mutex<dyn FnMut(ButtplugClientEvent) + Send>>;
+
+/// Synchronous version of [ButtplugClient].
+///
+/// Events from the server are queued until read with
+/// [BlockingButtplugClient::event_iter] or
+/// [BlockingButtplugClient::try_next_event], unless a callback is set with
+/// [BlockingButtplugClient::set_event_callback].
+pub struct BlockingButtplugClient {
+  client: ButtplugClient,
+  event_receiver: Receiver<ButtplugClientEvent>,
+  event_callback: Arc<Mutex<Option<ButtplugClientEventCallback>>>,
+}
+
+impl BlockingButtplugClient {
+  /// Connects to a server, blocking until the handshake is finished. See
+  /// [ButtplugClient::connect].
+  pub fn connect<ConnectorType>(
+    name: &str,
+    connector: ConnectorType,
+  ) -> Result<Self, ButtplugClientError>
+  where
+    ConnectorType: ButtplugConnector<ButtplugCurrentSpecClientMessage, ButtplugCurrentSpecServerMessage>
+      + 'static,
+  {
+    let (client, events) = async_manager::block_on(ButtplugClient::connect(name, connector))?;
+    Ok(BlockingButtplugClient::new(client, events))
+  }
+
+  /// Connects to a server, reconnecting if the connection drops. See
+  /// [ButtplugClient::connect_with_reconnect].
+  pub fn connect_with_reconnect<ConnectorType, F>(
+    name: &str,
+    connector_factory: F,
+    policy: ButtplugClientReconnectPolicy,
+  ) -> Result<Self, ButtplugClientError>
+  where
+    ConnectorType: ButtplugConnector<ButtplugCurrentSpecClientMessage, ButtplugCurrentSpecServerMessage>
+      + 'static,
+    F: Fn() -> ConnectorType + Send + Sync + 'static,
+  {
+    let (client, events) = async_manager::block_on(ButtplugClient::connect_with_reconnect(
+      name,
+      connector_factory,
+      policy,
+    ))?;
+    Ok(BlockingButtplugClient::new(client, events))
+  }
+
+  /// Connects to a server in the same process. See
+  /// [ButtplugClient::connect_in_process].
+  #[cfg(feature = "server")]
+  pub fn connect_in_process(
+    name: &str,
+    options: &crate::server::ButtplugServerOptions,
+  ) -> Result<Self, ButtplugClientError> {
+    let (client, events) =
+      async_manager::block_on(ButtplugClient::connect_in_process(name, options))?;
+    Ok(BlockingButtplugClient::new(client, events))
+  }
+
+  fn new(
+    client: ButtplugClient,
+    events: impl Stream<Item = ButtplugClientEvent> + Send + 'static,
+  ) -> Self {
+    let (event_sender, event_receiver): (Sender<ButtplugClientEvent>, _) = unbounded();
+    let event_callback: Arc<Mutex<Option<ButtplugClientEventCallback>>> =
+      Arc::new(Mutex::new(None));
+    let event_callback_clone = event_callback.clone();
+    async_manager::spawn(async move {
+      let mut events = Box::pin(events);
+      while let Some(event) = events.next().await {
+        // Hand the event to the callback if there is one, otherwise queue it
+        // for the event iterator. The callback is taken out of the lock first,
+        // so it can call set_event_callback itself.
+        let callback = event_callback_clone.lock().unwrap().clone();
+        if let Some(callback) = callback {
+          (*callback.lock().unwrap())(event);
+          continue;
+        }
+        if event_sender.try_send(event).is_err() {
+          // The blocking client has been dropped.
+          break;
+        }
+      }
+    })
+    .unwrap();
+    Self {
+      client,
+      event_receiver,
+      event_callback,
+    }
+  }
+
+  /// Returns the [ButtplugClient] being wrapped, for anything that isn't
+  /// exposed here.
+  pub fn client(&self) -> &ButtplugClient {
+    &self.client
+  }
+
+  pub fn client_name(&self) -> &str {
+    &self.client.client_name
+  }
+
+  pub fn server_name(&self) -> &str {
+    &self.client.server_name
+  }
+
+  pub fn server_spec_version(&self) -> ButtplugMessageSpecVersion {
+    self.client.server_spec_version()
+  }
+
+  /// Returns true if client is currently connected.
+  pub fn connected(&self) -> bool {
+    self.client.connected()
+  }
+
+  /// Disconnects from the server. See [ButtplugClient::disconnect].
+  pub fn disconnect(&self) -> Result<(), ButtplugClientError> {
+    async_manager::block_on(self.client.disconnect())
+  }
+
+  /// Tells server to start scanning for devices.
+  pub fn start_scanning(&self) -> Result<(), ButtplugClientError> {
+    async_manager::block_on(self.client.start_scanning())
+  }
+
+  /// Tells server to scan for devices, and stop after `duration`. Returns once
+  /// scanning has started.
+  pub fn start_scanning_with_duration(
+    &self,
+    duration: Duration,
+  ) -> Result<(), ButtplugClientError> {
+    async_manager::block_on(self.client.start_scanning_with_duration(duration))
+  }
+
+  /// Tells server to stop scanning for devices.
+  pub fn stop_scanning(&self) -> Result<(), ButtplugClientError> {
+    async_manager::block_on(self.client.stop_scanning())
+  }
+
+  /// Tells server to stop all devices.
+  pub fn stop_all_devices(&self) -> Result<(), ButtplugClientError> {
+    async_manager::block_on(self.client.stop_all_devices())
+  }
+
+  /// Sets the timeout for messages to the server. See
+  /// [ButtplugClient::set_default_timeout].
+  pub fn set_default_timeout(&self, timeout: Option<Duration>) {
+    self.client.set_default_timeout(timeout)
+  }
+
+  /// Returns the currently connected devices.
+  pub fn devices(&self) -> Vec<BlockingButtplugClientDevice> {
+    self
+      .client
+      .devices()
+      .into_iter()
+      .map(BlockingButtplugClientDevice::from)
+      .collect()
+  }
+
+  /// Returns an iterator over events from the server. Each call to `next()`
+  /// blocks until there's an event, and the iterator ends once the client has
+  /// disconnected and all events have been read.
+  ///
+  /// Events handled by a callback set with
+  /// [BlockingButtplugClient::set_event_callback] won't show up here.
+  pub fn event_iter(&self) -> impl Iterator<Item = ButtplugClientEvent> + '_ {
+    std::iter::from_fn(move || async_manager::block_on(self.event_receiver.recv()).ok())
+  }
+
+  /// Returns the next event from the server if one is waiting, without
+  /// blocking.
+  pub fn try_next_event(&self) -> Option<ButtplugClientEvent> {
+    self.event_receiver.try_recv().ok()
+  }
+
+  /// Calls `callback` for every event from the server, instead of queuing
+  /// them for [BlockingButtplugClient::event_iter]. The callback runs on the
+  /// library's runtime, not the thread that set it, so it shouldn't block.
+  /// Passing None goes back to queuing events.
+  pub fn set_event_callback(
+    &self,
+    callback: Option<impl FnMut(ButtplugClientEvent) + Send + 'static>,
+  ) {
+    *self.event_callback.lock().unwrap() =
+      callback.map(|callback| Arc::new(Mutex::new(callback)) as ButtplugClientEventCallback);
+  }
+}
+
+/// Synchronous version of [ButtplugClientDevice].
+#[derive(Clone)]
+pub struct BlockingButtplugClientDevice {
+  device: ButtplugClientDevice,
+}
+
+impl From<ButtplugClientDevice> for BlockingButtplugClientDevice {
+  fn from(device: ButtplugClientDevice) -> Self {
+    Self { device }
+  }
+}
+
+impl BlockingButtplugClientDevice {
+  /// Returns the [ButtplugClientDevice] being wrapped.
+  pub fn device(&self) -> &ButtplugClientDevice {
+    &self.device
+  }
+
+  pub fn name(&self) -> &str {
+    &self.device.name
+  }
+
+  pub fn index(&self) -> u32 {
+    self.device.index()
+  }
+
+  pub fn allowed_messages(&self) -> &MessageAttributesMap {
+    &self.device.allowed_messages
+  }
+
+  /// Returns a handle to this device that uses `timeout` for its commands.
+  /// See [ButtplugClientDevice::with_timeout].
+  pub fn with_timeout(&self, timeout: Duration) -> Self {
+    Self {
+      device: self.device.with_timeout(timeout),
+    }
+  }
+
+  /// Commands device to vibrate, assuming it has the features to do so.
+  pub fn vibrate(&self, speed_cmd: VibrateCommand) -> Result<(), ButtplugClientError> {
+    async_manager::block_on(self.device.vibrate(speed_cmd))
+  }
+
+  /// Commands device to move linearly, assuming it has the features to do so.
+  pub fn linear(&self, linear_cmd: LinearCommand) -> Result<(), ButtplugClientError> {
+    async_manager::block_on(self.device.linear(linear_cmd))
+  }
+
+  /// Commands device to rotate, assuming it has the features to do so.
+  pub fn rotate(&self, rotate_cmd: RotateCommand) -> Result<(), ButtplugClientError> {
+    async_manager::block_on(self.device.rotate(rotate_cmd))
+  }
+
+  pub fn battery_level(&self) -> Result<f64, ButtplugClientError> {
+    async_manager::block_on(self.device.battery_level())
+  }
+
+  pub fn rssi_level(&self) -> Result<i32, ButtplugClientError> {
+    async_manager::block_on(self.device.rssi_level())
+  }
+
+  pub fn raw_write(
+    &self,
+    endpoint: Endpoint,
+    data: Vec<u8>,
+    write_with_response: bool,
+  ) -> Result<(), ButtplugClientError> {
+    async_manager::block_on(self.device.raw_write(endpoint, data, write_with_response))
+  }
+
+  pub fn raw_read(
+    &self,
+    endpoint: Endpoint,
+    expected_length: u32,
+    timeout: u32,
+  ) -> Result<Vec<u8>, ButtplugClientError> {
+    async_manager::block_on(self.device.raw_read(endpoint, expected_length, timeout))
+  }
+
+  pub fn raw_subscribe(&self, endpoint: Endpoint) -> Result<(), ButtplugClientError> {
+    async_manager::block_on(self.device.raw_subscribe(endpoint))
+  }
+
+  pub fn raw_unsubscribe(&self, endpoint: Endpoint) -> Result<(), ButtplugClientError> {
+    async_manager::block_on(self.device.raw_unsubscribe(endpoint))
+  }
+
+  /// Commands device to stop all movement.
+  pub fn stop(&self) -> Result<(), ButtplugClientError> {
+    async_manager::block_on(self.device.stop())
+  }
+}
@@ -8,7 +8,12 @@
 //! Handling of remote message pairing and future resolution.
 
 use crate::{
-  client::{ButtplugClientMessageFuturePair, ButtplugClientMessageStateShared},
+  client::{
+    ButtplugClientError,
+    ButtplugClientMessageFuturePair,
+    ButtplugClientMessageStateShared,
+  },
+  connector::ButtplugConnectorError,
   core::{
     errors::{ButtplugError, ButtplugServerError},
     messages::{ButtplugCurrentSpecServerMessage, ButtplugMessage},
@@ -116,6 +121,27 @@ impl ClientMessageSorter {
       }
     }
   }
+
+  /// Resolves the future waiting on `id` with an error, for when the message
+  /// couldn't be sent or timed out. Removes the future from the map, so a
+  /// response arriving later is treated like any other unmatched message.
+  ///
+  /// Does nothing if the future was already resolved.
+  pub fn fail_future(&mut self, id: u32, err: ButtplugClientError) {
+    if let Some(state) = self.future_map.remove(&id) {
+      trace!("Failing future for id {}: {:?}", id, err);
+      state.set_reply(Err(err));
+    }
+  }
+
+  /// Resolves every future still waiting on a response with an error. Used
+  /// when the connection to the server drops, as those responses won't arrive.
+  pub fn fail_all_futures(&mut self) {
+    for (id, state) in self.future_map.drain() {
+      trace!("Failing future for id {}, connection closed.", id);
+      state.set_reply(Err(ButtplugConnectorError::ConnectorChannelClosed.into()));
+    }
+  }
 }
 
 impl Default for ClientMessageSorter {
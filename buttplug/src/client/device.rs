@@ -7,7 +7,13 @@
 
 //! Representation and management of devices connected to the server.
 
-use super::{ButtplugClientError, ButtplugClientRequest, ButtplugClientResultFuture};
+use super::{
+  funscript::{self, Funscript, FunscriptClock, FunscriptPlayerOptions},
+  pattern::{self, ButtplugClientPattern, ButtplugClientPatternHandle, PatternTarget},
+  ButtplugClientError,
+  ButtplugClientRequest,
+  ButtplugClientResultFuture,
+};
 use crate::{
   client::{ButtplugClientMessageFuture, ButtplugClientMessageFuturePair},
   connector::ButtplugConnectorError,
@@ -46,7 +52,9 @@ use std::{
   sync::{
     atomic::{AtomicBool, Ordering},
     Arc,
+    Mutex,
   },
+  time::Duration,
 };
 use tracing_futures::Instrument;
 
@@ -114,6 +122,75 @@ pub enum LinearCommand {
   LinearMap(HashMap<u32, (u32, f64)>),
 }
 
+/// Features a [ButtplugClientDevice] can have, for use with
+/// [ButtplugClientDevice::has_capability] and
+/// [ButtplugClient::devices_with][super::ButtplugClient::devices_with].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ButtplugClientDeviceCapability {
+  Vibrate,
+  Rotate,
+  Linear,
+  BatteryLevel,
+  RSSILevel,
+}
+
+impl ButtplugClientDeviceCapability {
+  fn message_type(self) -> ButtplugDeviceMessageType {
+    match self {
+      ButtplugClientDeviceCapability::Vibrate => ButtplugDeviceMessageType::VibrateCmd,
+      ButtplugClientDeviceCapability::Rotate => ButtplugDeviceMessageType::RotateCmd,
+      ButtplugClientDeviceCapability::Linear => ButtplugDeviceMessageType::LinearCmd,
+      ButtplugClientDeviceCapability::BatteryLevel => ButtplugDeviceMessageType::BatteryLevelCmd,
+      ButtplugClientDeviceCapability::RSSILevel => ButtplugDeviceMessageType::RSSILevelCmd,
+    }
+  }
+}
+
+/// Last known state of a device, as commanded through the client.
+///
+/// Only reflects commands that the server accepted. Shared between all
+/// [ButtplugClientDevice] instances for the same device.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct ButtplugClientDeviceState {
+  /// Last speed sent to each vibration feature.
+  pub vibrate: Vec<f64>,
+  /// Last speed and direction (clockwise if true) sent to each rotation
+  /// feature.
+  pub rotate: Vec<(f64, bool)>,
+  /// Last position sent to each linear feature. None until a position has
+  /// been sent, as we don't know where the device starts.
+  pub linear: Vec<Option<f64>>,
+  /// Last battery level read from the device.
+  pub battery_level: Option<f64>,
+  /// Last RSSI level read from the device.
+  pub rssi_level: Option<i32>,
+}
+
+impl ButtplugClientDeviceState {
+  pub(super) fn new(allowed_messages: &MessageAttributesMap) -> Self {
+    let feature_count = |msg_type| {
+      allowed_messages
+        .get(&msg_type)
+        .and_then(|attrs| attrs.feature_count)
+        .unwrap_or(0) as usize
+    };
+    Self {
+      vibrate: vec![0.0; feature_count(ButtplugDeviceMessageType::VibrateCmd)],
+      rotate: vec![(0.0, true); feature_count(ButtplugDeviceMessageType::RotateCmd)],
+      linear: vec![None; feature_count(ButtplugDeviceMessageType::LinearCmd)],
+      battery_level: None,
+      rssi_level: None,
+    }
+  }
+
+  /// Updates state for a device being stopped. Linear features stay where
+  /// they are.
+  pub(super) fn stop(&mut self) {
+    self.vibrate.iter_mut().for_each(|speed| *speed = 0.0);
+    self.rotate.iter_mut().for_each(|rotation| rotation.0 = 0.0);
+  }
+}
+
 // Using a macro here so we can encabe the return statement. Otherwise we'd have
 // to do validity checks on every call since we return futures, not results.
 macro_rules! check_message_support {
@@ -142,6 +219,9 @@ pub struct ButtplugClientDevice {
   /// Map of messages the device can take, along with the attributes of those
   /// messages.
   pub allowed_messages: MessageAttributesMap,
+  /// Identifier of the device if the server didn't have it in its
+  /// configuration.
+  unknown_identifier: Option<String>,
   /// Sends commands from the [ButtplugClientDevice] instance to the
   /// [ButtplugClient][super::ButtplugClient]'s event loop, which will then send
   /// the message on to the [ButtplugServer][crate::server::ButtplugServer]
@@ -161,6 +241,12 @@ pub struct ButtplugClientDevice {
   /// [ButtplugClientDevice] instance is still connected to the
   /// [ButtplugServer][crate::server::ButtplugServer].
   client_connected: Arc<AtomicBool>,
+  /// Timeout for messages sent by this handle. If None, the
+  /// [ButtplugClient][super::ButtplugClient]'s default timeout is used.
+  timeout: Option<Duration>,
+  /// Last commanded values and readings, shared with the
+  /// [ButtplugClient][super::ButtplugClient]'s device map.
+  state: Arc<Mutex<ButtplugClientDeviceState>>,
 }
 
 unsafe impl Send for ButtplugClientDevice {
@@ -186,8 +272,10 @@ impl ButtplugClientDevice {
     name: &str,
     index: u32,
     allowed_messages: MessageAttributesMap,
+    unknown_identifier: Option<String>,
     message_sender: Sender<ButtplugClientRequest>,
     event_receiver: BroadcastChannel<ButtplugClientDeviceEvent>,
+    state: Arc<Mutex<ButtplugClientDeviceState>>,
   ) -> Self {
     info!(
       "Creating client device {} with index {} and messages {:?}.",
@@ -234,13 +322,26 @@ impl ButtplugClientDevice {
       name: name.to_owned(),
       index,
       allowed_messages,
+      unknown_identifier,
       message_sender,
       event_receiver,
       device_connected,
       client_connected,
+      timeout: None,
+      state,
     }
   }
 
+  /// Returns a handle to this device that uses `timeout` for its commands
+  /// instead of the client's default timeout. If the server doesn't reply in
+  /// time, commands fail with
+  /// [ButtplugClientError::MessageTimeout][super::ButtplugClientError::MessageTimeout].
+  pub fn with_timeout(&self, timeout: Duration) -> Self {
+    let mut device = self.clone();
+    device.timeout = Some(timeout);
+    device
+  }
+
   /// Sends a message through the owning
   /// [ButtplugClient][super::ButtplugClient].
   ///
@@ -255,6 +356,7 @@ impl ButtplugClientDevice {
     let device_connected = self.device_connected.clone();
     let id = msg.get_id();
     let device_name = self.name.clone();
+    let timeout = self.timeout;
     Box::pin(
       async move {
         if !client_connected.load(Ordering::SeqCst) {
@@ -269,7 +371,11 @@ impl ButtplugClientDevice {
         let fut = ButtplugClientMessageFuture::default();
         message_sender
           .send(ButtplugClientRequest::Message(
-            ButtplugClientMessageFuturePair::new(msg.clone(), fut.get_state_clone()),
+            ButtplugClientMessageFuturePair::new_with_timeout(
+              msg.clone(),
+              fut.get_state_clone(),
+              timeout,
+            ),
           ))
           .await
           .map_err(|_| {
@@ -321,18 +427,93 @@ impl ButtplugClientDevice {
     })
   }
 
+  /// Sends a message expecting an [Ok][crate::core::messages::Ok] back, then
+  /// updates the device state with `update` if it succeeded.
+  fn send_message_expect_ok_and_update(
+    &self,
+    msg: ButtplugCurrentSpecClientMessage,
+    update: impl FnOnce(&mut ButtplugClientDeviceState) + Send + 'static,
+  ) -> ButtplugClientResultFuture {
+    let send_fut = self.send_message_expect_ok(msg);
+    let state = self.state.clone();
+    Box::pin(async move {
+      send_fut.await?;
+      update(&mut state.lock().unwrap());
+      Ok(())
+    })
+  }
+
+  fn feature_count(&self, msg_type: ButtplugDeviceMessageType) -> u32 {
+    self
+      .allowed_messages
+      .get(&msg_type)
+      .and_then(|attrs| attrs.feature_count)
+      .unwrap_or(0)
+  }
+
+  fn step_counts(&self, msg_type: ButtplugDeviceMessageType) -> Vec<u32> {
+    self
+      .allowed_messages
+      .get(&msg_type)
+      .and_then(|attrs| attrs.step_count.clone())
+      .unwrap_or_default()
+  }
+
+  /// Returns the number of vibration features on the device.
+  pub fn vibrator_count(&self) -> u32 {
+    self.feature_count(ButtplugDeviceMessageType::VibrateCmd)
+  }
+
+  /// Returns the number of speed steps for each vibration feature.
+  pub fn vibrate_step_counts(&self) -> Vec<u32> {
+    self.step_counts(ButtplugDeviceMessageType::VibrateCmd)
+  }
+
+  /// Returns the number of rotation features on the device.
+  pub fn rotator_count(&self) -> u32 {
+    self.feature_count(ButtplugDeviceMessageType::RotateCmd)
+  }
+
+  /// Returns the number of speed steps for each rotation feature.
+  pub fn rotate_step_counts(&self) -> Vec<u32> {
+    self.step_counts(ButtplugDeviceMessageType::RotateCmd)
+  }
+
+  /// Returns the number of linear features on the device.
+  pub fn linear_count(&self) -> u32 {
+    self.feature_count(ButtplugDeviceMessageType::LinearCmd)
+  }
+
+  /// Returns the number of position steps for each linear feature.
+  pub fn linear_step_counts(&self) -> Vec<u32> {
+    self.step_counts(ButtplugDeviceMessageType::LinearCmd)
+  }
+
+  pub fn supports_battery_level(&self) -> bool {
+    self.has_capability(ButtplugClientDeviceCapability::BatteryLevel)
+  }
+
+  pub fn supports_rssi_level(&self) -> bool {
+    self.has_capability(ButtplugClientDeviceCapability::RSSILevel)
+  }
+
+  /// Returns true if the device has `capability`.
+  pub fn has_capability(&self, capability: ButtplugClientDeviceCapability) -> bool {
+    self
+      .allowed_messages
+      .contains_key(&capability.message_type())
+  }
+
+  /// Returns a snapshot of the last values sent to the device and the last
+  /// readings received from it.
+  pub fn state(&self) -> ButtplugClientDeviceState {
+    self.state.lock().unwrap().clone()
+  }
+
   /// Commands device to vibrate, assuming it has the features to do so.
   pub fn vibrate(&self, speed_cmd: VibrateCommand) -> ButtplugClientResultFuture {
     check_message_support!(self, ButtplugDeviceMessageType::VibrateCmd);
-    let mut vibrator_count: u32 = 0;
-    if let Some(features) = self
-      .allowed_messages
-      .get(&ButtplugDeviceMessageType::VibrateCmd)
-    {
-      if let Some(v) = features.feature_count {
-        vibrator_count = v;
-      }
-    }
+    let vibrator_count = self.vibrator_count();
     let mut speed_vec: Vec<VibrateSubcommand>;
     match speed_cmd {
       VibrateCommand::Speed(speed) => {
@@ -371,22 +552,20 @@ impl ButtplugClientDevice {
         }
       }
     }
-    let msg = VibrateCmd::new(self.index, speed_vec).into();
-    self.send_message_expect_ok(msg)
+    let msg = VibrateCmd::new(self.index, speed_vec.clone()).into();
+    self.send_message_expect_ok_and_update(msg, move |state| {
+      for cmd in speed_vec {
+        if let Some(speed) = state.vibrate.get_mut(cmd.index as usize) {
+          *speed = cmd.speed;
+        }
+      }
+    })
   }
 
   /// Commands device to move linearly, assuming it has the features to do so.
   pub fn linear(&self, linear_cmd: LinearCommand) -> ButtplugClientResultFuture {
     check_message_support!(self, ButtplugDeviceMessageType::LinearCmd);
-    let mut linear_count: u32 = 0;
-    if let Some(features) = self
-      .allowed_messages
-      .get(&ButtplugDeviceMessageType::LinearCmd)
-    {
-      if let Some(v) = features.feature_count {
-        linear_count = v;
-      }
-    }
+    let linear_count = self.linear_count();
     let mut linear_vec: Vec<VectorSubcommand>;
     match linear_cmd {
       LinearCommand::Linear(dur, pos) => {
@@ -423,22 +602,20 @@ impl ButtplugClientDevice {
         }
       }
     }
-    let msg = LinearCmd::new(self.index, linear_vec).into();
-    self.send_message_expect_ok(msg)
+    let msg = LinearCmd::new(self.index, linear_vec.clone()).into();
+    self.send_message_expect_ok_and_update(msg, move |state| {
+      for cmd in linear_vec {
+        if let Some(position) = state.linear.get_mut(cmd.index as usize) {
+          *position = Some(cmd.position);
+        }
+      }
+    })
   }
 
   /// Commands device to rotate, assuming it has the features to do so.
   pub fn rotate(&self, rotate_cmd: RotateCommand) -> ButtplugClientResultFuture {
     check_message_support!(self, ButtplugDeviceMessageType::RotateCmd);
-    let mut rotate_count: u32 = 0;
-    if let Some(features) = self
-      .allowed_messages
-      .get(&ButtplugDeviceMessageType::RotateCmd)
-    {
-      if let Some(v) = features.feature_count {
-        rotate_count = v;
-      }
-    }
+    let rotate_count = self.rotator_count();
     let mut rotate_vec: Vec<RotationSubcommand>;
     match rotate_cmd {
       RotateCommand::Rotate(speed, clockwise) => {
@@ -475,17 +652,60 @@ impl ButtplugClientDevice {
         }
       }
     }
-    let msg = RotateCmd::new(self.index, rotate_vec).into();
-    self.send_message_expect_ok(msg)
+    let msg = RotateCmd::new(self.index, rotate_vec.clone()).into();
+    self.send_message_expect_ok_and_update(msg, move |state| {
+      for cmd in rotate_vec {
+        if let Some(rotation) = state.rotate.get_mut(cmd.index as usize) {
+          *rotation = (cmd.speed, cmd.clockwise);
+        }
+      }
+    })
+  }
+
+  /// Plays a [ButtplugClientPattern] on the device's vibration features,
+  /// assuming it has the features to do so. Updates are only sent when they'd
+  /// change the device's speed, given its step counts.
+  pub fn vibrate_pattern(
+    &self,
+    pattern: ButtplugClientPattern,
+  ) -> Result<ButtplugClientPatternHandle, ButtplugClientError> {
+    pattern::play_pattern(self, pattern, PatternTarget::Vibrate)
+  }
+
+  /// Plays a [ButtplugClientPattern] on the device's rotation features,
+  /// assuming it has the features to do so. Negative pattern values rotate
+  /// counterclockwise.
+  pub fn rotate_pattern(
+    &self,
+    pattern: ButtplugClientPattern,
+  ) -> Result<ButtplugClientPatternHandle, ButtplugClientError> {
+    pattern::play_pattern(self, pattern, PatternTarget::Rotate)
+  }
+
+  /// Plays a [Funscript] on the device, following `clock`. Linear devices
+  /// move to each action, vibrating devices vibrate based on stroke speed,
+  /// unless [FunscriptPlayerOptions::output] says otherwise. Playback runs
+  /// until cancelled.
+  pub fn play_funscript(
+    &self,
+    script: Funscript,
+    clock: Arc<dyn FunscriptClock>,
+    options: FunscriptPlayerOptions,
+  ) -> Result<ButtplugClientPatternHandle, ButtplugClientError> {
+    funscript::play_funscript(self, script, clock, options)
   }
 
   pub fn battery_level(&self) -> ButtplugClientResultFuture<f64> {
     check_message_support!(self, ButtplugDeviceMessageType::BatteryLevelCmd);
     let msg = ButtplugCurrentSpecClientMessage::BatteryLevelCmd(BatteryLevelCmd::new(self.index));
     let send_fut = self.send_message(msg);
+    let state = self.state.clone();
     Box::pin(async move {
       match send_fut.await? {
-        ButtplugCurrentSpecServerMessage::BatteryLevelReading(reading) => Ok(reading.battery_level),
+        ButtplugCurrentSpecServerMessage::BatteryLevelReading(reading) => {
+          state.lock().unwrap().battery_level = Some(reading.battery_level);
+          Ok(reading.battery_level)
+        }
         ButtplugCurrentSpecServerMessage::Error(err) => Err(ButtplugError::from(err).into()),
         msg => Err(
           ButtplugError::from(ButtplugMessageError::UnexpectedMessageType(format!(
@@ -502,9 +722,13 @@ impl ButtplugClientDevice {
     check_message_support!(self, ButtplugDeviceMessageType::RSSILevelCmd);
     let msg = ButtplugCurrentSpecClientMessage::RSSILevelCmd(RSSILevelCmd::new(self.index));
     let send_fut = self.send_message(msg);
+    let state = self.state.clone();
     Box::pin(async move {
       match send_fut.await? {
-        ButtplugCurrentSpecServerMessage::RSSILevelReading(reading) => Ok(reading.rssi_level),
+        ButtplugCurrentSpecServerMessage::RSSILevelReading(reading) => {
+          state.lock().unwrap().rssi_level = Some(reading.rssi_level);
+          Ok(reading.rssi_level)
+        }
         ButtplugCurrentSpecServerMessage::Error(err) => Err(ButtplugError::from(err).into()),
         msg => Err(
           ButtplugError::from(ButtplugMessageError::UnexpectedMessageType(format!(
@@ -582,12 +806,22 @@ impl ButtplugClientDevice {
     // Everything *should* support StopDeviceCmd but let's just make sure.
     check_message_support!(self, ButtplugDeviceMessageType::StopDeviceCmd);
     // All devices accept StopDeviceCmd
-    self.send_message_expect_ok(StopDeviceCmd::default().into())
+    self.send_message_expect_ok_and_update(StopDeviceCmd::new(self.index).into(), |state| {
+      state.stop()
+    })
   }
 
   pub fn index(&self) -> u32 {
     self.index
   }
+
+  /// Returns the device's identifier if the server doesn't have it in its
+  /// configuration. Devices like this are still usable, but are running on
+  /// their protocol's defaults, so names and capabilities may be off. Users
+  /// can report the identifier so it gets added.
+  pub fn unknown_identifier(&self) -> Option<&str> {
+    self.unknown_identifier.as_deref()
+  }
 }
 
 impl Eq for ButtplugClientDevice {
@@ -604,6 +838,7 @@ impl
     &DeviceMessageInfo,
     Sender<ButtplugClientRequest>,
     BroadcastChannel<ButtplugClientDeviceEvent>,
+    Arc<Mutex<ButtplugClientDeviceState>>,
   )> for ButtplugClientDevice
 {
   fn from(
@@ -611,6 +846,7 @@ impl
       &DeviceMessageInfo,
       Sender<ButtplugClientRequest>,
       BroadcastChannel<ButtplugClientDeviceEvent>,
+      Arc<Mutex<ButtplugClientDeviceState>>,
     ),
   ) -> Self {
     let msg = msg_sender_tuple.0.clone();
@@ -618,8 +854,10 @@ impl
       &*msg.device_name,
       msg.device_index,
       msg.device_messages,
+      msg.unknown_identifier,
       msg_sender_tuple.1,
       msg_sender_tuple.2,
+      msg_sender_tuple.3,
     )
   }
 }
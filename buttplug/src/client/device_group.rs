@@ -0,0 +1,136 @@
+// Buttplug Rust Source Code File - See https://buttplug.io for more info.
+//
+// Copyright 2016-2020 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+
+//! Groups of devices that can be commanded together.
+
+use super::{
+  device::{
+    ButtplugClientDevice,
+    ButtplugClientDeviceCapability,
+    LinearCommand,
+    RotateCommand,
+    VibrateCommand,
+  },
+  ButtplugClient,
+  ButtplugClientResultFuture,
+};
+use futures::future;
+use std::sync::Arc;
+
+pub(super) type ButtplugClientDeviceFilter =
+  Arc<dyn Fn(&ButtplugClientDevice) -> bool + Send + Sync>;
+
+/// Command for a [ButtplugClientDeviceGroup], translated into whatever each
+/// device in the group supports.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ButtplugClientDeviceGroupCommand {
+  /// Runs vibration and rotation features at the given speed (0.0-1.0).
+  /// Rotation is clockwise. Linear features are left where they are.
+  Intensity(f64),
+  /// Moves linear features to a position (0.0-1.0) over a duration in
+  /// milliseconds, matching [LinearCommand::Linear]. Vibration and rotation
+  /// features run at a speed equal to the position.
+  Move(u32, f64),
+  /// Stops all features.
+  Stop,
+}
+
+/// A set of devices that receive the same commands.
+///
+/// Membership follows the client's device list, so devices matching the
+/// group's filter join when they're added to the server, and leave when
+/// they're removed. Obtained from [ButtplugClient::device_group] or
+/// [ButtplugClient::device_group_with_filter].
+#[derive(Clone)]
+pub struct ButtplugClientDeviceGroup {
+  client: Arc<ButtplugClient>,
+  filter: ButtplugClientDeviceFilter,
+}
+
+impl ButtplugClientDeviceGroup {
+  pub(super) fn new(client: Arc<ButtplugClient>, filter: ButtplugClientDeviceFilter) -> Self {
+    Self { client, filter }
+  }
+
+  /// Returns the devices currently in the group.
+  pub fn devices(&self) -> Vec<ButtplugClientDevice> {
+    self
+      .client
+      .devices()
+      .into_iter()
+      .filter(|device| (self.filter)(device))
+      .collect()
+  }
+
+  pub fn len(&self) -> usize {
+    self.devices().len()
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.len() == 0
+  }
+
+  /// Sends `command` to every device in the group, translated to each
+  /// device's features.
+  ///
+  /// Commands for all devices are sent before waiting on any replies, so
+  /// devices start as close together as possible. Devices that don't have
+  /// any features the command applies to are skipped. If any device fails,
+  /// the first error is returned once every device has replied.
+  pub fn send(&self, command: ButtplugClientDeviceGroupCommand) -> ButtplugClientResultFuture {
+    let futures: Vec<ButtplugClientResultFuture> = self
+      .devices()
+      .iter()
+      .flat_map(|device| device_commands(device, command))
+      .collect();
+    Box::pin(async move {
+      future::join_all(futures)
+        .await
+        .into_iter()
+        .collect::<Result<Vec<()>, _>>()
+        .map(|_| ())
+    })
+  }
+
+  /// Runs vibration and rotation features of every device at `speed`. See
+  /// [ButtplugClientDeviceGroupCommand::Intensity].
+  pub fn intensity(&self, speed: f64) -> ButtplugClientResultFuture {
+    self.send(ButtplugClientDeviceGroupCommand::Intensity(speed))
+  }
+
+  /// Stops every device in the group.
+  pub fn stop(&self) -> ButtplugClientResultFuture {
+    self.send(ButtplugClientDeviceGroupCommand::Stop)
+  }
+}
+
+/// Builds the commands needed to apply `command` to `device`.
+fn device_commands(
+  device: &ButtplugClientDevice,
+  command: ButtplugClientDeviceGroupCommand,
+) -> Vec<ButtplugClientResultFuture> {
+  let (speed, linear) = match command {
+    ButtplugClientDeviceGroupCommand::Intensity(speed) => (speed, None),
+    ButtplugClientDeviceGroupCommand::Move(duration, position) => {
+      (position, Some((duration, position)))
+    }
+    ButtplugClientDeviceGroupCommand::Stop => return vec![device.stop()],
+  };
+  let mut commands = vec![];
+  if device.has_capability(ButtplugClientDeviceCapability::Vibrate) {
+    commands.push(device.vibrate(VibrateCommand::Speed(speed)));
+  }
+  if device.has_capability(ButtplugClientDeviceCapability::Rotate) {
+    commands.push(device.rotate(RotateCommand::Rotate(speed, true)));
+  }
+  if let Some((duration, position)) = linear {
+    if device.has_capability(ButtplugClientDeviceCapability::Linear) {
+      commands.push(device.linear(LinearCommand::Linear(duration, position)));
+    }
+  }
+  commands
+}
@@ -0,0 +1,501 @@
+// Buttplug Rust Source Code File - See https://buttplug.io for more info.
+//
+// Copyright 2016-2020 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+
+//! Funscript loading and playback, synchronized to a media clock.
+//!
+//! [Funscripts](https://github.com/funjack/funscripting) are JSON files
+//! holding a list of timed positions, used to sync linear devices to videos.
+//! Scripts are played with
+//! [ButtplugClientDevice::play_funscript][super::device::ButtplugClientDevice::play_funscript],
+//! which follows a [FunscriptClock] supplied by the application, usually
+//! wrapping whatever media player the script is synced to. On devices without
+//! linear movement, strokes can be turned into vibration speed instead.
+
+use super::{
+  device::{ButtplugClientDevice, LinearCommand, VibrateCommand},
+  pattern::{device_steps, ButtplugClientPatternHandle, DeviceStep},
+  ButtplugClientError,
+};
+use crate::core::{
+  errors::{ButtplugDeviceError, ButtplugError},
+  messages::ButtplugDeviceMessageType,
+};
+use futures_timer::Delay;
+use serde::{Deserialize, Serialize};
+use std::{
+  sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+    Mutex,
+  },
+  time::{Duration, Instant},
+};
+
+/// Media time jumps larger than this, compared to what the clock's rate
+/// predicts, are treated as seeks.
+const SEEK_TOLERANCE: Duration = Duration::from_millis(100);
+
+/// A single point in a [Funscript].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FunscriptAction {
+  /// Media time of the action, in milliseconds.
+  pub at: u32,
+  /// Position to be at, from 0 to the script's range.
+  pub pos: u32,
+}
+
+/// A parsed funscript.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Funscript {
+  #[serde(default)]
+  pub version: String,
+  /// If true, positions are flipped (0 is 100 and vice versa).
+  #[serde(default)]
+  pub inverted: bool,
+  /// Position that counts as fully extended. Defaults to 100.
+  #[serde(default = "default_range")]
+  pub range: u32,
+  pub actions: Vec<FunscriptAction>,
+}
+
+fn default_range() -> u32 {
+  100
+}
+
+impl Funscript {
+  /// Parses a funscript from JSON. Actions are sorted by time.
+  pub fn from_json(json: &str) -> Result<Self, serde_json::Error> {
+    let mut script: Funscript = serde_json::from_str(json)?;
+    script.actions.sort_by_key(|action| action.at);
+    Ok(script)
+  }
+
+  /// Media time of the last action.
+  pub fn duration(&self) -> Duration {
+    self
+      .actions
+      .last()
+      .map_or(Duration::from_secs(0), |action| {
+        Duration::from_millis(action.at as u64)
+      })
+  }
+
+  /// Position of an action as the device sees it (0.0-1.0), taking range and
+  /// inversion into account.
+  fn device_position(&self, action: &FunscriptAction) -> f64 {
+    let position = action.pos.min(self.range) as f64 / self.range as f64;
+    if self.inverted {
+      1.0 - position
+    } else {
+      position
+    }
+  }
+
+  /// Index of the first action after media time `at_ms`.
+  fn next_action(&self, at_ms: f64) -> Option<usize> {
+    self
+      .actions
+      .iter()
+      .position(|action| action.at as f64 > at_ms)
+  }
+
+  /// Returns the device position (0.0-1.0) at media time `at`, interpolated
+  /// between actions.
+  pub fn position_at(&self, at: Duration) -> Option<f64> {
+    let at_ms = at.as_secs_f64() * 1000.0;
+    let first = self.actions.first()?;
+    match self.next_action(at_ms) {
+      Some(0) => Some(self.device_position(first)),
+      Some(idx) => {
+        let start = &self.actions[idx - 1];
+        let end = &self.actions[idx];
+        let progress = (at_ms - start.at as f64) / (end.at - start.at) as f64;
+        let start_pos = self.device_position(start);
+        let end_pos = self.device_position(end);
+        Some(start_pos + (end_pos - start_pos) * progress)
+      }
+      None => self.actions.last().map(|last| self.device_position(last)),
+    }
+  }
+
+  /// Returns the speed of the stroke happening at media time `at`, in full
+  /// strokes per second. Returns 0 before the first action or after the last.
+  pub fn stroke_speed_at(&self, at: Duration) -> f64 {
+    let at_ms = at.as_secs_f64() * 1000.0;
+    match self.next_action(at_ms) {
+      Some(idx) if idx > 0 => {
+        let start = &self.actions[idx - 1];
+        let end = &self.actions[idx];
+        let distance = (self.device_position(end) - self.device_position(start)).abs();
+        distance / ((end.at - start.at) as f64 / 1000.0)
+      }
+      _ => 0.0,
+    }
+  }
+}
+
+/// Media clock that funscript playback follows.
+///
+/// Implement this over the media player the script is synced to. It's polled
+/// on every playback update, so it should be cheap to call.
+pub trait FunscriptClock: Send + Sync {
+  /// Current position in the media.
+  fn position(&self) -> Duration;
+  /// Playback rate, where 1.0 is normal speed.
+  fn rate(&self) -> f64;
+  /// True if the media is currently playing.
+  fn is_playing(&self) -> bool;
+}
+
+struct FunscriptMediaClockState {
+  /// Media position when the clock was last changed.
+  position: Duration,
+  /// When the clock was last changed.
+  updated: Instant,
+  rate: f64,
+  playing: bool,
+}
+
+impl FunscriptMediaClockState {
+  fn position(&self) -> Duration {
+    if self.playing {
+      self.position + self.updated.elapsed().mul_f64(self.rate.max(0.0))
+    } else {
+      self.position
+    }
+  }
+}
+
+/// [FunscriptClock] driven by calls from the application, for players that
+/// only report play, pause, seek and rate change events.
+///
+/// Starts paused at 0. Clones share the same clock.
+#[derive(Clone)]
+pub struct FunscriptMediaClock {
+  state: Arc<Mutex<FunscriptMediaClockState>>,
+}
+
+impl Default for FunscriptMediaClock {
+  fn default() -> Self {
+    Self {
+      state: Arc::new(Mutex::new(FunscriptMediaClockState {
+        position: Duration::from_secs(0),
+        updated: Instant::now(),
+        rate: 1.0,
+        playing: false,
+      })),
+    }
+  }
+}
+
+impl FunscriptMediaClock {
+  fn update(&self, f: impl FnOnce(&mut FunscriptMediaClockState)) {
+    let mut state = self.state.lock().unwrap();
+    state.position = state.position();
+    state.updated = Instant::now();
+    f(&mut state);
+  }
+
+  pub fn play(&self) {
+    self.update(|state| state.playing = true);
+  }
+
+  pub fn pause(&self) {
+    self.update(|state| state.playing = false);
+  }
+
+  pub fn seek(&self, position: Duration) {
+    self.update(|state| state.position = position);
+  }
+
+  pub fn set_rate(&self, rate: f64) {
+    self.update(|state| state.rate = rate);
+  }
+}
+
+impl FunscriptClock for FunscriptMediaClock {
+  fn position(&self) -> Duration {
+    self.state.lock().unwrap().position()
+  }
+
+  fn rate(&self) -> f64 {
+    self.state.lock().unwrap().rate
+  }
+
+  fn is_playing(&self) -> bool {
+    self.state.lock().unwrap().playing
+  }
+}
+
+/// How a [Funscript] is sent to a device.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FunscriptOutput {
+  /// Sends each action as a [LinearCmd][crate::core::messages::LinearCmd].
+  Linear,
+  /// Vibrates at a speed based on how fast the script is stroking.
+  /// `max_stroke_speed` is the stroke speed, in full strokes per second, that
+  /// maps to full vibration speed.
+  Vibrate { max_stroke_speed: f64 },
+}
+
+/// Options for
+/// [ButtplugClientDevice::play_funscript][super::device::ButtplugClientDevice::play_funscript].
+#[derive(Debug, Clone)]
+pub struct FunscriptPlayerOptions {
+  /// How far ahead of the media clock commands are sent, to make up for
+  /// device latency.
+  pub lookahead: Duration,
+  /// How often the clock is polled.
+  pub update_interval: Duration,
+  /// How the script is sent to the device. If None, linear devices get
+  /// [FunscriptOutput::Linear], and vibrating devices get
+  /// [FunscriptOutput::Vibrate] with a `max_stroke_speed` of 4.0.
+  pub output: Option<FunscriptOutput>,
+}
+
+impl Default for FunscriptPlayerOptions {
+  fn default() -> Self {
+    Self {
+      lookahead: Duration::from_millis(0),
+      update_interval: Duration::from_millis(10),
+      output: None,
+    }
+  }
+}
+
+/// Starts playing `script` on `device`, following `clock`. Playback runs
+/// until cancelled through the returned handle.
+pub(super) fn play_funscript(
+  device: &ButtplugClientDevice,
+  script: Funscript,
+  clock: Arc<dyn FunscriptClock>,
+  options: FunscriptPlayerOptions,
+) -> Result<ButtplugClientPatternHandle, ButtplugClientError> {
+  let supports = |msg_type| device.allowed_messages.contains_key(&msg_type);
+  let output = match options.output {
+    Some(FunscriptOutput::Linear) | None if supports(ButtplugDeviceMessageType::LinearCmd) => {
+      FunscriptOutput::Linear
+    }
+    Some(output @ FunscriptOutput::Vibrate { .. })
+      if supports(ButtplugDeviceMessageType::VibrateCmd) =>
+    {
+      output
+    }
+    None if supports(ButtplugDeviceMessageType::VibrateCmd) => FunscriptOutput::Vibrate {
+      max_stroke_speed: 4.0,
+    },
+    Some(FunscriptOutput::Vibrate { .. }) => {
+      return Err(
+        ButtplugError::from(ButtplugDeviceError::MessageNotSupported(
+          ButtplugDeviceMessageType::VibrateCmd,
+        ))
+        .into(),
+      )
+    }
+    _ => {
+      return Err(
+        ButtplugError::from(ButtplugDeviceError::MessageNotSupported(
+          ButtplugDeviceMessageType::LinearCmd,
+        ))
+        .into(),
+      )
+    }
+  };
+  let vibrate_attributes = device
+    .allowed_messages
+    .get(&ButtplugDeviceMessageType::VibrateCmd)
+    .cloned()
+    .unwrap_or_default();
+  let device = device.clone();
+  Ok(ButtplugClientPatternHandle::spawn(
+    &device.name.clone(),
+    |cancelled| async move {
+      let mut player = FunscriptPlayer {
+        device,
+        script,
+        output,
+        vibrator_count: vibrate_attributes.feature_count.unwrap_or(0) as usize,
+        vibrate_step_counts: vibrate_attributes.step_count,
+        options,
+        sent_action: None,
+        last_vibrate_steps: None,
+        last_tick: None,
+      };
+      player.run(&*clock, cancelled).await
+    },
+  ))
+}
+
+struct FunscriptPlayer {
+  device: ButtplugClientDevice,
+  script: Funscript,
+  output: FunscriptOutput,
+  vibrator_count: usize,
+  vibrate_step_counts: Option<Vec<u32>>,
+  options: FunscriptPlayerOptions,
+  /// Index of the action the last LinearCmd moved towards.
+  sent_action: Option<usize>,
+  /// Last vibration speeds sent, as the device sees them.
+  last_vibrate_steps: Option<Vec<DeviceStep>>,
+  /// Media position, rate and time of the previous update, for spotting
+  /// seeks and rate changes.
+  last_tick: Option<(Duration, f64, Instant)>,
+}
+
+impl FunscriptPlayer {
+  async fn run(
+    &mut self,
+    clock: &dyn FunscriptClock,
+    cancelled: Arc<AtomicBool>,
+  ) -> Result<(), ButtplugClientError> {
+    loop {
+      if cancelled.load(Ordering::SeqCst) {
+        debug!("Funscript on device {} cancelled.", self.device.name);
+        return self.device.stop().await;
+      }
+      let position = clock.position();
+      let rate = clock.rate();
+      if clock.is_playing() && rate > 0.0 {
+        self.check_for_seek(position, rate);
+        self.update(position, rate).await?;
+        self.last_tick = Some((position, rate, Instant::now()));
+      } else if self.last_tick.take().is_some() {
+        self.hold(position).await?;
+      }
+      Delay::new(self.options.update_interval).await;
+    }
+  }
+
+  /// Forgets what was last sent if the clock jumped or changed rate, so the
+  /// next update resends with the right timing.
+  fn check_for_seek(&mut self, position: Duration, rate: f64) {
+    if let Some((last_position, last_rate, last_time)) = self.last_tick {
+      let expected = last_position + last_time.elapsed().mul_f64(last_rate);
+      let drift = position.abs_diff(expected);
+      if drift > SEEK_TOLERANCE || (rate - last_rate).abs() > f64::EPSILON {
+        debug!("Funscript clock seeked or changed rate, resyncing.");
+        self.sent_action = None;
+      }
+    }
+  }
+
+  async fn update(&mut self, position: Duration, rate: f64) -> Result<(), ButtplugClientError> {
+    // Commands take effect `lookahead` from now, so aim for where the media
+    // will be by then.
+    let target = position + self.options.lookahead.mul_f64(rate);
+    match self.output {
+      FunscriptOutput::Linear => {
+        let target_ms = target.as_secs_f64() * 1000.0;
+        let next = match self.script.next_action(target_ms) {
+          Some(next) => next,
+          None => return Ok(()),
+        };
+        if self.sent_action == Some(next) {
+          return Ok(());
+        }
+        let action = self.script.actions[next];
+        let duration = ((action.at as f64 - target_ms) / rate).max(1.0) as u32;
+        let device_position = self.script.device_position(&action);
+        self
+          .device
+          .linear(LinearCommand::Linear(duration, device_position))
+          .await?;
+        self.sent_action = Some(next);
+        Ok(())
+      }
+      FunscriptOutput::Vibrate { max_stroke_speed } => {
+        let speed = (self.script.stroke_speed_at(target) * rate / max_stroke_speed).min(1.0);
+        self.vibrate(speed).await
+      }
+    }
+  }
+
+  /// Stops the device where it is when the media pauses.
+  async fn hold(&mut self, position: Duration) -> Result<(), ButtplugClientError> {
+    self.sent_action = None;
+    match self.output {
+      FunscriptOutput::Linear => {
+        if let Some(device_position) = self.script.position_at(position) {
+          let duration = self.options.update_interval.as_millis().max(1) as u32;
+          self
+            .device
+            .linear(LinearCommand::Linear(duration, device_position))
+            .await?;
+        }
+        Ok(())
+      }
+      FunscriptOutput::Vibrate { .. } => self.vibrate(0.0).await,
+    }
+  }
+
+  async fn vibrate(&mut self, speed: f64) -> Result<(), ButtplugClientError> {
+    let speeds = vec![speed; self.vibrator_count];
+    let steps = device_steps(&speeds, &self.vibrate_step_counts);
+    if self.last_vibrate_steps.as_ref() == Some(&steps) {
+      return Ok(());
+    }
+    self.device.vibrate(VibrateCommand::Speed(speed)).await?;
+    self.last_vibrate_steps = Some(steps);
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::Funscript;
+  use std::time::Duration;
+
+  const SCRIPT: &str = r#"{
+    "version": "1.0",
+    "inverted": false,
+    "range": 90,
+    "actions": [
+      {"at": 500, "pos": 90},
+      {"at": 0, "pos": 0},
+      {"at": 1000, "pos": 45}
+    ]
+  }"#;
+
+  #[test]
+  fn test_funscript_parse() {
+    let script = Funscript::from_json(SCRIPT).unwrap();
+    assert_eq!(script.range, 90);
+    assert_eq!(
+      script
+        .actions
+        .iter()
+        .map(|action| action.at)
+        .collect::<Vec<u32>>(),
+      vec![0, 500, 1000]
+    );
+    assert_eq!(script.duration(), Duration::from_millis(1000));
+    assert!(Funscript::from_json("{\"actions\": [{\"at\": 0}]}").is_err());
+    // Everything but the actions is optional.
+    let minimal = Funscript::from_json("{\"actions\": []}").unwrap();
+    assert_eq!(minimal.range, 100);
+    assert!(!minimal.inverted);
+  }
+
+  #[test]
+  fn test_funscript_position_at() {
+    let mut script = Funscript::from_json(SCRIPT).unwrap();
+    assert_eq!(script.position_at(Duration::from_millis(0)), Some(0.0));
+    assert_eq!(script.position_at(Duration::from_millis(250)), Some(0.5));
+    assert_eq!(script.position_at(Duration::from_millis(750)), Some(0.75));
+    assert_eq!(script.position_at(Duration::from_millis(2000)), Some(0.5));
+    script.inverted = true;
+    assert_eq!(script.position_at(Duration::from_millis(500)), Some(0.0));
+  }
+
+  #[test]
+  fn test_funscript_stroke_speed() {
+    let script = Funscript::from_json(SCRIPT).unwrap();
+    assert_eq!(script.stroke_speed_at(Duration::from_millis(250)), 2.0);
+    assert_eq!(script.stroke_speed_at(Duration::from_millis(750)), 1.0);
+    assert_eq!(script.stroke_speed_at(Duration::from_millis(2000)), 0.0);
+  }
+}
@@ -9,30 +9,42 @@
 
 use super::{
   client_message_sorter::ClientMessageSorter,
-  device::{ButtplugClientDevice, ButtplugClientDeviceEvent},
+  device::{ButtplugClientDevice, ButtplugClientDeviceEvent, ButtplugClientDeviceState},
+  is_spec_version_rejection,
   ButtplugClientError,
   ButtplugClientEvent,
+  ButtplugClientMessageFuture,
   ButtplugClientMessageFuturePair,
+  ButtplugClientReconnectPolicy,
+  ButtplugInternalClientMessageResult,
+  HANDSHAKE_MESSAGE_SPEC_VERSIONS,
 };
 use crate::{
-  connector::{ButtplugConnector, ButtplugConnectorStateShared},
+  connector::{ButtplugConnector, ButtplugConnectorError, ButtplugConnectorStateShared},
   core::{
-    errors::ButtplugServerError,
+    errors::{ButtplugHandshakeError, ButtplugServerError},
     messages::{
       ButtplugCurrentSpecClientMessage,
       ButtplugCurrentSpecServerMessage,
+      ButtplugMessage,
+      ButtplugMessageSpecVersion,
       DeviceList,
       DeviceMessageInfo,
+      RequestDeviceList,
+      RequestServerInfo,
     },
   },
+  util::{async_manager, future::ButtplugFutureStateShared},
 };
 use async_channel::{bounded, Receiver, Sender};
 use broadcaster::BroadcastChannel;
 use dashmap::DashMap;
-use futures::{Future, FutureExt, StreamExt};
+use futures::{future, Future, FutureExt, StreamExt};
+use futures_timer::Delay;
 use std::{
   hash::{Hash, Hasher},
-  sync::Arc,
+  sync::{Arc, Mutex},
+  time::Duration,
 };
 use tracing_futures::Instrument;
 
@@ -42,12 +54,18 @@ pub(super) enum ButtplugClientRequest {
   Disconnect(ButtplugConnectorStateShared),
   /// Given a DeviceList message, update the inner loop values and create
   /// events for additions.
-  HandleDeviceList(DeviceList),
+  ///
+  /// Bundled future should have reply set and waker called once the device
+  /// map has been updated.
+  HandleDeviceList(DeviceList, ButtplugFutureStateShared<()>),
   /// Client request to send a message via the connector.
   ///
   /// Bundled future should have reply set and waker called when this is
   /// finished.
   Message(ButtplugClientMessageFuturePair),
+  /// The message with the given id has gone without a reply for the given
+  /// duration. Sent by timers started when messages are sent.
+  MessageTimeout(u32, Duration),
 }
 
 pub(super) struct ButtplugClientDeviceInternal {
@@ -56,6 +74,7 @@ pub(super) struct ButtplugClientDeviceInternal {
   // client devices when they are requested.
   pub device: Arc<DeviceMessageInfo>,
   pub channel: Arc<BroadcastChannel<ButtplugClientDeviceEvent>>,
+  pub state: Arc<Mutex<ButtplugClientDeviceState>>,
 }
 
 impl Eq for ButtplugClientDeviceInternal {
@@ -66,13 +85,42 @@ impl ButtplugClientDeviceInternal {
     device: DeviceMessageInfo,
     channel: BroadcastChannel<ButtplugClientDeviceEvent>,
   ) -> Self {
+    let state = Arc::new(Mutex::new(ButtplugClientDeviceState::new(
+      &device.device_messages,
+    )));
     Self {
       device: Arc::new(device),
       channel: Arc::new(channel),
+      state,
     }
   }
 }
 
+/// What the event loop needs to build a new connection if the current one
+/// drops.
+pub(super) struct ButtplugClientReconnector<ConnectorType> {
+  /// Creates a new, unconnected connector for each reconnection attempt.
+  pub connector_factory: Box<dyn Fn() -> ConnectorType + Send + Sync>,
+  pub policy: ButtplugClientReconnectPolicy,
+}
+
+/// Settings for the event loop that come from how the client was created,
+/// and stay the same across reconnections, along with state shared with the
+/// [ButtplugClient].
+pub(super) struct ButtplugClientEventLoopOptions<ConnectorType> {
+  /// Client name, used to redo the handshake on reconnection.
+  pub client_name: String,
+  /// If set, used to reconnect when the connector drops. Otherwise the loop
+  /// exits on disconnection.
+  pub reconnector: Option<ButtplugClientReconnector<ConnectorType>>,
+  /// Timeout for messages that don't have their own. Shared with the
+  /// [ButtplugClient] so it can be changed.
+  pub default_timeout: Arc<Mutex<Option<Duration>>>,
+  /// Message spec version used with the server, updated when the handshake is
+  /// redone on reconnection.
+  pub server_spec_version: Arc<Mutex<ButtplugMessageSpecVersion>>,
+}
+
 impl PartialEq for ButtplugClientDeviceInternal {
   fn eq(&self, other: &Self) -> bool {
     self.device.device_index == other.device.device_index
@@ -120,6 +168,17 @@ where
   /// Receiver for messages send from the [ButtplugServer] via the connector.
   connector_receiver: Receiver<Result<ButtplugCurrentSpecServerMessage, ButtplugServerError>>,
   sorter: ClientMessageSorter,
+  /// Client name, used to redo the handshake on reconnection.
+  client_name: String,
+  /// If set, used to reconnect when the connector drops. Otherwise the loop
+  /// exits on disconnection.
+  reconnector: Option<ButtplugClientReconnector<ConnectorType>>,
+  /// Timeout for messages that don't have their own. Shared with the
+  /// [ButtplugClient] so it can be changed.
+  default_timeout: Arc<Mutex<Option<Duration>>>,
+  /// Message spec version used with the server, shared with the
+  /// [ButtplugClient] so it sees the version from the latest handshake.
+  server_spec_version: Arc<Mutex<ButtplugMessageSpecVersion>>,
 }
 
 impl<ConnectorType> ButtplugClientEventLoop<ConnectorType>
@@ -139,8 +198,15 @@ where
     client_sender: Sender<ButtplugClientRequest>,
     client_receiver: Receiver<ButtplugClientRequest>,
     device_map: Arc<DashMap<u32, ButtplugClientDeviceInternal>>,
+    options: ButtplugClientEventLoopOptions<ConnectorType>,
   ) -> Self {
     trace!("Creating ButtplugClientEventLoop instance.");
+    let ButtplugClientEventLoopOptions {
+      client_name,
+      reconnector,
+      default_timeout,
+      server_spec_version,
+    } = options;
     Self {
       device_map,
       client_sender,
@@ -149,6 +215,10 @@ where
       connector_receiver,
       connector,
       sorter: ClientMessageSorter::default(),
+      client_name,
+      reconnector,
+      default_timeout,
+      server_spec_version,
     }
   }
 
@@ -170,18 +240,21 @@ where
           &*dev.device,
           self.client_sender.clone(),
           (*dev.channel).clone(),
+          dev.state.clone(),
         ))
       }
       // If it doesn't, insert it.
       None => {
         debug!("Device does not exist, creating new entry.");
         let channel = BroadcastChannel::new();
-        let device =
-          ButtplugClientDevice::from((info, self.client_sender.clone(), channel.clone()));
-        self.device_map.insert(
-          info.device_index,
-          ButtplugClientDeviceInternal::new(info.clone(), channel),
-        );
+        let device_internal = ButtplugClientDeviceInternal::new(info.clone(), channel.clone());
+        let device = ButtplugClientDevice::from((
+          info,
+          self.client_sender.clone(),
+          channel,
+          device_internal.state.clone(),
+        ));
+        self.device_map.insert(info.device_index, device_internal);
         device
       }
     }
@@ -242,6 +315,12 @@ where
               .send_client_event(&ButtplugClientEvent::ScanningFinished)
               .await;
           }
+          ButtplugCurrentSpecServerMessage::Error(e) => {
+            trace!("Error event received, forwarding to client.");
+            self
+              .send_client_event(&ButtplugClientEvent::Error(e.clone().into()))
+              .await;
+          }
           _ => error!("Cannot process message, dropping: {:?}", msg),
         }
       }
@@ -253,12 +332,258 @@ where
     }
   }
 
+  /// Returns the timeout for a message, which is either its own or the
+  /// client default.
+  fn message_timeout(&self, msg_fut: &ButtplugClientMessageFuturePair) -> Option<Duration> {
+    msg_fut.timeout.or(*self.default_timeout.lock().unwrap())
+  }
+
   /// Send a message from the [ButtplugClient] to the [ButtplugClientConnector].
-  async fn send_message(&mut self, mut msg_fut: ButtplugClientMessageFuturePair) {
+  ///
+  /// If the message has a timeout, starts a timer that will fail the message
+  /// future if the server hasn't replied by then. Returns the message id.
+  async fn send_message(&mut self, mut msg_fut: ButtplugClientMessageFuturePair) -> u32 {
     trace!("Sending message to connector: {:?}", msg_fut.msg);
+    let timeout = self.message_timeout(&msg_fut);
     self.sorter.register_future(&mut msg_fut);
-    // TODO What happens if the connector isn't connected?
-    self.connector.send(msg_fut.msg).await.unwrap();
+    let id = msg_fut.msg.get_id();
+    if let Err(err) = self.connector.send(msg_fut.msg).await {
+      error!("Cannot send message to connector: {:?}", err);
+      self.sorter.fail_future(id, err.into());
+      return id;
+    }
+    if let Some(timeout) = timeout {
+      let client_sender = self.client_sender.clone();
+      async_manager::spawn(async move {
+        Delay::new(timeout).await;
+        // If the loop has exited, there's nothing left to time out.
+        let _ = client_sender
+          .send(ButtplugClientRequest::MessageTimeout(id, timeout))
+          .await;
+      })
+      .unwrap();
+    }
+    id
+  }
+
+  /// Sends a message from inside the event loop, and handles connector
+  /// messages until its response arrives. Used to redo the handshake on
+  /// reconnection, since the client can't send messages until that's done.
+  async fn send_message_and_wait(
+    &mut self,
+    msg: ButtplugCurrentSpecClientMessage,
+  ) -> ButtplugInternalClientMessageResult {
+    let fut = ButtplugClientMessageFuture::default();
+    let msg_fut = ButtplugClientMessageFuturePair::new(msg, fut.get_state_clone());
+    // We aren't reading client requests here, so we can't rely on the
+    // message timer, and have to watch the timeout ourselves.
+    let timeout = self.message_timeout(&msg_fut);
+    let id = self.send_message(msg_fut).await;
+    let mut fut = fut.fuse();
+    let mut timeout_fut = match timeout {
+      Some(timeout) => Delay::new(timeout).boxed(),
+      None => future::pending().boxed(),
+    }
+    .fuse();
+    let mut connector_receiver = self.connector_receiver.clone();
+    loop {
+      select! {
+        reply = fut => return reply,
+        _ = timeout_fut => {
+          // This resolves fut with the timeout error.
+          self
+            .sorter
+            .fail_future(id, ButtplugClientError::MessageTimeout(timeout.unwrap()));
+        },
+        event = connector_receiver.next().fuse() => match event {
+          None => return Err(ButtplugConnectorError::ConnectorChannelClosed.into()),
+          Some(msg) => self.parse_connector_message(msg).await,
+        },
+      };
+    }
+  }
+
+  /// Adds devices in a DeviceList that we don't already know about, emitting
+  /// DeviceAdded for each.
+  async fn handle_device_list(&mut self, device_list: &DeviceList) {
+    for d in &device_list.devices {
+      if self.device_map.contains_key(&d.device_index) {
+        continue;
+      }
+      let device = self.create_client_device(d);
+      self
+        .send_client_event(&ButtplugClientEvent::DeviceAdded(device))
+        .await;
+    }
+  }
+
+  /// Updates the device map from the DeviceList received after reconnecting.
+  ///
+  /// Devices that still exist with the same index and name keep their entries,
+  /// so [ButtplugClientDevice] handles from before the disconnect keep
+  /// working. Devices that are gone are removed, and new devices are added.
+  async fn rebind_devices(&mut self, device_list: &DeviceList) {
+    let stale_devices: Vec<u32> = self
+      .device_map
+      .iter()
+      .filter(|dev| {
+        !device_list
+          .devices
+          .iter()
+          .any(|d| d.device_index == *dev.key() && d.device_name == dev.value().device.device_name)
+      })
+      .map(|dev| *dev.key())
+      .collect();
+    for index in stale_devices {
+      if let Some(dev) = self.device_map.remove_take(&index) {
+        debug!(
+          "Device {} did not come back after reconnect, removing.",
+          index
+        );
+        let info = (*dev.value().device).clone();
+        let channel = dev.value().channel.clone();
+        let _ = channel
+          .send(&ButtplugClientDeviceEvent::DeviceDisconnect)
+          .await;
+        self
+          .send_client_event(&ButtplugClientEvent::DeviceRemoved(info))
+          .await;
+      }
+    }
+    self.handle_device_list(device_list).await;
+  }
+
+  /// Runs the handshake and device list request on a new connection.
+  async fn rerun_handshake(&mut self) -> Result<(), ButtplugClientError> {
+    let mut spec_versions = HANDSHAKE_MESSAGE_SPEC_VERSIONS.iter().peekable();
+    let (msg, spec_version) = loop {
+      let spec_version = *spec_versions.next().unwrap();
+      match self
+        .send_message_and_wait(RequestServerInfo::new(&self.client_name, spec_version).into())
+        .await
+      {
+        result if spec_versions.peek().is_some() && is_spec_version_rejection(&result) => {}
+        result => break (result?, spec_version),
+      }
+    };
+    if let ButtplugCurrentSpecServerMessage::ServerInfo(server_info) = &msg {
+      // We may have reconnected to a different server.
+      *self.server_spec_version.lock().unwrap() = spec_version.min(server_info.message_version);
+    } else {
+      return Err(ButtplugClientError::ButtplugError(
+        ButtplugHandshakeError::UnexpectedHandshakeMessageReceived(format!("{:?}", msg)).into(),
+      ));
+    }
+    let msg = self
+      .send_message_and_wait(RequestDeviceList::default().into())
+      .await?;
+    if let ButtplugCurrentSpecServerMessage::DeviceList(device_list) = msg {
+      self.rebind_devices(&device_list).await;
+    }
+    Ok(())
+  }
+
+  /// Tells the client and all devices that we've lost the server for good.
+  async fn handle_server_disconnect(&mut self) {
+    let channels: Vec<Arc<BroadcastChannel<ButtplugClientDeviceEvent>>> = self
+      .device_map
+      .iter()
+      .map(|dev| dev.value().channel.clone())
+      .collect();
+    for channel in channels {
+      let _ = channel
+        .send(&ButtplugClientDeviceEvent::ClientDisconnect)
+        .await;
+    }
+    self
+      .send_client_event(&ButtplugClientEvent::ServerDisconnect)
+      .await;
+  }
+
+  /// Handles the connector dropping, by reconnecting if the client was created
+  /// with a reconnect policy.
+  ///
+  /// Returns true if we're connected again, false if the loop should exit.
+  async fn reconnect(&mut self) -> bool {
+    // Anything waiting on a response from the old connection won't get one.
+    self.sorter.fail_all_futures();
+    let reconnector = match self.reconnector.take() {
+      Some(reconnector) => reconnector,
+      None => {
+        self.handle_server_disconnect().await;
+        return false;
+      }
+    };
+    self
+      .send_client_event(&ButtplugClientEvent::Reconnecting)
+      .await;
+    let mut client_receiver = self.client_receiver.clone();
+    let mut attempt = 0;
+    loop {
+      attempt += 1;
+      if reconnector.policy.max_attempts != 0 && attempt > reconnector.policy.max_attempts {
+        warn!("Could not reconnect to server, giving up.");
+        self.handle_server_disconnect().await;
+        return false;
+      }
+      // Wait out the backoff. Nothing can be sent while we're disconnected, so
+      // fail anything the client or devices send instead of letting it queue.
+      let mut delay = Delay::new(reconnector.policy.delay_for_attempt(attempt)).fuse();
+      loop {
+        select! {
+          _ = delay => break,
+          request = client_receiver.next().fuse() => match request {
+            None => return false,
+            Some(ButtplugClientRequest::Disconnect(state)) => {
+              info!("Client requested disconnect while reconnecting, stopping.");
+              state.set_reply(Ok(()));
+              return false;
+            }
+            Some(ButtplugClientRequest::Message(msg_fut)) => {
+              msg_fut
+                .waker
+                .set_reply(Err(ButtplugConnectorError::ConnectorNotConnected.into()));
+            }
+            // The device list is from before the connection dropped, so it's
+            // out of date. We'll get a new one once we reconnect.
+            Some(ButtplugClientRequest::HandleDeviceList(_, state)) => state.set_reply(()),
+            // Everything waiting on a reply was failed when the connection
+            // dropped, so there's nothing to time out.
+            Some(ButtplugClientRequest::MessageTimeout(..)) => {}
+          },
+        };
+      }
+      info!("Reconnection attempt {}.", attempt);
+      let mut connector = (reconnector.connector_factory)();
+      match connector.connect().await {
+        Ok(connector_receiver) => {
+          self.connector = connector;
+          self.connector_receiver = connector_receiver;
+        }
+        Err(err) => {
+          warn!("Reconnection attempt {} failed: {:?}", attempt, err);
+          continue;
+        }
+      }
+      match self.rerun_handshake().await {
+        Ok(()) => {
+          info!("Reconnected to server.");
+          self.reconnector = Some(reconnector);
+          self
+            .send_client_event(&ButtplugClientEvent::Reconnected)
+            .await;
+          return true;
+        }
+        Err(err) => {
+          warn!(
+            "Handshake failed on reconnection attempt {}: {:?}",
+            attempt, err
+          );
+          self.sorter.fail_all_futures();
+          let _ = self.connector.disconnect().await;
+        }
+      }
+    }
   }
 
   /// Parses message types from the client, returning false when disconnect
@@ -281,17 +606,17 @@ where
         state.set_reply(self.connector.disconnect().await);
         false
       }
-      ButtplugClientRequest::HandleDeviceList(device_list) => {
+      ButtplugClientRequest::HandleDeviceList(device_list, state) => {
         trace!("Device list received, updating map.");
-        for d in &device_list.devices {
-          if self.device_map.contains_key(&d.device_index) {
-            continue;
-          }
-          let device = self.create_client_device(&d);
-          self
-            .send_client_event(&ButtplugClientEvent::DeviceAdded(device))
-            .await;
-        }
+        self.handle_device_list(&device_list).await;
+        state.set_reply(());
+        true
+      }
+      ButtplugClientRequest::MessageTimeout(id, timeout) => {
+        // Only does something if the server hasn't replied yet.
+        self
+          .sorter
+          .fail_future(id, ButtplugClientError::MessageTimeout(timeout));
         true
       }
     }
@@ -309,8 +634,12 @@ where
       select! {
         event = connector_receiver.next().fuse() => match event {
           None => {
-            info!("Connector disconnected, exiting loop.");
-            return;
+            info!("Connector disconnected.");
+            if !self.reconnect().await {
+              info!("Not reconnecting, exiting loop.");
+              return;
+            }
+            connector_receiver = self.connector_receiver.clone();
           }
           Some(msg) => {
             self.parse_connector_message(msg).await;
@@ -346,20 +675,25 @@ where
 ///   routing them to their proper receivers until either server/client
 ///   disconnects.
 ///
-/// - On disconnect, it will tear down, and cannot be used again. All clients
-///   and devices associated with the loop will be invalidated, and a new
+/// - On disconnect, it will try to reconnect if given a reconnector. If it
+///   can't, it will tear down, and cannot be used again. All clients and
+///   devices associated with the loop will be invalidated, and a new
 ///   [super::ButtplugClient] must be created.
-pub(super) fn client_event_loop(
-  connector: impl ButtplugConnector<ButtplugCurrentSpecClientMessage, ButtplugCurrentSpecServerMessage>
-    + 'static,
+pub(super) fn client_event_loop<ConnectorType>(
+  connector: ConnectorType,
   connector_receiver: Receiver<Result<ButtplugCurrentSpecServerMessage, ButtplugServerError>>,
+  options: ButtplugClientEventLoopOptions<ConnectorType>,
 ) -> (
   impl Future<Output = Result<(), ButtplugClientError>>,
   Arc<DashMap<u32, ButtplugClientDeviceInternal>>,
   Sender<ButtplugClientRequest>,
   // This needs clone internally, as the client will make multiple copies.
   impl StreamExt<Item = ButtplugClientEvent> + Clone,
-) {
+)
+where
+  ConnectorType:
+    ButtplugConnector<ButtplugCurrentSpecClientMessage, ButtplugCurrentSpecServerMessage> + 'static,
+{
   trace!("Creating client event loop future.");
   let event_channel = BroadcastChannel::new();
   let device_map = Arc::new(DashMap::new());
@@ -375,6 +709,7 @@ pub(super) fn client_event_loop(
     client_sender,
     client_receiver,
     device_map,
+    options,
   );
   (
     Box::pin(async move {
@@ -6,12 +6,23 @@
 // for full license information.
 
 //! Communications API for accessing Buttplug Servers
+pub mod blocking;
 mod client_message_sorter;
 pub mod device;
+pub mod device_group;
+pub mod funscript;
 pub mod internal;
+pub mod pattern;
 
-use device::ButtplugClientDevice;
-use internal::{client_event_loop, ButtplugClientDeviceInternal, ButtplugClientRequest};
+use device::{ButtplugClientDevice, ButtplugClientDeviceCapability};
+use device_group::ButtplugClientDeviceGroup;
+use internal::{
+  client_event_loop,
+  ButtplugClientDeviceInternal,
+  ButtplugClientEventLoopOptions,
+  ButtplugClientReconnector,
+  ButtplugClientRequest,
+};
 
 use crate::{
   connector::{ButtplugConnector, ButtplugConnectorError, ButtplugConnectorFuture},
@@ -22,11 +33,13 @@ use crate::{
       ButtplugCurrentSpecServerMessage,
       ButtplugMessageSpecVersion,
       DeviceMessageInfo,
+      ErrorCode,
       RequestDeviceList,
       RequestServerInfo,
       StartScanning,
       StopAllDevices,
       StopScanning,
+      BUTTPLUG_CURRENT_MESSAGE_SPEC_VERSION,
     },
   },
   util::{
@@ -34,16 +47,21 @@ use crate::{
     future::{ButtplugFuture, ButtplugFutureStateShared},
   },
 };
-use async_channel::Sender;
+use async_channel::{unbounded, Receiver, Sender};
 use dashmap::DashMap;
 use futures::{
   future::{self, BoxFuture},
   FutureExt,
   StreamExt,
 };
-use std::sync::{
-  atomic::{AtomicBool, Ordering},
-  Arc,
+use futures_timer::Delay;
+use std::{
+  sync::{
+    atomic::{AtomicBool, AtomicU32, Ordering},
+    Arc,
+    Mutex,
+  },
+  time::Duration,
 };
 use thiserror::Error;
 use tracing::{span::Span, Level};
@@ -95,6 +113,9 @@ pub(crate) type ButtplugClientMessageFuture = ButtplugFuture<ButtplugInternalCli
 pub struct ButtplugClientMessageFuturePair {
   pub msg: ButtplugCurrentSpecClientMessage,
   pub waker: ButtplugClientMessageStateShared,
+  /// How long to wait for a reply before failing the future. If None, the
+  /// client's default timeout is used.
+  pub timeout: Option<Duration>,
 }
 
 impl ButtplugClientMessageFuturePair {
@@ -102,7 +123,19 @@ impl ButtplugClientMessageFuturePair {
     msg: ButtplugCurrentSpecClientMessage,
     waker: ButtplugClientMessageStateShared,
   ) -> Self {
-    Self { msg, waker }
+    Self::new_with_timeout(msg, waker, None)
+  }
+
+  pub fn new_with_timeout(
+    msg: ButtplugCurrentSpecClientMessage,
+    waker: ButtplugClientMessageStateShared,
+    timeout: Option<Duration>,
+  ) -> Self {
+    Self {
+      msg,
+      waker,
+      timeout,
+    }
   }
 }
 
@@ -122,6 +155,37 @@ pub enum ButtplugClientError {
   /// Protocol error
   #[error(transparent)]
   ButtplugError(#[from] ButtplugError),
+  /// Server did not reply to a message in time
+  #[error("No reply received from server within {0:?}")]
+  MessageTimeout(Duration),
+  /// Waited event did not happen in time
+  #[error("Waited event did not happen within {0:?}")]
+  WaitTimeout(Duration),
+  /// Scanning selections aren't part of the Buttplug protocol
+  #[error("Scanning selections can only be used with servers in the same process")]
+  ScanningSelectionUnsupported,
+}
+
+/// Spec versions offered to the server during the handshake, newest first.
+/// Servers older than the client turn down versions they don't know, so we
+/// step down until one is accepted.
+const HANDSHAKE_MESSAGE_SPEC_VERSIONS: [ButtplugMessageSpecVersion; 3] = [
+  ButtplugMessageSpecVersion::Version2,
+  ButtplugMessageSpecVersion::Version1,
+  ButtplugMessageSpecVersion::Version0,
+];
+
+/// True if the server turned down our RequestServerInfo in a way that means
+/// it may accept an older spec version.
+fn is_spec_version_rejection(result: &ButtplugInternalClientMessageResult) -> bool {
+  // Remote servers reply with untyped Error messages, so we can't tell a
+  // version mismatch from other handshake errors. Retrying is harmless either
+  // way.
+  match result {
+    Ok(ButtplugCurrentSpecServerMessage::Error(err)) => err.error_code == ErrorCode::ErrorHandshake,
+    Err(ButtplugClientError::ButtplugError(ButtplugError::ButtplugHandshakeError(_))) => true,
+    _ => false,
+  }
 }
 
 /// Enum representing different events that can be emitted by a client.
@@ -144,11 +208,67 @@ pub enum ButtplugClientEvent {
   /// of time.
   PingTimeout,
   /// Emitted when a client connector detects that the server has
-  /// disconnected.
+  /// disconnected. If the client has a reconnect policy, this is only emitted
+  /// once reconnection has failed.
   ServerDisconnect,
+  /// Emitted when the connection to the server drops and the client is trying
+  /// to reconnect, using the reconnect policy passed to
+  /// [ButtplugClient::connect_with_reconnect]. Commands fail until
+  /// [ButtplugClientEvent::Reconnected] is emitted.
+  Reconnecting,
+  /// Emitted when the client has reconnected to the server. Existing
+  /// [ButtplugClientDevice] handles keep working for devices that are still
+  /// connected. DeviceRemoved and DeviceAdded are emitted for devices that
+  /// changed while the client was disconnected.
+  Reconnected,
+  /// Emitted when the server sends an error that isn't a reply to a message.
+  /// Devices the server has no protocol implementation for are reported as
+  /// [ButtplugDeviceError::ProtocolNotImplemented][crate::core::errors::ButtplugDeviceError::ProtocolNotImplemented].
   Error(ButtplugError),
 }
 
+/// Controls how a [ButtplugClient] reconnects when its connection to the
+/// server drops.
+///
+/// The delay before each attempt starts at `initial_delay`, and is multiplied
+/// by `backoff_multiplier` after each failed attempt, up to `max_delay`.
+#[derive(Debug, Clone)]
+pub struct ButtplugClientReconnectPolicy {
+  /// Number of attempts before giving up and emitting
+  /// [ButtplugClientEvent::ServerDisconnect]. 0 means never give up.
+  pub max_attempts: u32,
+  /// Delay before the first reconnection attempt.
+  pub initial_delay: Duration,
+  /// Longest delay between attempts.
+  pub max_delay: Duration,
+  /// Amount the delay is multiplied by after each failed attempt.
+  pub backoff_multiplier: f64,
+}
+
+impl Default for ButtplugClientReconnectPolicy {
+  fn default() -> Self {
+    Self {
+      max_attempts: 5,
+      initial_delay: Duration::from_millis(500),
+      max_delay: Duration::from_secs(30),
+      backoff_multiplier: 2.0,
+    }
+  }
+}
+
+impl ButtplugClientReconnectPolicy {
+  /// Returns how long to wait before the given attempt, starting at 1.
+  pub fn delay_for_attempt(&self, attempt: u32) -> Duration {
+    let multiplier = self
+      .backoff_multiplier
+      .powi(attempt.saturating_sub(1) as i32);
+    let delay = (self.initial_delay.as_secs_f64() * multiplier)
+      .min(self.max_delay.as_secs_f64())
+      .max(0.0);
+    Duration::from_secs_f64(delay)
+  }
+}
+
 /// Struct used by applications to communicate with a Buttplug Server.
 ///
 /// Buttplug Clients provide an API layer on top of the Buttplug Protocol that
@@ -171,13 +291,43 @@ pub struct ButtplugClient {
   pub client_name: String,
   /// The server name that we're current connected to.
   pub server_name: String,
+  // Message spec version used with the server, shared with the event loop so
+  // it can be updated on reconnection.
+  server_spec_version: Arc<Mutex<ButtplugMessageSpecVersion>>,
   // Sender to relay messages to the internal client loop
   message_sender: Sender<ButtplugClientRequest>,
   // True if the connector is currently connected, and handshake was
   // successful.
   connected: Arc<AtomicBool>,
+  // True while the connection has dropped and the client is trying to
+  // reconnect.
+  reconnecting: Arc<AtomicBool>,
   _client_span: Span,
   device_map: Arc<DashMap<u32, ButtplugClientDeviceInternal>>,
+  // Incremented every time scanning is started, so timed scans know if
+  // they've been superseded.
+  scan_session: Arc<AtomicU32>,
+  // True from when scanning is started until the server says it's finished.
+  scanning: Arc<AtomicBool>,
+  // Channels that get a copy of every event, for waiting on events without
+  // holding the event stream.
+  event_subscribers: Arc<Mutex<Vec<Sender<ButtplugClientEvent>>>>,
+  // Timeout for messages sent by this handle, overriding the default.
+  timeout: Option<Duration>,
+  // Timeout for messages without their own, shared with the event loop.
+  default_timeout: Arc<Mutex<Option<Duration>>>,
+  // True if the connector serializes messages to a server in another process.
+  remote_connector: bool,
+}
+
+// Status kept up to date from client events, shared between all handles to a
+// client.
+struct ButtplugClientStatus {
+  connected: Arc<AtomicBool>,
+  reconnecting: Arc<AtomicBool>,
+  scanning: Arc<AtomicBool>,
+  event_subscribers: Arc<Mutex<Vec<Sender<ButtplugClientEvent>>>>,
+  server_spec_version: Arc<Mutex<ButtplugMessageSpecVersion>>,
 }
 
 unsafe impl Send for ButtplugClient {
@@ -189,8 +339,60 @@ unsafe impl Sync for ButtplugClient {
 
 impl ButtplugClient {
   pub fn connect<ConnectorType>(
+    name: &str,
+    connector: ConnectorType,
+  ) -> BoxFuture<
+    'static,
+    Result<(Self, impl StreamExt<Item = ButtplugClientEvent>), ButtplugClientError>,
+  >
+  where
+    ConnectorType: ButtplugConnector<ButtplugCurrentSpecClientMessage, ButtplugCurrentSpecServerMessage>
+      + 'static,
+  {
+    ButtplugClient::connect_with_reconnector(name, connector, None)
+  }
+
+  /// Connects to a server like [ButtplugClient::connect], but reconnects if
+  /// the connection drops, following `policy`.
+  ///
+  /// `connector_factory` is called to create a new connector for the first
+  /// connection and for each reconnection attempt, since connectors can't be
+  /// reused once disconnected. If the first connection fails, the error is
+  /// returned without retrying.
+  ///
+  /// While reconnecting, [ButtplugClientEvent::Reconnecting] is emitted and
+  /// commands return errors. Once the handshake succeeds again and the device
+  /// list has been updated, [ButtplugClientEvent::Reconnected] is emitted.
+  /// [ButtplugClientDevice] handles for devices that came back under the same
+  /// index and name keep working.
+  pub fn connect_with_reconnect<ConnectorType, F>(
+    name: &str,
+    connector_factory: F,
+    policy: ButtplugClientReconnectPolicy,
+  ) -> BoxFuture<
+    'static,
+    Result<(Self, impl StreamExt<Item = ButtplugClientEvent>), ButtplugClientError>,
+  >
+  where
+    ConnectorType: ButtplugConnector<ButtplugCurrentSpecClientMessage, ButtplugCurrentSpecServerMessage>
+      + 'static,
+    F: Fn() -> ConnectorType + Send + Sync + 'static,
+  {
+    let connector = connector_factory();
+    ButtplugClient::connect_with_reconnector(
+      name,
+      connector,
+      Some(ButtplugClientReconnector {
+        connector_factory: Box::new(connector_factory),
+        policy,
+      }),
+    )
+  }
+
+  fn connect_with_reconnector<ConnectorType>(
     name: &str,
     mut connector: ConnectorType,
+    reconnector: Option<ButtplugClientReconnector<ConnectorType>>,
   ) -> BoxFuture<
     'static,
     Result<(Self, impl StreamExt<Item = ButtplugClientEvent>), ButtplugClientError>,
@@ -205,29 +407,72 @@ impl ButtplugClient {
       let span = span!(Level::INFO, "Client");
       let _client_span = span.enter();
       info!("Connecting to server.");
+      let remote_connector = connector.is_remote();
       let connector_receiver = connector.connect().await.map_err(|e| {
         error!("Connection to server failed: {:?}", e);
         let err: ButtplugClientError = e.into();
         err
       })?;
       info!("Connection to server succeeded.");
+      let default_timeout = Arc::new(Mutex::new(None));
+      let server_spec_version = Arc::new(Mutex::new(BUTTPLUG_CURRENT_MESSAGE_SPEC_VERSION));
       let (client_event_loop_fut, device_map_reader, message_sender, event_channel) =
-        client_event_loop(connector, connector_receiver);
+        client_event_loop(
+          connector,
+          connector_receiver,
+          ButtplugClientEventLoopOptions {
+            client_name: client_name.clone(),
+            reconnector,
+            default_timeout: default_timeout.clone(),
+            server_spec_version: server_spec_version.clone(),
+          },
+        );
 
       let client_event_receiver = event_channel.clone();
       let mut disconnect_event_receiver = event_channel.clone();
       let connected_status = Arc::new(AtomicBool::new(true));
       let connected_status_clone = connected_status.clone();
+      let reconnecting_status = Arc::new(AtomicBool::new(false));
+      let reconnecting_status_clone = reconnecting_status.clone();
+      let scanning_status = Arc::new(AtomicBool::new(false));
+      let scanning_status_clone = scanning_status.clone();
+      let event_subscribers: Arc<Mutex<Vec<Sender<ButtplugClientEvent>>>> =
+        Arc::new(Mutex::new(vec![]));
+      let event_subscribers_clone = event_subscribers.clone();
 
       // Start the event loop before we run the handshake.
       async_manager::spawn(
         async move {
+          let loop_connected_status = connected_status.clone();
+          let loop_reconnecting_status = reconnecting_status.clone();
+          let loop_event_subscribers = event_subscribers.clone();
           let disconnect_fut = async move {
             loop {
-              if let Some(ButtplugClientEvent::ServerDisconnect) =
-                disconnect_event_receiver.next().await
-              {
-                connected_status.store(false, Ordering::SeqCst);
+              let event = disconnect_event_receiver.next().await;
+              // Update status before telling subscribers, so anything waiting
+              // on an event sees the status that goes with it.
+              match &event {
+                Some(ButtplugClientEvent::Reconnecting) => {
+                  loop_connected_status.store(false, Ordering::SeqCst);
+                  loop_reconnecting_status.store(true, Ordering::SeqCst);
+                }
+                Some(ButtplugClientEvent::Reconnected) => {
+                  loop_reconnecting_status.store(false, Ordering::SeqCst);
+                  loop_connected_status.store(true, Ordering::SeqCst);
+                }
+                Some(ButtplugClientEvent::ScanningFinished) => {
+                  scanning_status.store(false, Ordering::SeqCst);
+                }
+                _ => {}
+              }
+              if let Some(event) = &event {
+                // Drop subscribers that have stopped listening.
+                loop_event_subscribers
+                  .lock()
+                  .unwrap()
+                  .retain(|subscriber| subscriber.try_send(event.clone()).is_ok());
+              }
+              if let Some(ButtplugClientEvent::ServerDisconnect) | None = event {
                 break;
               }
             }
@@ -241,15 +486,28 @@ impl ButtplugClient {
             _ = client_event_loop_fut.fuse() => (),
             _ = disconnect_fut.fuse() => (),
           };
+          // Either way, we're not connected anymore.
+          connected_status.store(false, Ordering::SeqCst);
+          reconnecting_status.store(false, Ordering::SeqCst);
+          // Wake up anything waiting on events.
+          event_subscribers.lock().unwrap().clear();
         }
         .instrument(tracing::info_span!("Client Loop Span")),
       )
       .unwrap();
       let client = ButtplugClient::create_client(
         &client_name,
-        connected_status_clone,
+        ButtplugClientStatus {
+          connected: connected_status_clone,
+          reconnecting: reconnecting_status_clone,
+          scanning: scanning_status_clone,
+          event_subscribers: event_subscribers_clone,
+          server_spec_version,
+        },
         message_sender,
         device_map_reader,
+        default_timeout,
+        remote_connector,
         span.clone(),
       )
       .await?;
@@ -355,37 +613,60 @@ impl ButtplugClient {
   /// goes well.
   async fn create_client(
     client_name: &str,
-    connected_status: Arc<AtomicBool>,
+    status: ButtplugClientStatus,
     message_sender: Sender<ButtplugClientRequest>,
     device_map: Arc<DashMap<u32, ButtplugClientDeviceInternal>>,
+    default_timeout: Arc<Mutex<Option<Duration>>>,
+    remote_connector: bool,
     span: Span,
   ) -> Result<Self, ButtplugClientError> {
     // Create the client
     let mut client = ButtplugClient {
       client_name: client_name.to_string(),
       server_name: String::new(),
+      server_spec_version: status.server_spec_version,
       message_sender,
       // Since we'll have already connected and initialized by the time we hand
       // this to the client function, we can go ahead and declare that we're
       // connected here. If that's not true, we won't even execute the client
       // function.
-      connected: connected_status,
+      connected: status.connected,
+      reconnecting: status.reconnecting,
       device_map,
       _client_span: span,
+      scan_session: Arc::new(AtomicU32::new(0)),
+      scanning: status.scanning,
+      event_subscribers: status.event_subscribers,
+      timeout: None,
+      default_timeout,
+      remote_connector,
     };
 
     // Run our handshake
     info!("Running handshake with server.");
-    let msg = client
-      .send_message(
-        RequestServerInfo::new(&client.client_name, ButtplugMessageSpecVersion::Version2).into(),
-      )
-      .await?;
+    let mut spec_versions = HANDSHAKE_MESSAGE_SPEC_VERSIONS.iter().peekable();
+    let (msg, spec_version) = loop {
+      // We always have at least one version to try, and stop at the last one.
+      let spec_version = *spec_versions.next().unwrap();
+      match client
+        .send_message(RequestServerInfo::new(&client.client_name, spec_version).into())
+        .await
+      {
+        result if spec_versions.peek().is_some() && is_spec_version_rejection(&result) => {
+          info!(
+            "Server turned down message spec version {}, trying an older one.",
+            spec_version
+          );
+        }
+        result => break (result?, spec_version),
+      }
+    };
 
     debug!("Got ServerInfo return.");
     if let ButtplugCurrentSpecServerMessage::ServerInfo(server_info) = msg {
       info!("Connected to {}", server_info.server_name);
       client.server_name = server_info.server_name;
+      *client.server_spec_version.lock().unwrap() = spec_version.min(server_info.message_version);
       // TODO Handle ping time in the internal event loop
 
       // Get currently connected devices. The event loop will
@@ -395,9 +676,16 @@ impl ButtplugClient {
         .send_message(RequestDeviceList::default().into())
         .await?;
       if let ButtplugCurrentSpecServerMessage::DeviceList(m) = msg {
+        // Wait for the event loop to add the devices, so they're in the
+        // device list as soon as we return.
+        let device_list_fut = ButtplugFuture::<()>::default();
         client
-          .send_internal_message(ButtplugClientRequest::HandleDeviceList(m))
+          .send_internal_message(ButtplugClientRequest::HandleDeviceList(
+            m,
+            device_list_fut.get_state_clone(),
+          ))
           .await?;
+        device_list_fut.await;
       }
       Ok(client)
     } else {
@@ -408,26 +696,101 @@ impl ButtplugClient {
     }
   }
 
+  /// Returns the message spec version used with the server. Older than the
+  /// client's version if the server is an older release, in which case
+  /// commands are translated for it by the connector's serializer. Updated
+  /// when the client reconnects, since the server may have changed.
+  pub fn server_spec_version(&self) -> ButtplugMessageSpecVersion {
+    *self.server_spec_version.lock().unwrap()
+  }
+
   /// Returns true if client is currently connected.
   pub fn connected(&self) -> bool {
     self.connected.load(Ordering::SeqCst)
   }
 
+  /// Returns true if the connection to the server dropped and the client is
+  /// trying to reconnect. Only happens with clients created via
+  /// [ButtplugClient::connect_with_reconnect].
+  pub fn reconnecting(&self) -> bool {
+    self.reconnecting.load(Ordering::SeqCst)
+  }
+
+  /// Returns the timeout used for messages to the server, for this client and
+  /// the devices it creates. None means wait forever, which is the default.
+  pub fn default_timeout(&self) -> Option<Duration> {
+    *self.default_timeout.lock().unwrap()
+  }
+
+  /// Sets how long to wait for the server to reply to a message before
+  /// failing with [ButtplugClientError::MessageTimeout]. Applies to this
+  /// client, and all devices it creates, unless overridden with
+  /// [ButtplugClient::with_timeout] or
+  /// [ButtplugClientDevice::with_timeout]. None means wait forever.
+  pub fn set_default_timeout(&self, timeout: Option<Duration>) {
+    *self.default_timeout.lock().unwrap() = timeout;
+  }
+
+  /// Returns a handle to this client that uses `timeout` for its messages
+  /// instead of the default timeout. The handle shares the same connection,
+  /// so it can be used for a single call:
+  /// `client.with_timeout(Duration::from_secs(1)).stop_all_devices()`.
+  pub fn with_timeout(&self, timeout: Duration) -> Self {
+    self.handle_with_timeout(Some(timeout))
+  }
+
+  /// Creates another handle to this client, with its own timeout.
+  fn handle_with_timeout(&self, timeout: Option<Duration>) -> Self {
+    Self {
+      client_name: self.client_name.clone(),
+      server_name: self.server_name.clone(),
+      server_spec_version: self.server_spec_version.clone(),
+      message_sender: self.message_sender.clone(),
+      connected: self.connected.clone(),
+      reconnecting: self.reconnecting.clone(),
+      _client_span: self._client_span.clone(),
+      device_map: self.device_map.clone(),
+      scan_session: self.scan_session.clone(),
+      scanning: self.scanning.clone(),
+      event_subscribers: self.event_subscribers.clone(),
+      timeout,
+      default_timeout: self.default_timeout.clone(),
+      remote_connector: self.remote_connector,
+    }
+  }
+
   /// Disconnects from server, if connected.
   ///
   /// Returns Err(ButtplugClientError) if disconnection fails. It can be assumed
   /// that even on failure, the client will be disconnected.
+  ///
+  /// If the client is reconnecting, this stops it from trying again.
   pub fn disconnect(&self) -> ButtplugClientResultFuture {
     // Send the connector to the internal loop for management. Once we throw
     // the connector over, the internal loop will handle connecting and any
     // further communications with the server, if connection is successful.
     let fut = ButtplugConnectorFuture::default();
     let msg = ButtplugClientRequest::Disconnect(fut.get_state_clone());
-    let send_fut = self.send_internal_message(msg);
+    let send_fut: BoxFuture<'static, Result<(), ButtplugConnectorError>> =
+      if self.reconnecting.load(Ordering::SeqCst) {
+        // We're not connected, but the event loop is still running and needs to
+        // hear about this.
+        let message_sender = self.message_sender.clone();
+        Box::pin(async move {
+          message_sender
+            .send(msg)
+            .await
+            .map_err(|_| ButtplugConnectorError::ConnectorChannelClosed)
+        })
+      } else {
+        self.send_internal_message(msg)
+      };
     let connected = self.connected.clone();
+    let reconnecting = self.reconnecting.clone();
     Box::pin(async move {
       send_fut.await?;
       connected.store(false, Ordering::SeqCst);
+      reconnecting.store(false, Ordering::SeqCst);
       Ok(())
     })
   }
@@ -437,7 +800,162 @@ impl ButtplugClient {
   /// Returns Err([ButtplugClientError]) if request fails due to issues with
   /// DeviceManagers on the server, disconnection, etc.
   pub fn start_scanning(&self) -> ButtplugClientResultFuture {
-    self.send_message_expect_ok(StartScanning::default().into())
+    self.send_start_scanning(StartScanning::default())
+  }
+
+  /// Tells server to start scanning for devices, only using the named device
+  /// communication managers, and only connecting to devices that match the
+  /// named protocols. Empty lists select everything.
+  ///
+  /// The selection isn't part of the Buttplug protocol, so it can only be
+  /// used when connected to a server in the same process (i.e. with
+  /// [ButtplugInProcessClientConnector][crate::connector::ButtplugInProcessClientConnector]).
+  /// With remote servers, this returns
+  /// [ButtplugClientError::ScanningSelectionUnsupported] unless both lists are
+  /// empty.
+  ///
+  /// Returns Err([ButtplugClientError]) if request fails due to issues with
+  /// DeviceManagers on the server, unknown comm manager names, disconnection,
+  /// etc.
+  pub fn start_scanning_with_selection(
+    &self,
+    comm_managers: &[&str],
+    protocols: &[&str],
+  ) -> ButtplugClientResultFuture {
+    if self.remote_connector && !(comm_managers.is_empty() && protocols.is_empty()) {
+      return Box::pin(future::ready(Err(
+        ButtplugClientError::ScanningSelectionUnsupported,
+      )));
+    }
+    self.send_start_scanning(StartScanning::new_with_selection(comm_managers, protocols))
+  }
+
+  fn send_start_scanning(&self, msg: StartScanning) -> ButtplugClientResultFuture {
+    self.scan_session.fetch_add(1, Ordering::SeqCst);
+    // Set before sending, so a quick ScanningFinished can't be overwritten.
+    self.scanning.store(true, Ordering::SeqCst);
+    let send_fut = self.send_message_expect_ok(msg.into());
+    let scanning = self.scanning.clone();
+    Box::pin(async move {
+      let result = send_fut.await;
+      if result.is_err() {
+        scanning.store(false, Ordering::SeqCst);
+      }
+      result
+    })
+  }
+
+  /// Returns true if the server is scanning for devices, i.e. scanning has
+  /// been started and no [ButtplugClientEvent::ScanningFinished] event has
+  /// been received since.
+  pub fn scanning(&self) -> bool {
+    self.scanning.load(Ordering::SeqCst)
+  }
+
+  /// Returns a channel that gets a copy of every event from now on, until the
+  /// client disconnects.
+  fn subscribe_events(&self) -> Receiver<ButtplugClientEvent> {
+    let (sender, receiver) = unbounded();
+    self.event_subscribers.lock().unwrap().push(sender);
+    receiver
+  }
+
+  /// Waits for a device that `predicate` returns true for, checking the
+  /// devices already connected before waiting for new ones.
+  ///
+  /// Returns Err([ButtplugClientError::WaitTimeout]) if no matching device
+  /// shows up within `timeout`.
+  pub fn wait_for_device(
+    &self,
+    predicate: impl Fn(&ButtplugClientDevice) -> bool + Send + 'static,
+    timeout: Duration,
+  ) -> ButtplugClientResultFuture<ButtplugClientDevice> {
+    // Subscribe before looking at the device list, so we can't miss a device
+    // added in between.
+    let event_receiver = self.subscribe_events();
+    if let Some(device) = self.devices().into_iter().find(|device| predicate(device)) {
+      return Box::pin(future::ready(Ok(device)));
+    }
+    let connected = self.connected.clone();
+    Box::pin(async move {
+      if !connected.load(Ordering::SeqCst) {
+        return Err(ButtplugConnectorError::ConnectorNotConnected.into());
+      }
+      let mut timeout_fut = Delay::new(timeout).fuse();
+      loop {
+        select! {
+          event = event_receiver.recv().fuse() => match event {
+            Ok(ButtplugClientEvent::DeviceAdded(device)) if predicate(&device) => {
+              return Ok(device)
+            }
+            Ok(_) => continue,
+            Err(_) => return Err(ButtplugConnectorError::ConnectorNotConnected.into()),
+          },
+          _ = timeout_fut => return Err(ButtplugClientError::WaitTimeout(timeout)),
+        }
+      }
+    })
+  }
+
+  /// Waits for the server to finish scanning, then returns all connected
+  /// devices. Returns right away if the server isn't scanning.
+  ///
+  /// Returns Err([ButtplugClientError::WaitTimeout]) if scanning doesn't
+  /// finish within `timeout`.
+  pub fn wait_for_scanning_finished(
+    &self,
+    timeout: Duration,
+  ) -> ButtplugClientResultFuture<Vec<ButtplugClientDevice>> {
+    let event_receiver = self.subscribe_events();
+    let client = self.handle_with_timeout(self.timeout);
+    Box::pin(async move {
+      if client.scanning() {
+        let mut timeout_fut = Delay::new(timeout).fuse();
+        loop {
+          select! {
+            event = event_receiver.recv().fuse() => match event {
+              Ok(ButtplugClientEvent::ScanningFinished) => break,
+              Ok(_) => continue,
+              Err(_) => return Err(ButtplugConnectorError::ConnectorNotConnected.into()),
+            },
+            _ = timeout_fut => return Err(ButtplugClientError::WaitTimeout(timeout)),
+          }
+        }
+      }
+      Ok(client.devices())
+    })
+  }
+
+  /// Tells server to start scanning for devices, then stop scanning after
+  /// `duration`. A [ButtplugClientEvent::ScanningFinished] event will be
+  /// emitted once scanning stops.
+  ///
+  /// If scanning is restarted before `duration` is up, the new scanning
+  /// session is not stopped.
+  ///
+  /// Returns Err([ButtplugClientError]) if starting scanning fails due to
+  /// issues with DeviceManagers on the server, disconnection, etc.
+  pub fn start_scanning_with_duration(&self, duration: Duration) -> ButtplugClientResultFuture {
+    let start_fut = self.start_scanning();
+    let session = self.scan_session.load(Ordering::SeqCst);
+    let scan_session = self.scan_session.clone();
+    let stop_fut = self.stop_scanning();
+    Box::pin(async move {
+      start_fut.await?;
+      async_manager::spawn(async move {
+        Delay::new(duration).await;
+        if scan_session.load(Ordering::SeqCst) != session {
+          return;
+        }
+        // The server may have already finished scanning on its own, in which
+        // case this will fail. That's fine, we just wanted it stopped.
+        if let Err(e) = stop_fut.await {
+          debug!("Timed scan stop returned error: {:?}", e);
+        }
+      })
+      .unwrap();
+      Ok(())
+    })
   }
 
   /// Tells server to stop scanning for devices.
@@ -453,7 +971,15 @@ impl ButtplugClient {
   /// Returns Err([ButtplugClientError]) if request fails due to issues with
   /// DeviceManagers on the server, disconnection, etc.
   pub fn stop_all_devices(&self) -> ButtplugClientResultFuture {
-    self.send_message_expect_ok(StopAllDevices::default().into())
+    let send_fut = self.send_message_expect_ok(StopAllDevices::default().into());
+    let device_map = self.device_map.clone();
+    Box::pin(async move {
+      send_fut.await?;
+      for device in device_map.iter() {
+        device.state.lock().unwrap().stop();
+      }
+      Ok(())
+    })
   }
 
   /// Send message to the internal event loop.
@@ -489,10 +1015,9 @@ impl ButtplugClient {
   ) -> ButtplugInternalClientMessageResultFuture {
     // Create a future to pair with the message being resolved.
     let fut = ButtplugClientMessageFuture::default();
-    let internal_msg = ButtplugClientRequest::Message(ButtplugClientMessageFuturePair::new(
-      msg,
-      fut.get_state_clone(),
-    ));
+    let internal_msg = ButtplugClientRequest::Message(
+      ButtplugClientMessageFuturePair::new_with_timeout(msg, fut.get_state_clone(), self.timeout),
+    );
 
     // Send message to internal loop and wait for return.
     let send_fut = self.send_internal_message(internal_msg);
@@ -524,8 +1049,39 @@ impl ButtplugClient {
         &(*device.device),
         self.message_sender.clone(),
         (*device.channel).clone(),
+        device.state.clone(),
       )));
     }
     device_clones
   }
+
+  /// Retrieves the connected devices that have `capability`.
+  pub fn devices_with(
+    &self,
+    capability: ButtplugClientDeviceCapability,
+  ) -> Vec<ButtplugClientDevice> {
+    self
+      .devices()
+      .into_iter()
+      .filter(|device| device.has_capability(capability))
+      .collect()
+  }
+
+  /// Creates a group of all devices connected to the server, now and in the
+  /// future.
+  pub fn device_group(&self) -> ButtplugClientDeviceGroup {
+    self.device_group_with_filter(|_| true)
+  }
+
+  /// Creates a group of the devices connected to the server, now and in the
+  /// future, that `filter` returns true for.
+  pub fn device_group_with_filter(
+    &self,
+    filter: impl Fn(&ButtplugClientDevice) -> bool + Send + Sync + 'static,
+  ) -> ButtplugClientDeviceGroup {
+    ButtplugClientDeviceGroup::new(
+      Arc::new(self.handle_with_timeout(self.timeout)),
+      Arc::new(filter),
+    )
+  }
 }
@@ -0,0 +1,472 @@
+// Buttplug Rust Source Code File - See https://buttplug.io for more info.
+//
+// Copyright 2016-2020 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+
+//! Keyframed vibration and rotation patterns, played back by the client.
+//!
+//! A [ButtplugClientPattern] is a list of keyframes, each holding an intensity
+//! per device feature, along with how to interpolate between them. Patterns
+//! are started with
+//! [ButtplugClientDevice::vibrate_pattern][super::device::ButtplugClientDevice::vibrate_pattern]
+//! or
+//! [ButtplugClientDevice::rotate_pattern][super::device::ButtplugClientDevice::rotate_pattern],
+//! which run them on the client's [async_manager] runtime and return a
+//! [ButtplugClientPatternHandle] for waiting on or cancelling playback.
+
+use super::{
+  device::{ButtplugClientDevice, RotateCommand, VibrateCommand},
+  ButtplugClientError,
+  ButtplugClientResultFuture,
+};
+use crate::{
+  connector::ButtplugConnectorError,
+  core::{
+    errors::{ButtplugDeviceError, ButtplugError},
+    messages::ButtplugDeviceMessageType,
+  },
+  util::async_manager,
+};
+use async_channel::{bounded, Receiver};
+use futures::Future;
+use futures_timer::Delay;
+use std::{
+  sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+  },
+  time::{Duration, Instant},
+};
+
+/// How values change between two keyframes of a [ButtplugClientPattern].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PatternInterpolation {
+  /// Holds each keyframe's values until the next keyframe.
+  Step,
+  /// Changes values at a constant rate.
+  Linear,
+  /// Starts slow and speeds up.
+  EaseIn,
+  /// Starts fast and slows down.
+  EaseOut,
+  /// Starts and ends slow.
+  EaseInOut,
+}
+
+impl PatternInterpolation {
+  /// Maps progress through a keyframe segment (0.0-1.0) to how far the value
+  /// has moved towards the next keyframe (0.0-1.0).
+  fn apply(self, progress: f64) -> f64 {
+    match self {
+      PatternInterpolation::Step => {
+        if progress < 1.0 {
+          0.0
+        } else {
+          1.0
+        }
+      }
+      PatternInterpolation::Linear => progress,
+      PatternInterpolation::EaseIn => progress * progress,
+      PatternInterpolation::EaseOut => 1.0 - (1.0 - progress) * (1.0 - progress),
+      PatternInterpolation::EaseInOut => progress * progress * (3.0 - 2.0 * progress),
+    }
+  }
+}
+
+/// A point in a [ButtplugClientPattern].
+#[derive(Debug, Clone, PartialEq)]
+pub struct PatternKeyframe {
+  /// Time from the start of the pattern.
+  pub time: Duration,
+  /// Intensity per feature, with the same layout as
+  /// [VibrateCommand::SpeedVec]. A single value applies to all features.
+  ///
+  /// For vibration, values are speeds (0.0-1.0). For rotation, values are
+  /// -1.0-1.0, with the sign giving the direction (clockwise if positive), so
+  /// interpolating between directions passes through a stop.
+  pub values: Vec<f64>,
+}
+
+/// Sequence of keyframes to play on a device.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ButtplugClientPattern {
+  keyframes: Vec<PatternKeyframe>,
+  interpolation: PatternInterpolation,
+  looping: bool,
+  update_interval: Duration,
+}
+
+impl ButtplugClientPattern {
+  /// Creates an empty pattern. Playback updates the device every 50ms and
+  /// doesn't loop unless changed with
+  /// [ButtplugClientPattern::update_interval] or
+  /// [ButtplugClientPattern::looping].
+  pub fn new(interpolation: PatternInterpolation) -> Self {
+    Self {
+      keyframes: vec![],
+      interpolation,
+      looping: false,
+      update_interval: Duration::from_millis(50),
+    }
+  }
+
+  /// Creates a pattern that moves all features from `from` to `to` over
+  /// `duration`, then holds at `to`.
+  pub fn ramp(from: f64, to: f64, duration: Duration, interpolation: PatternInterpolation) -> Self {
+    Self::new(interpolation)
+      .keyframe(Duration::from_secs(0), vec![from])
+      .keyframe(duration, vec![to])
+  }
+
+  /// Adds a keyframe. Keyframes are kept sorted by time, and a keyframe at
+  /// the same time as an existing one replaces it.
+  pub fn keyframe(mut self, time: Duration, values: Vec<f64>) -> Self {
+    match self.keyframes.binary_search_by(|k| k.time.cmp(&time)) {
+      Ok(idx) => self.keyframes[idx].values = values,
+      Err(idx) => self.keyframes.insert(idx, PatternKeyframe { time, values }),
+    }
+    self
+  }
+
+  /// Sets whether the pattern starts over after its last keyframe.
+  pub fn looping(mut self, looping: bool) -> Self {
+    self.looping = looping;
+    self
+  }
+
+  /// Sets how often playback checks whether the device needs an update.
+  pub fn update_interval(mut self, update_interval: Duration) -> Self {
+    self.update_interval = update_interval;
+    self
+  }
+
+  pub fn keyframes(&self) -> &[PatternKeyframe] {
+    &self.keyframes
+  }
+
+  pub fn interpolation(&self) -> PatternInterpolation {
+    self.interpolation
+  }
+
+  pub fn is_looping(&self) -> bool {
+    self.looping
+  }
+
+  /// Time of the last keyframe.
+  pub fn duration(&self) -> Duration {
+    self
+      .keyframes
+      .last()
+      .map_or(Duration::from_secs(0), |k| k.time)
+  }
+
+  /// Number of values per keyframe, after single values have been expanded
+  /// to match the longest keyframe.
+  fn value_count(&self) -> usize {
+    self
+      .keyframes
+      .iter()
+      .map(|k| k.values.len())
+      .max()
+      .unwrap_or(0)
+  }
+
+  /// Returns the interpolated values at `elapsed` time from the start of the
+  /// pattern. Looping patterns wrap around, others hold their last keyframe.
+  pub fn values_at(&self, elapsed: Duration) -> Vec<f64> {
+    let count = self.value_count();
+    let expand = |values: &[f64]| -> Vec<f64> {
+      if values.len() == 1 {
+        vec![values[0]; count]
+      } else {
+        values.to_vec()
+      }
+    };
+    let (first, last) = match (self.keyframes.first(), self.keyframes.last()) {
+      (Some(first), Some(last)) => (first, last),
+      _ => return vec![],
+    };
+    let duration = self.duration();
+    let elapsed = if self.looping && duration.as_nanos() > 0 {
+      Duration::from_nanos((elapsed.as_nanos() % duration.as_nanos()) as u64)
+    } else {
+      elapsed
+    };
+    if elapsed <= first.time {
+      return expand(&first.values);
+    }
+    if elapsed >= last.time {
+      return expand(&last.values);
+    }
+    // Find the segment we're in. The checks above guarantee there's a
+    // keyframe on each side.
+    let next_idx = self
+      .keyframes
+      .iter()
+      .position(|k| k.time > elapsed)
+      .unwrap();
+    let start = &self.keyframes[next_idx - 1];
+    let end = &self.keyframes[next_idx];
+    let progress = (elapsed - start.time).as_secs_f64() / (end.time - start.time).as_secs_f64();
+    let amount = self.interpolation.apply(progress);
+    let start_values = expand(&start.values);
+    let end_values = expand(&end.values);
+    start_values
+      .iter()
+      .zip(end_values.iter().chain(std::iter::repeat(&0.0)))
+      .map(|(start, end)| start + (end - start) * amount)
+      .collect()
+  }
+}
+
+/// Handle to a pattern or script being played on a device.
+///
+/// Dropping the handle does not stop playback.
+pub struct ButtplugClientPatternHandle {
+  cancelled: Arc<AtomicBool>,
+  result_receiver: Receiver<Result<(), ButtplugClientError>>,
+}
+
+impl ButtplugClientPatternHandle {
+  /// Spawns `playback`, passing it the flag set by
+  /// [ButtplugClientPatternHandle::cancel].
+  pub(super) fn spawn<F>(device_name: &str, playback: impl FnOnce(Arc<AtomicBool>) -> F) -> Self
+  where
+    F: Future<Output = Result<(), ButtplugClientError>> + Send + 'static,
+  {
+    let cancelled = Arc::new(AtomicBool::new(false));
+    let (result_sender, result_receiver) = bounded(1);
+    let playback_fut = playback(cancelled.clone());
+    let device_name = device_name.to_owned();
+    async_manager::spawn(async move {
+      let result = playback_fut.await;
+      if let Err(err) = &result {
+        error!("Playback on device {} stopped: {:?}", device_name, err);
+      }
+      // The handle may have been dropped, in which case there's no one to
+      // tell.
+      let _ = result_sender.try_send(result);
+    })
+    .unwrap();
+    Self {
+      cancelled,
+      result_receiver,
+    }
+  }
+
+  /// Stops playback and sets the features being played on to 0. Takes effect
+  /// by the next update interval.
+  pub fn cancel(&self) {
+    self.cancelled.store(true, Ordering::SeqCst);
+  }
+
+  /// Returns true if playback has finished, been cancelled, or failed.
+  pub fn is_finished(&self) -> bool {
+    self.result_receiver.is_closed()
+  }
+
+  /// Waits for playback to finish. Returns the error that stopped playback,
+  /// if any. Looping patterns only finish when cancelled or when sending to
+  /// the device fails.
+  pub fn wait(self) -> ButtplugClientResultFuture {
+    Box::pin(async move {
+      self
+        .result_receiver
+        .recv()
+        .await
+        .unwrap_or_else(|_| Err(ButtplugConnectorError::ConnectorChannelClosed.into()))
+    })
+  }
+}
+
+#[derive(Clone, Copy)]
+pub(super) enum PatternTarget {
+  Vibrate,
+  Rotate,
+}
+
+impl PatternTarget {
+  fn message_type(self) -> ButtplugDeviceMessageType {
+    match self {
+      PatternTarget::Vibrate => ButtplugDeviceMessageType::VibrateCmd,
+      PatternTarget::Rotate => ButtplugDeviceMessageType::RotateCmd,
+    }
+  }
+
+  fn send(self, device: &ButtplugClientDevice, values: &[f64]) -> ButtplugClientResultFuture {
+    match self {
+      PatternTarget::Vibrate => device.vibrate(VibrateCommand::SpeedVec(
+        values.iter().map(|v| v.clamp(0.0, 1.0)).collect(),
+      )),
+      PatternTarget::Rotate => device.rotate(RotateCommand::RotateVec(
+        values
+          .iter()
+          .map(|v| (v.abs().min(1.0), *v >= 0.0))
+          .collect(),
+      )),
+    }
+  }
+}
+
+/// Value as the device will see it, given its step count. Used to skip
+/// updates that wouldn't change anything on the device.
+#[derive(PartialEq)]
+pub(super) enum DeviceStep {
+  Step(u32, bool),
+  Continuous(f64),
+}
+
+pub(super) fn device_steps(values: &[f64], step_counts: &Option<Vec<u32>>) -> Vec<DeviceStep> {
+  values
+    .iter()
+    .enumerate()
+    .map(|(i, value)| {
+      let magnitude = value.abs().min(1.0);
+      match step_counts.as_ref().and_then(|steps| steps.get(i)) {
+        // Matches the rounding the server does when converting to device
+        // steps.
+        Some(steps) => {
+          let step = (magnitude * *steps as f64).ceil() as u32;
+          // Direction doesn't matter when stopped.
+          DeviceStep::Step(step, step == 0 || *value >= 0.0)
+        }
+        None => DeviceStep::Continuous(*value),
+      }
+    })
+    .collect()
+}
+
+/// Starts playing `pattern` on `device`, returning a handle for the running
+/// pattern.
+pub(super) fn play_pattern(
+  device: &ButtplugClientDevice,
+  pattern: ButtplugClientPattern,
+  target: PatternTarget,
+) -> Result<ButtplugClientPatternHandle, ButtplugClientError> {
+  let message_type = target.message_type();
+  let attributes = device
+    .allowed_messages
+    .get(&message_type)
+    .ok_or_else(|| ButtplugError::from(ButtplugDeviceError::MessageNotSupported(message_type)))?;
+  let feature_count = attributes.feature_count.unwrap_or(0);
+  if pattern.value_count() as u32 > feature_count {
+    return Err(
+      ButtplugError::from(ButtplugDeviceError::DeviceFeatureCountMismatch(
+        feature_count,
+        pattern.value_count() as u32,
+      ))
+      .into(),
+    );
+  }
+  // Single value patterns apply to every feature.
+  let expand_to = if pattern.value_count() == 1 {
+    feature_count as usize
+  } else {
+    pattern.value_count()
+  };
+  let step_counts = attributes.step_count.clone();
+  let device = device.clone();
+  Ok(ButtplugClientPatternHandle::spawn(
+    &device.name.clone(),
+    |cancelled| async move {
+      let start = Instant::now();
+      let duration = pattern.duration();
+      let mut last_steps = None;
+      loop {
+        if cancelled.load(Ordering::SeqCst) {
+          debug!("Pattern on device {} cancelled.", device.name);
+          if last_steps.is_none() {
+            return Ok(());
+          }
+          return target.send(&device, &vec![0.0; expand_to]).await;
+        }
+        let elapsed = start.elapsed();
+        let finished = !pattern.is_looping() && elapsed >= duration;
+        let mut values = pattern.values_at(elapsed);
+        if values.len() == 1 {
+          values = vec![values[0]; expand_to];
+        }
+        let steps = device_steps(&values, &step_counts);
+        if !values.is_empty() && last_steps.as_ref() != Some(&steps) {
+          target.send(&device, &values).await?;
+          last_steps = Some(steps);
+        }
+        if finished {
+          return Ok(());
+        }
+        Delay::new(pattern.update_interval).await;
+      }
+    },
+  ))
+}
+
+#[cfg(test)]
+mod test {
+  use super::{ButtplugClientPattern, PatternInterpolation};
+  use std::time::Duration;
+
+  fn ms(millis: u64) -> Duration {
+    Duration::from_millis(millis)
+  }
+
+  #[test]
+  fn test_pattern_linear_interpolation() {
+    let pattern = ButtplugClientPattern::ramp(0.0, 1.0, ms(100), PatternInterpolation::Linear);
+    assert_eq!(pattern.values_at(ms(0)), vec![0.0]);
+    assert!((pattern.values_at(ms(25))[0] - 0.25).abs() < 0.0001);
+    assert_eq!(pattern.values_at(ms(100)), vec![1.0]);
+    // Non-looping patterns hold their last value.
+    assert_eq!(pattern.values_at(ms(500)), vec![1.0]);
+  }
+
+  #[test]
+  fn test_pattern_step_interpolation() {
+    let pattern = ButtplugClientPattern::new(PatternInterpolation::Step)
+      .keyframe(ms(0), vec![0.2, 0.4])
+      .keyframe(ms(100), vec![0.8, 1.0]);
+    assert_eq!(pattern.values_at(ms(99)), vec![0.2, 0.4]);
+    assert_eq!(pattern.values_at(ms(100)), vec![0.8, 1.0]);
+  }
+
+  #[test]
+  fn test_pattern_ease_interpolation() {
+    let ease_in = ButtplugClientPattern::ramp(0.0, 1.0, ms(100), PatternInterpolation::EaseIn);
+    let ease_out = ButtplugClientPattern::ramp(0.0, 1.0, ms(100), PatternInterpolation::EaseOut);
+    let ease_in_out =
+      ButtplugClientPattern::ramp(0.0, 1.0, ms(100), PatternInterpolation::EaseInOut);
+    assert!(ease_in.values_at(ms(50))[0] < 0.5);
+    assert!(ease_out.values_at(ms(50))[0] > 0.5);
+    assert!((ease_in_out.values_at(ms(50))[0] - 0.5).abs() < 0.0001);
+  }
+
+  #[test]
+  fn test_pattern_looping() {
+    let pattern = ButtplugClientPattern::new(PatternInterpolation::Linear)
+      .keyframe(ms(0), vec![0.0])
+      .keyframe(ms(100), vec![1.0])
+      .looping(true);
+    assert!((pattern.values_at(ms(150))[0] - 0.5).abs() < 0.0001);
+  }
+
+  #[test]
+  fn test_pattern_keyframe_ordering() {
+    let pattern = ButtplugClientPattern::new(PatternInterpolation::Linear)
+      .keyframe(ms(100), vec![1.0])
+      .keyframe(ms(0), vec![0.0])
+      .keyframe(ms(100), vec![0.5]);
+    assert_eq!(pattern.keyframes().len(), 2);
+    assert_eq!(pattern.duration(), ms(100));
+    assert_eq!(pattern.values_at(ms(100)), vec![0.5]);
+  }
+
+  #[test]
+  fn test_pattern_mixed_value_counts() {
+    let pattern = ButtplugClientPattern::new(PatternInterpolation::Linear)
+      .keyframe(ms(0), vec![0.0])
+      .keyframe(ms(100), vec![1.0, 0.5]);
+    assert_eq!(pattern.values_at(ms(0)), vec![0.0, 0.0]);
+    assert_eq!(pattern.values_at(ms(100)), vec![1.0, 0.5]);
+  }
+}
@@ -0,0 +1,383 @@
+// Buttplug Rust Source Code File - See https://buttplug.io for more info.
+//
+// Copyright 2016-2020 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+
+//! Scriptable connector for testing applications built on
+//! [ButtplugClient][crate::client::ButtplugClient].
+
+use crate::{
+  connector::{ButtplugConnector, ButtplugConnectorError, ButtplugConnectorResultFuture},
+  core::{
+    errors::{ButtplugError, ButtplugServerError},
+    messages::{
+      ButtplugCurrentSpecClientMessage,
+      ButtplugCurrentSpecServerMessage,
+      ButtplugMessage,
+      DeviceAdded,
+      DeviceList,
+      DeviceMessageInfo,
+      DeviceRemoved,
+      MessageAttributesMap,
+      Ok,
+      ServerInfo,
+      BUTTPLUG_CURRENT_MESSAGE_SPEC_VERSION,
+    },
+  },
+};
+use async_channel::{unbounded, Receiver, Sender};
+use futures::{
+  future::{self, BoxFuture},
+  StreamExt,
+};
+use std::sync::{
+  atomic::{AtomicBool, Ordering},
+  Arc,
+  Mutex,
+};
+
+/// Server name the mock connector reports during the handshake.
+pub const BUTTPLUG_MOCK_SERVER_NAME: &str = "Buttplug Mock Server";
+
+/// Reply to a client message, either a server message or an error.
+pub type ButtplugMockReply = Result<ButtplugCurrentSpecServerMessage, ButtplugError>;
+
+type ButtplugMockReplyHandler =
+  Arc<dyn Fn(&ButtplugCurrentSpecClientMessage) -> Option<ButtplugMockReply> + Send + Sync>;
+
+#[derive(Default)]
+struct ButtplugMockConnectorState {
+  /// Every message the client has sent, in order.
+  sent_messages: Vec<ButtplugCurrentSpecClientMessage>,
+  /// Channels waiting on the next messages the client sends.
+  sent_subscribers: Vec<Sender<ButtplugCurrentSpecClientMessage>>,
+  /// Scripted replies, newest first.
+  reply_handlers: Vec<ButtplugMockReplyHandler>,
+  /// Devices reported in DeviceList replies.
+  devices: Vec<DeviceMessageInfo>,
+}
+
+/// Connector that stands in for a server, for testing applications without
+/// running one.
+///
+/// Every message the client sends is recorded, and can be checked using the
+/// [ButtplugMockConnectorHandle] returned alongside the connector. The handle
+/// also scripts replies, and sends events as if they came from the server.
+///
+/// Without scripted replies, the connector answers the handshake with
+/// [BUTTPLUG_MOCK_SERVER_NAME], RequestDeviceList with the devices added via
+/// [ButtplugMockConnectorHandle::add_device], and everything else with Ok.
+///
+/// Only needs the `client` feature, so no server, device configuration or
+/// protocols are involved.
+pub struct ButtplugMockConnector {
+  handle: ButtplugMockConnectorHandle,
+  /// Receiver for messages going to the client. Taken on connect.
+  client_receiver: Option<Receiver<Result<ButtplugCurrentSpecServerMessage, ButtplugServerError>>>,
+}
+
+impl ButtplugMockConnector {
+  // Can't use the Default trait because we return a tuple, so this is the next
+  // best thing.
+  pub fn new() -> (Self, ButtplugMockConnectorHandle) {
+    let (client_sender, client_receiver) = unbounded();
+    let handle = ButtplugMockConnectorHandle {
+      state: Arc::new(Mutex::new(ButtplugMockConnectorState::default())),
+      client_sender,
+      connected: Arc::new(AtomicBool::new(false)),
+    };
+    (
+      Self {
+        handle: handle.clone(),
+        client_receiver: Some(client_receiver),
+      },
+      handle,
+    )
+  }
+}
+
+impl ButtplugConnector<ButtplugCurrentSpecClientMessage, ButtplugCurrentSpecServerMessage>
+  for ButtplugMockConnector
+{
+  fn connect(
+    &mut self,
+  ) -> BoxFuture<
+    'static,
+    Result<
+      Receiver<Result<ButtplugCurrentSpecServerMessage, ButtplugServerError>>,
+      ButtplugConnectorError,
+    >,
+  > {
+    if let Some(recv) = self.client_receiver.take() {
+      self.handle.connected.store(true, Ordering::SeqCst);
+      Box::pin(future::ready(Result::Ok(recv)))
+    } else {
+      ButtplugConnectorError::ConnectorAlreadyConnected.into()
+    }
+  }
+
+  fn disconnect(&self) -> ButtplugConnectorResultFuture {
+    self.handle.close();
+    Box::pin(future::ready(Result::Ok(())))
+  }
+
+  fn send(&self, msg: ButtplugCurrentSpecClientMessage) -> ButtplugConnectorResultFuture {
+    if !self.handle.connected() {
+      return ButtplugConnectorError::ConnectorNotConnected.into();
+    }
+    let reply = self.handle.record_and_reply(msg);
+    let sender = self.handle.client_sender.clone();
+    Box::pin(async move {
+      sender
+        .send(reply)
+        .await
+        .map_err(|_| ButtplugConnectorError::ConnectorChannelClosed)
+    })
+  }
+}
+
+/// Test side of a [ButtplugMockConnector], for checking what the client sent,
+/// scripting replies, and sending server events.
+///
+/// Clones share the same connector.
+#[derive(Clone)]
+pub struct ButtplugMockConnectorHandle {
+  state: Arc<Mutex<ButtplugMockConnectorState>>,
+  client_sender: Sender<Result<ButtplugCurrentSpecServerMessage, ButtplugServerError>>,
+  connected: Arc<AtomicBool>,
+}
+
+impl ButtplugMockConnectorHandle {
+  /// Returns true while the client is connected, from when it connects until
+  /// either side disconnects.
+  pub fn connected(&self) -> bool {
+    self.connected.load(Ordering::SeqCst)
+  }
+
+  /// Returns every message the client has sent so far, including the
+  /// handshake.
+  pub fn sent_messages(&self) -> Vec<ButtplugCurrentSpecClientMessage> {
+    self.state.lock().unwrap().sent_messages.clone()
+  }
+
+  /// Returns the last message the client sent.
+  pub fn last_sent_message(&self) -> Option<ButtplugCurrentSpecClientMessage> {
+    self.state.lock().unwrap().sent_messages.last().cloned()
+  }
+
+  /// Forgets the messages recorded so far, so later assertions only see what
+  /// the client sends from now on.
+  pub fn clear_sent_messages(&self) {
+    self.state.lock().unwrap().sent_messages.clear();
+  }
+
+  /// Panics unless the client has sent a message matching `predicate`.
+  pub fn assert_sent<F>(&self, predicate: F)
+  where
+    F: Fn(&ButtplugCurrentSpecClientMessage) -> bool,
+  {
+    let sent_messages = self.sent_messages();
+    assert!(
+      sent_messages.iter().any(predicate),
+      "No matching message was sent. Sent messages: {:?}",
+      sent_messages
+    );
+  }
+
+  /// Panics if the client has sent a message matching `predicate`.
+  pub fn assert_not_sent<F>(&self, predicate: F)
+  where
+    F: Fn(&ButtplugCurrentSpecClientMessage) -> bool,
+  {
+    let sent_messages = self.sent_messages();
+    assert!(
+      !sent_messages.iter().any(predicate),
+      "A matching message was sent. Sent messages: {:?}",
+      sent_messages
+    );
+  }
+
+  /// Waits for the client to send a message matching `predicate`, and
+  /// returns it. Resolves immediately if one was already sent.
+  ///
+  /// Resolves to None if the connection closes first.
+  pub fn wait_for_sent<F>(
+    &self,
+    predicate: F,
+  ) -> BoxFuture<'static, Option<ButtplugCurrentSpecClientMessage>>
+  where
+    F: Fn(&ButtplugCurrentSpecClientMessage) -> bool + Send + 'static,
+  {
+    let (sender, mut receiver) = unbounded();
+    {
+      // Check and subscribe under the same lock, so nothing sent in between
+      // is missed.
+      let mut state = self.state.lock().unwrap();
+      if let Some(msg) = state.sent_messages.iter().find(|msg| predicate(msg)) {
+        return Box::pin(future::ready(Some(msg.clone())));
+      }
+      if !self.connected() {
+        return Box::pin(future::ready(None));
+      }
+      state.sent_subscribers.push(sender);
+    }
+    Box::pin(async move {
+      while let Some(msg) = receiver.next().await {
+        if predicate(&msg) {
+          return Some(msg);
+        }
+      }
+      None
+    })
+  }
+
+  /// Adds a reply handler. For each message the client sends, handlers are
+  /// tried from the most recently added, and the first one returning Some
+  /// supplies the reply. If none do, the default reply is used.
+  ///
+  /// The reply's message id is set to match the client message. Handlers are
+  /// called without the handle's state locked, so they can use a clone of
+  /// the handle.
+  pub fn reply_with<F>(&self, handler: F)
+  where
+    F: Fn(&ButtplugCurrentSpecClientMessage) -> Option<ButtplugMockReply> + Send + Sync + 'static,
+  {
+    self
+      .state
+      .lock()
+      .unwrap()
+      .reply_handlers
+      .insert(0, Arc::new(handler));
+  }
+
+  /// Removes all reply handlers, going back to the default replies.
+  pub fn clear_reply_handlers(&self) {
+    self.state.lock().unwrap().reply_handlers.clear();
+  }
+
+  /// Adds a device, which is included in DeviceList replies. If the client
+  /// is connected, it's also told about the device with a DeviceAdded event.
+  pub fn add_device(
+    &self,
+    device_index: u32,
+    device_name: &str,
+    device_messages: &MessageAttributesMap,
+  ) -> ButtplugConnectorResultFuture {
+    let device_added = DeviceAdded::new(device_index, device_name, device_messages);
+    {
+      let mut state = self.state.lock().unwrap();
+      state
+        .devices
+        .retain(|device| device.device_index != device_index);
+      state.devices.push(DeviceMessageInfo::from(&device_added));
+    }
+    if self.connected() {
+      self.send_event(device_added.into())
+    } else {
+      Box::pin(future::ready(Result::Ok(())))
+    }
+  }
+
+  /// Removes a device added with [ButtplugMockConnectorHandle::add_device].
+  /// If the client is connected, it's told with a DeviceRemoved event.
+  pub fn remove_device(&self, device_index: u32) -> ButtplugConnectorResultFuture {
+    self
+      .state
+      .lock()
+      .unwrap()
+      .devices
+      .retain(|device| device.device_index != device_index);
+    if self.connected() {
+      self.send_event(DeviceRemoved::new(device_index).into())
+    } else {
+      Box::pin(future::ready(Result::Ok(())))
+    }
+  }
+
+  /// Sends a message to the client as a server event, like ScanningFinished
+  /// or RawReading. Event messages normally have an id of 0.
+  pub fn send_event(&self, msg: ButtplugCurrentSpecServerMessage) -> ButtplugConnectorResultFuture {
+    self.send_to_client(Result::Ok(msg))
+  }
+
+  /// Sends an error to the client that isn't a reply to any message.
+  pub fn send_error(&self, error: ButtplugError) -> ButtplugConnectorResultFuture {
+    self.send_to_client(Err(ButtplugServerError::new_system_error(error)))
+  }
+
+  /// Drops the connection from the server side, as if the server went away.
+  pub fn disconnect(&self) {
+    self.close();
+  }
+
+  fn send_to_client(
+    &self,
+    msg: Result<ButtplugCurrentSpecServerMessage, ButtplugServerError>,
+  ) -> ButtplugConnectorResultFuture {
+    if !self.connected() {
+      return ButtplugConnectorError::ConnectorNotConnected.into();
+    }
+    let sender = self.client_sender.clone();
+    Box::pin(async move {
+      sender
+        .send(msg)
+        .await
+        .map_err(|_| ButtplugConnectorError::ConnectorChannelClosed)
+    })
+  }
+
+  fn close(&self) {
+    self.connected.store(false, Ordering::SeqCst);
+    self.client_sender.close();
+    // Wake up anything waiting on sent messages.
+    self.state.lock().unwrap().sent_subscribers.clear();
+  }
+
+  /// Records a message from the client, and works out the reply to it.
+  fn record_and_reply(
+    &self,
+    msg: ButtplugCurrentSpecClientMessage,
+  ) -> Result<ButtplugCurrentSpecServerMessage, ButtplugServerError> {
+    let reply_handlers = {
+      let mut state = self.state.lock().unwrap();
+      state.sent_messages.push(msg.clone());
+      state
+        .sent_subscribers
+        .retain(|subscriber| subscriber.try_send(msg.clone()).is_ok());
+      state.reply_handlers.clone()
+    };
+    // Handlers run without the state locked, so they can use the handle.
+    let reply = reply_handlers
+      .iter()
+      .find_map(|handler| handler(&msg))
+      .unwrap_or_else(|| Result::Ok(default_reply(&msg, &self.state.lock().unwrap().devices)));
+    match reply {
+      Result::Ok(mut reply_msg) => {
+        reply_msg.set_id(msg.get_id());
+        Result::Ok(reply_msg)
+      }
+      Err(err) => Err(ButtplugServerError::new_message_error(msg.get_id(), err)),
+    }
+  }
+}
+
+/// Reply used when no handler supplies one.
+fn default_reply(
+  msg: &ButtplugCurrentSpecClientMessage,
+  devices: &[DeviceMessageInfo],
+) -> ButtplugCurrentSpecServerMessage {
+  match msg {
+    ButtplugCurrentSpecClientMessage::RequestServerInfo(_) => ServerInfo::new(
+      BUTTPLUG_MOCK_SERVER_NAME,
+      BUTTPLUG_CURRENT_MESSAGE_SPEC_VERSION,
+      0,
+    )
+    .into(),
+    ButtplugCurrentSpecClientMessage::RequestDeviceList(_) => {
+      DeviceList::new(devices.to_vec()).into()
+    }
+    _ => Ok::new(0).into(),
+  }
+}
@@ -76,11 +76,20 @@
 
 #[cfg(all(feature = "server", feature = "client"))]
 mod in_process_connector;
+#[cfg(feature = "client")]
+mod mock_connector;
 pub mod remote_connector;
 pub mod transport;
 
 #[cfg(all(feature = "server", feature = "client"))]
 pub use in_process_connector::ButtplugInProcessClientConnector;
+#[cfg(feature = "client")]
+pub use mock_connector::{
+  ButtplugMockConnector,
+  ButtplugMockConnectorHandle,
+  ButtplugMockReply,
+  BUTTPLUG_MOCK_SERVER_NAME,
+};
 pub use remote_connector::{
   ButtplugRemoteClientConnector,
   ButtplugRemoteConnector,
@@ -194,4 +203,10 @@ where
   /// If the connector is not currently connected, or an error happens during
   /// the send operation, this will return a [ButtplugConnectorError]
   fn send(&self, msg: OutboundMessageType) -> ButtplugConnectorResultFuture;
+  /// Returns true if messages are serialized to a server in another process.
+  /// Anything that isn't part of the Buttplug protocol, like scanning
+  /// selections, is lost on the way.
+  fn is_remote(&self) -> bool {
+    false
+  }
 }
@@ -310,4 +310,8 @@ where
       ButtplugConnectorError::ConnectorNotConnected.into()
     }
   }
+
+  fn is_remote(&self) -> bool {
+    true
+  }
 }
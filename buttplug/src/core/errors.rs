@@ -146,12 +146,18 @@ pub enum ButtplugDeviceError {
   DeviceScanningAlreadyStarted,
   /// Device scanning already stopped.
   DeviceScanningAlreadyStopped,
+  /// Device communication manager {0} not found
+  DeviceCommunicationManagerNotFound(String),
   /// Device permission error: {0}
   DevicePermissionError(String),
   /// {0}
   ProtocolAttributesNotFound(String),
   /// Protocol {0} not implemented in library
   ProtocolNotImplemented(String),
+  /// Protocol {0} has already been added
+  ProtocolAlreadyAdded(String),
+  /// Device at {0} reported unknown identifier {1}, using {2} protocol defaults. Please report this identifier so the device can be added to the device configuration.
+  UnknownDeviceIdentifier(String, String, String),
   /// {0} protocol specific error: {1}
   ProtocolSpecificError(&'static str, &'static str),
   /// {0}
@@ -293,13 +299,24 @@ where
   }
 }
 
+// Servers tell clients about devices matching protocols they can't talk to
+// with an error, so give it back its type to let clients match on it. This
+// relies on the message format of ButtplugDeviceError::ProtocolNotImplemented.
+fn device_error_from_message(message: String) -> ButtplugDeviceError {
+  match message
+    .strip_prefix("Protocol ")
+    .and_then(|msg| msg.strip_suffix(" not implemented in library"))
+  {
+    Some(protocol_name) => ButtplugDeviceError::ProtocolNotImplemented(protocol_name.to_owned()),
+    None => ButtplugDeviceError::UntypedDeserializedError(message),
+  }
+}
+
 impl From<messages::Error> for ButtplugError {
   /// Turns a Buttplug Protocol Error Message [super::messages::Error] into a [ButtplugError] type.
   fn from(error: messages::Error) -> Self {
     match error.error_code {
-      ErrorCode::ErrorDevice => {
-        ButtplugDeviceError::UntypedDeserializedError(error.error_message).into()
-      }
+      ErrorCode::ErrorDevice => device_error_from_message(error.error_message).into(),
       ErrorCode::ErrorMessage => {
         ButtplugMessageError::UntypedDeserializedError(error.error_message).into()
       }
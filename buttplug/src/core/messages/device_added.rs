@@ -22,6 +22,17 @@ pub struct DeviceAdded {
   pub device_name: String,
   #[cfg_attr(feature = "serialize-json", serde(rename = "DeviceMessages"))]
   pub device_messages: MessageAttributesMap,
+  /// Set if the device's identifier isn't in the server's configuration, in
+  /// which case the device is running on its protocol's defaults.
+  #[cfg_attr(
+    feature = "serialize-json",
+    serde(
+      rename = "UnknownDeviceIdentifier",
+      default,
+      skip_serializing_if = "Option::is_none"
+    )
+  )]
+  pub unknown_identifier: Option<String>,
 }
 
 impl DeviceAdded {
@@ -31,6 +42,7 @@ impl DeviceAdded {
       device_index,
       device_name: device_name.to_string(),
       device_messages: device_messages.clone(),
+      unknown_identifier: None,
     }
   }
 }
@@ -63,6 +75,23 @@ impl From<DeviceAdded> for DeviceAddedV1 {
   }
 }
 
+impl From<DeviceAddedV1> for DeviceAdded {
+  fn from(msg: DeviceAddedV1) -> Self {
+    let dmi = DeviceMessageInfo::from(DeviceMessageInfoV1 {
+      device_index: msg.device_index,
+      device_name: msg.device_name,
+      device_messages: msg.device_messages,
+    });
+    Self {
+      id: msg.id,
+      device_index: dmi.device_index,
+      device_name: dmi.device_name,
+      device_messages: dmi.device_messages,
+      unknown_identifier: None,
+    }
+  }
+}
+
 #[derive(Default, ButtplugMessage, Clone, Debug, PartialEq)]
 #[cfg_attr(feature = "serialize-json", derive(Serialize, Deserialize))]
 pub struct DeviceAddedV0 {
@@ -91,3 +120,20 @@ impl From<DeviceAdded> for DeviceAddedV0 {
     }
   }
 }
+
+impl From<DeviceAddedV0> for DeviceAdded {
+  fn from(msg: DeviceAddedV0) -> Self {
+    let dmi = DeviceMessageInfo::from(DeviceMessageInfoV0 {
+      device_index: msg.device_index,
+      device_name: msg.device_name,
+      device_messages: msg.device_messages,
+    });
+    Self {
+      id: msg.id,
+      device_index: dmi.device_index,
+      device_name: dmi.device_name,
+      device_messages: dmi.device_messages,
+      unknown_identifier: None,
+    }
+  }
+}
@@ -47,6 +47,19 @@ impl From<DeviceList> for DeviceListV1 {
   }
 }
 
+impl From<DeviceListV1> for DeviceList {
+  fn from(msg: DeviceListV1) -> Self {
+    Self {
+      id: msg.id,
+      devices: msg
+        .devices
+        .into_iter()
+        .map(DeviceMessageInfo::from)
+        .collect(),
+    }
+  }
+}
+
 #[derive(Default, Clone, Debug, PartialEq, ButtplugMessage)]
 #[cfg_attr(feature = "serialize-json", derive(Serialize, Deserialize))]
 pub struct DeviceListV0 {
@@ -69,3 +82,16 @@ impl From<DeviceList> for DeviceListV0 {
     }
   }
 }
+
+impl From<DeviceListV0> for DeviceList {
+  fn from(msg: DeviceListV0) -> Self {
+    Self {
+      id: msg.id,
+      devices: msg
+        .devices
+        .into_iter()
+        .map(DeviceMessageInfo::from)
+        .collect(),
+    }
+  }
+}
@@ -32,6 +32,17 @@ pub struct DeviceMessageInfo {
     serde(rename = "DeviceMessages", serialize_with = "ordered_map")
   )]
   pub device_messages: MessageAttributesMap,
+  /// Set if the device's identifier isn't in the server's configuration, in
+  /// which case the device is running on its protocol's defaults.
+  #[cfg_attr(
+    feature = "serialize-json",
+    serde(
+      rename = "UnknownDeviceIdentifier",
+      default,
+      skip_serializing_if = "Option::is_none"
+    )
+  )]
+  pub unknown_identifier: Option<String>,
 }
 
 impl From<&DeviceAdded> for DeviceMessageInfo {
@@ -40,6 +51,7 @@ impl From<&DeviceAdded> for DeviceMessageInfo {
       device_index: device_added.device_index,
       device_name: device_added.device_name.clone(),
       device_messages: device_added.device_messages.clone(),
+      unknown_identifier: device_added.unknown_identifier.clone(),
     }
   }
 }
@@ -50,6 +62,7 @@ impl From<DeviceAdded> for DeviceMessageInfo {
       device_index: device_added.device_index,
       device_name: device_added.device_name,
       device_messages: device_added.device_messages,
+      unknown_identifier: device_added.unknown_identifier,
     }
   }
 }
@@ -170,3 +183,48 @@ impl From<DeviceMessageInfoV1> for DeviceMessageInfoV0 {
     }
   }
 }
+
+impl From<DeviceMessageInfoV1> for DeviceMessageInfo {
+  fn from(device_message_info: DeviceMessageInfoV1) -> Self {
+    // V1 attributes are a subset of V2, so there's nothing to fill in. Step
+    // counts stay unknown.
+    Self {
+      device_index: device_message_info.device_index,
+      device_name: device_message_info.device_name,
+      device_messages: device_message_info.device_messages,
+      unknown_identifier: None,
+    }
+  }
+}
+
+impl From<DeviceMessageInfoV0> for DeviceMessageInfo {
+  fn from(device_message_info: DeviceMessageInfoV0) -> Self {
+    let mut device_messages: MessageAttributesMap = device_message_info
+      .device_messages
+      .iter()
+      .map(|msg_type| (*msg_type, MessageAttributes::default()))
+      .collect();
+    // V0 devices only have a single motor or stroker, so expose those as the
+    // generic commands with one feature. Clients talking to V0 servers are
+    // expected to translate the generic commands back.
+    let single_feature = MessageAttributes {
+      feature_count: Some(1),
+      ..Default::default()
+    };
+    if device_messages.contains_key(&ButtplugDeviceMessageType::SingleMotorVibrateCmd) {
+      device_messages.insert(
+        ButtplugDeviceMessageType::VibrateCmd,
+        single_feature.clone(),
+      );
+    }
+    if device_messages.contains_key(&ButtplugDeviceMessageType::FleshlightLaunchFW12Cmd) {
+      device_messages.insert(ButtplugDeviceMessageType::LinearCmd, single_feature);
+    }
+    Self {
+      device_index: device_message_info.device_index,
+      device_name: device_message_info.device_name,
+      device_messages,
+      unknown_identifier: None,
+    }
+  }
+}
@@ -397,6 +397,36 @@ impl TryFrom<ButtplugServerMessage> for ButtplugSpecV1ServerMessage {
   }
 }
 
+impl TryFrom<ButtplugSpecV1ServerMessage> for ButtplugSpecV2ServerMessage {
+  type Error = ButtplugMessageError;
+  fn try_from(msg: ButtplugSpecV1ServerMessage) -> Result<Self, ButtplugMessageError> {
+    match msg {
+      ButtplugSpecV1ServerMessage::Ok(msg) => Ok(ButtplugSpecV2ServerMessage::Ok(msg)),
+      ButtplugSpecV1ServerMessage::Error(msg) => Ok(ButtplugSpecV2ServerMessage::Error(msg)),
+      ButtplugSpecV1ServerMessage::ServerInfo(msg) => {
+        Ok(ButtplugSpecV2ServerMessage::ServerInfo(msg.into()))
+      }
+      ButtplugSpecV1ServerMessage::DeviceList(msg) => {
+        Ok(ButtplugSpecV2ServerMessage::DeviceList(msg.into()))
+      }
+      ButtplugSpecV1ServerMessage::DeviceAdded(msg) => {
+        Ok(ButtplugSpecV2ServerMessage::DeviceAdded(msg.into()))
+      }
+      ButtplugSpecV1ServerMessage::DeviceRemoved(msg) => {
+        Ok(ButtplugSpecV2ServerMessage::DeviceRemoved(msg))
+      }
+      ButtplugSpecV1ServerMessage::ScanningFinished(msg) => {
+        Ok(ButtplugSpecV2ServerMessage::ScanningFinished(msg))
+      }
+      ButtplugSpecV1ServerMessage::Log(_) => Err(ButtplugMessageError::VersionError(
+        "ButtplugSpecV1ServerMessage",
+        format!("{:?}", msg),
+        "ButtplugSpecV2ServerMessage",
+      )),
+    }
+  }
+}
+
 /// Represents all client-to-server messages in v0 of the Buttplug Spec
 #[derive(
   Debug, Clone, PartialEq, ButtplugMessage, ButtplugClientMessageType, TryFromButtplugClientMessage,
@@ -473,6 +503,36 @@ impl TryFrom<ButtplugServerMessage> for ButtplugSpecV0ServerMessage {
     }
   }
 }
+
+impl TryFrom<ButtplugSpecV0ServerMessage> for ButtplugSpecV2ServerMessage {
+  type Error = ButtplugMessageError;
+  fn try_from(msg: ButtplugSpecV0ServerMessage) -> Result<Self, ButtplugMessageError> {
+    match msg {
+      ButtplugSpecV0ServerMessage::Ok(msg) => Ok(ButtplugSpecV2ServerMessage::Ok(msg)),
+      ButtplugSpecV0ServerMessage::Error(msg) => Ok(ButtplugSpecV2ServerMessage::Error(msg)),
+      ButtplugSpecV0ServerMessage::ServerInfo(msg) => {
+        Ok(ButtplugSpecV2ServerMessage::ServerInfo(msg.into()))
+      }
+      ButtplugSpecV0ServerMessage::DeviceList(msg) => {
+        Ok(ButtplugSpecV2ServerMessage::DeviceList(msg.into()))
+      }
+      ButtplugSpecV0ServerMessage::DeviceAdded(msg) => {
+        Ok(ButtplugSpecV2ServerMessage::DeviceAdded(msg.into()))
+      }
+      ButtplugSpecV0ServerMessage::DeviceRemoved(msg) => {
+        Ok(ButtplugSpecV2ServerMessage::DeviceRemoved(msg))
+      }
+      ButtplugSpecV0ServerMessage::ScanningFinished(msg) => {
+        Ok(ButtplugSpecV2ServerMessage::ScanningFinished(msg))
+      }
+      ButtplugSpecV0ServerMessage::Log(_) => Err(ButtplugMessageError::VersionError(
+        "ButtplugSpecV0ServerMessage",
+        format!("{:?}", msg),
+        "ButtplugSpecV2ServerMessage",
+      )),
+    }
+  }
+}
 /// Represents messages that should go to the
 /// [DeviceManager][crate::server::device_manager::DeviceManager] of a
 /// [ButtplugServer](crate::server::ButtplugServer)
@@ -1,7 +1,7 @@
 use super::{ButtplugMessageSerializer, ButtplugSerializedMessage, ButtplugSerializerError};
 use crate::{
   core::{
-    errors::{ButtplugError, ButtplugHandshakeError},
+    errors::{ButtplugError, ButtplugHandshakeError, ButtplugMessageError},
     messages::{
       self,
       ButtplugClientMessage,
@@ -16,12 +16,16 @@ use crate::{
       ButtplugSpecV1ServerMessage,
       ButtplugSpecV2ClientMessage,
       ButtplugSpecV2ServerMessage,
+      FleshlightLaunchFW12Cmd,
+      SingleMotorVibrateCmd,
+      BUTTPLUG_CURRENT_MESSAGE_SPEC_VERSION,
     },
   },
+  device::protocol::fleshlight_launch_helper::get_speed,
   util::json::JSONValidator,
 };
 use serde::{Deserialize, Serialize};
-use std::convert::TryFrom;
+use std::{collections::HashMap, convert::TryFrom};
 
 static MESSAGE_JSON_SCHEMA: &str =
   include_str!("../../../../dependencies/buttplug-schema/schema/buttplug-schema.json");
@@ -138,7 +142,7 @@ impl ButtplugMessageSerializer for ButtplugServerJSONSerializer {
     // always be parsed as the latest message version, as we keep it
     // compatible across versions via serde options.
     if let Some(version) = self.message_version {
-      Ok(match version {
+      let msgs: Vec<ButtplugClientMessage> = match version {
         ButtplugMessageSpecVersion::Version0 => {
           deserialize_to_message::<ButtplugSpecV0ClientMessage>(&self.validator, msg)?
             .iter()
@@ -160,7 +164,15 @@ impl ButtplugMessageSerializer for ButtplugServerJSONSerializer {
             .map(|m| m.into())
             .collect()
         }
-      })
+      };
+      // Clients may retry the handshake with an older version if we turned
+      // down the first one, so keep following RequestServerInfo.
+      for msg in &msgs {
+        if let ButtplugClientMessage::RequestServerInfo(rsi) = msg {
+          self.message_version = Some(rsi.message_version);
+        }
+      }
+      Ok(msgs)
     } else {
       let msg_union = deserialize_to_message::<ButtplugSpecV2ClientMessage>(&self.validator, msg)?;
       if let ButtplugSpecV2ClientMessage::RequestServerInfo(rsi) = &msg_union[0] {
@@ -198,18 +210,93 @@ impl ButtplugMessageSerializer for ButtplugServerJSONSerializer {
   }
 }
 
+/// Converts messages from an older spec version into the current spec.
+fn upgrade_server_messages<T>(
+  msgs: Vec<T>,
+) -> Result<Vec<ButtplugCurrentSpecServerMessage>, ButtplugSerializerError>
+where
+  ButtplugCurrentSpecServerMessage: TryFrom<T, Error = ButtplugMessageError>,
+{
+  msgs
+    .into_iter()
+    .map(ButtplugCurrentSpecServerMessage::try_from)
+    .collect::<Result<Vec<_>, _>>()
+    .map_err(|e| ButtplugSerializerError::JsonSerializerError(format!("{:?}", e)))
+}
+
 pub struct ButtplugClientJSONSerializer {
+  /// Spec version to speak to the server with. Follows the version in the
+  /// last RequestServerInfo we sent, or an older version if the server
+  /// reports one in ServerInfo.
+  message_version: ButtplugMessageSpecVersion,
+  /// Last position sent to each linear device, used to work out stroke
+  /// speeds when translating LinearCmd to FleshlightLaunchFW12Cmd for v0
+  /// servers.
+  linear_positions: HashMap<u32, f64>,
   validator: JSONValidator,
 }
 
 impl Default for ButtplugClientJSONSerializer {
   fn default() -> Self {
     Self {
+      message_version: BUTTPLUG_CURRENT_MESSAGE_SPEC_VERSION,
+      linear_positions: HashMap::new(),
       validator: create_message_validator(),
     }
   }
 }
 
+impl ButtplugClientJSONSerializer {
+  /// Turns a current spec message into JSON for the spec version the server
+  /// speaks. Messages that have no equivalent in that version are sent as is,
+  /// and left for the server to reject.
+  fn message_to_version(&mut self, msg: ButtplugCurrentSpecClientMessage) -> String {
+    let value = match self.message_version {
+      ButtplugMessageSpecVersion::Version0 => {
+        let legacy_msg: ButtplugClientMessage = match &msg {
+          ButtplugCurrentSpecClientMessage::VibrateCmd(cmd) => {
+            // V0 only knows about single motor devices, so use the fastest
+            // speed we were given.
+            let speed = cmd.speeds.iter().map(|s| s.speed).fold(0f64, f64::max);
+            let mut legacy_cmd = SingleMotorVibrateCmd::new(cmd.device_index, speed);
+            legacy_cmd.set_id(cmd.get_id());
+            legacy_cmd.into()
+          }
+          ButtplugCurrentSpecClientMessage::LinearCmd(cmd) if !cmd.vectors.is_empty() => {
+            let vector = &cmd.vectors[0];
+            let previous_position = self
+              .linear_positions
+              .insert(cmd.device_index, vector.position)
+              .unwrap_or(0f64);
+            let distance = (previous_position - vector.position).abs();
+            let mut legacy_cmd = FleshlightLaunchFW12Cmd::new(
+              cmd.device_index,
+              (vector.position * 99f64) as u8,
+              (get_speed(distance, vector.duration) * 99f64).min(99f64) as u8,
+            );
+            legacy_cmd.set_id(cmd.get_id());
+            legacy_cmd.into()
+          }
+          _ => msg.clone().into(),
+        };
+        match ButtplugSpecV0ClientMessage::try_from(legacy_msg) {
+          Ok(msgv0) => serde_json::to_string(&msgv0),
+          Err(_) => serde_json::to_string(&msg),
+        }
+      }
+      ButtplugMessageSpecVersion::Version1 => {
+        match ButtplugSpecV1ClientMessage::try_from(ButtplugClientMessage::from(msg.clone())) {
+          Ok(msgv1) => serde_json::to_string(&msgv1),
+          Err(_) => serde_json::to_string(&msg),
+        }
+      }
+      ButtplugMessageSpecVersion::Version2 => serde_json::to_string(&msg),
+    };
+    // Message structs always serialize.
+    value.unwrap()
+  }
+}
+
 unsafe impl Sync for ButtplugClientJSONSerializer {
 }
 unsafe impl Send for ButtplugClientJSONSerializer {
@@ -223,15 +310,58 @@ impl ButtplugMessageSerializer for ButtplugClientJSONSerializer {
     &mut self,
     msg: ButtplugSerializedMessage,
   ) -> Result<Vec<ButtplugCurrentSpecServerMessage>, ButtplugSerializerError> {
-    if let ButtplugSerializedMessage::Text(text_msg) = msg {
-      deserialize_to_message::<Self::Inbound>(&self.validator, text_msg)
+    let text_msg = if let ButtplugSerializedMessage::Text(text_msg) = msg {
+      text_msg
     } else {
-      Err(ButtplugSerializerError::BinaryDeserializationError)
+      return Err(ButtplugSerializerError::BinaryDeserializationError);
+    };
+    let msgs = match self.message_version {
+      ButtplugMessageSpecVersion::Version0 => {
+        upgrade_server_messages(deserialize_to_message::<ButtplugSpecV0ServerMessage>(
+          &self.validator,
+          text_msg,
+        )?)?
+      }
+      ButtplugMessageSpecVersion::Version1 => {
+        upgrade_server_messages(deserialize_to_message::<ButtplugSpecV1ServerMessage>(
+          &self.validator,
+          text_msg,
+        )?)?
+      }
+      ButtplugMessageSpecVersion::Version2 => {
+        deserialize_to_message::<ButtplugCurrentSpecServerMessage>(&self.validator, text_msg)?
+      }
+    };
+    // Older servers answer a newer RequestServerInfo with their own version,
+    // so switch down to whatever they told us.
+    for msg in &msgs {
+      if let ButtplugCurrentSpecServerMessage::ServerInfo(server_info) = msg {
+        if server_info.message_version < self.message_version {
+          info!(
+            "Server only supports message spec version {}, downgrading",
+            server_info.message_version
+          );
+          self.message_version = server_info.message_version;
+        }
+      }
     }
+    Ok(msgs)
   }
 
-  fn serialize(&mut self, msg: Vec<ButtplugCurrentSpecClientMessage>) -> ButtplugSerializedMessage {
-    ButtplugSerializedMessage::Text(vec_to_protocol_json(msg))
+  fn serialize(
+    &mut self,
+    msgs: Vec<ButtplugCurrentSpecClientMessage>,
+  ) -> ButtplugSerializedMessage {
+    let mut version_msgs = vec![];
+    for msg in msgs {
+      if let ButtplugCurrentSpecClientMessage::RequestServerInfo(rsi) = &msg {
+        self.message_version = rsi.message_version;
+      }
+      version_msgs.push(self.message_to_version(msg));
+    }
+    // Messages may be from different spec versions, so put the array
+    // together ourselves.
+    ButtplugSerializedMessage::Text(format!("[{}]", version_msgs.join(",")))
   }
 }
 
@@ -311,4 +441,98 @@ mod test {
       }
     }
   }
+
+  #[test]
+  fn test_client_version0_translation() {
+    let mut serializer = ButtplugClientJSONSerializer::default();
+    let _ = serializer.serialize(vec![RequestServerInfo::new(
+      "test client",
+      ButtplugMessageSpecVersion::Version0,
+    )
+    .into()]);
+    let msgs = serializer
+      .deserialize(ButtplugSerializedMessage::Text(
+        r#"[{"DeviceAdded":{"Id":0,"DeviceIndex":0,"DeviceName":"Kiiroo Keon","DeviceMessages":["FleshlightLaunchFW12Cmd","StopDeviceCmd"]}}]"#
+          .to_owned(),
+      ))
+      .unwrap();
+    if let ButtplugCurrentSpecServerMessage::DeviceAdded(da) = &msgs[0] {
+      assert_eq!(
+        da.device_messages[&messages::ButtplugDeviceMessageType::LinearCmd].feature_count,
+        Some(1)
+      );
+    } else {
+      panic!("Expected DeviceAdded, got {:?}", msgs[0]);
+    }
+    assert_eq!(
+      serializer.serialize(vec![messages::VibrateCmd::new(
+        0,
+        vec![
+          messages::VibrateSubcommand::new(0, 0.25),
+          messages::VibrateSubcommand::new(1, 0.5)
+        ]
+      )
+      .into()]),
+      ButtplugSerializedMessage::Text(
+        r#"[{"SingleMotorVibrateCmd":{"Id":1,"DeviceIndex":0,"Speed":0.5}}]"#.to_owned()
+      )
+    );
+    assert_eq!(
+      serializer.serialize(vec![messages::LinearCmd::new(
+        0,
+        vec![messages::VectorSubcommand::new(0, 500, 0.5)]
+      )
+      .into()]),
+      ButtplugSerializedMessage::Text(
+        r#"[{"FleshlightLaunchFW12Cmd":{"Id":1,"DeviceIndex":0,"Position":49,"Speed":19}}]"#
+          .to_owned()
+      )
+    );
+  }
+
+  #[test]
+  fn test_client_follows_server_version() {
+    let mut serializer = ButtplugClientJSONSerializer::default();
+    let _ = serializer.serialize(vec![RequestServerInfo::new(
+      "test client",
+      BUTTPLUG_CURRENT_MESSAGE_SPEC_VERSION,
+    )
+    .into()]);
+    serializer
+      .deserialize(ButtplugSerializedMessage::Text(
+        r#"[{"ServerInfo":{"Id":1,"MajorVersion":0,"MinorVersion":0,"BuildVersion":0,"MessageVersion":1,"MaxPingTime":0,"ServerName":"Old Server"}}]"#
+          .to_owned(),
+      ))
+      .unwrap();
+    assert_eq!(
+      serializer.message_version,
+      ButtplugMessageSpecVersion::Version1
+    );
+  }
+
+  #[test]
+  fn test_unknown_device_identifier_round_trip() {
+    let mut server_serializer = ButtplugServerJSONSerializer::default();
+    server_serializer
+      .deserialize(ButtplugSerializedMessage::Text(
+        r#"[{"RequestServerInfo":{"Id":1,"ClientName":"Test Client","MessageVersion":2}}]"#
+          .to_owned(),
+      ))
+      .unwrap();
+    let mut device_added = messages::DeviceAdded::new(0, "Generic Device", &HashMap::new());
+    device_added.unknown_identifier = Some("W".to_owned());
+    let serialized = server_serializer.serialize(vec![device_added.into()]);
+    let mut client_serializer = ButtplugClientJSONSerializer::default();
+    let _ = client_serializer.serialize(vec![RequestServerInfo::new(
+      "test client",
+      BUTTPLUG_CURRENT_MESSAGE_SPEC_VERSION,
+    )
+    .into()]);
+    let msgs = client_serializer.deserialize(serialized).unwrap();
+    if let ButtplugCurrentSpecServerMessage::DeviceAdded(da) = &msgs[0] {
+      assert_eq!(da.unknown_identifier.as_deref(), Some("W"));
+    } else {
+      panic!("Expected DeviceAdded, got {:?}", msgs[0]);
+    }
+  }
 }
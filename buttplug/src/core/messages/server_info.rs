@@ -80,3 +80,11 @@ impl From<ServerInfo> for ServerInfoV0 {
     out_msg
   }
 }
+
+impl From<ServerInfoV0> for ServerInfo {
+  fn from(msg: ServerInfoV0) -> Self {
+    let mut out_msg = Self::new(&msg.server_name, msg.message_version, msg.max_ping_time);
+    out_msg.set_id(msg.get_id());
+    out_msg
+  }
+}
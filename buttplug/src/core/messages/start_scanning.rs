@@ -14,10 +14,46 @@ use serde::{Deserialize, Serialize};
 pub struct StartScanning {
   #[cfg_attr(feature = "serialize-json", serde(rename = "Id"))]
   pub(super) id: u32,
+  // The scanning selection isn't part of the Buttplug protocol, so it's never
+  // serialized, and only reaches servers running in the same process.
+  #[cfg_attr(feature = "serialize-json", serde(skip))]
+  comm_managers: Vec<String>,
+  #[cfg_attr(feature = "serialize-json", serde(skip))]
+  protocols: Vec<String>,
+}
+
+impl StartScanning {
+  /// Creates a StartScanning message that only starts the named device
+  /// communication managers, and only connects to devices matching the named
+  /// protocols. Empty lists select everything.
+  ///
+  /// The selection is not part of the Buttplug protocol, so it is only used by
+  /// servers running in the same process. Remote servers will treat this the
+  /// same as [StartScanning::default()], so clients refuse to send a selection
+  /// through a remote connector.
+  pub fn new_with_selection(comm_managers: &[&str], protocols: &[&str]) -> Self {
+    Self {
+      id: 1,
+      comm_managers: comm_managers.iter().map(|name| name.to_string()).collect(),
+      protocols: protocols.iter().map(|name| name.to_string()).collect(),
+    }
+  }
+
+  pub fn comm_managers(&self) -> &[String] {
+    &self.comm_managers
+  }
+
+  pub fn protocols(&self) -> &[String] {
+    &self.protocols
+  }
 }
 
 impl Default for StartScanning {
   fn default() -> Self {
-    Self { id: 1 }
+    Self {
+      id: 1,
+      comm_managers: vec![],
+      protocols: vec![],
+    }
   }
 }
@@ -12,12 +12,18 @@ use crate::{
     errors::{ButtplugDeviceError, ButtplugError},
     messages::{ButtplugDeviceMessageType, MessageAttributes, MessageAttributesMap},
   },
-  device::Endpoint,
-  util::json::JSONValidator,
+  device::{
+    filter::{DeviceFilter, DeviceFilterList},
+    protocol::{ButtplugProtocol, ProtocolTypes, TryCreateProtocolFunc},
+    Endpoint,
+  },
+  util::{json::JSONValidator, yaml::YamlDocument},
 };
-use serde::Deserialize;
+use dashmap::DashMap;
+use serde::{de::DeserializeOwned, Deserialize};
 use std::collections::{HashMap, HashSet};
-use std::mem;
+use std::convert::TryFrom;
+use std::sync::RwLock;
 use uuid::Uuid;
 
 static DEVICE_CONFIGURATION_JSON: &str =
@@ -26,6 +32,9 @@ static DEVICE_CONFIGURATION_JSON_SCHEMA: &str =
   include_str!("../../dependencies/buttplug-device-config/buttplug-device-config-schema.json");
 static USER_DEVICE_CONFIGURATION_JSON_SCHEMA: &str =
   include_str!("../../dependencies/buttplug-device-config/buttplug-user-device-config-schema.json");
+/// Name used for devices whose identifier isn't in the configuration, if the
+/// protocol defaults don't have a name.
+pub const GENERIC_DEVICE_NAME: &str = "Generic Device";
 
 // Note: There's a ton of extra structs in here just to deserialize the json
 // file. Just leave them and build extras (for instance,
@@ -36,6 +45,9 @@ static USER_DEVICE_CONFIGURATION_JSON_SCHEMA: &str =
 #[derive(Deserialize, Debug, Clone)]
 pub struct BluetoothLESpecifier {
   pub names: HashSet<String>,
+  // User configurations can add names to an existing protocol without
+  // specifying services.
+  #[serde(default)]
   pub services: HashMap<Uuid, HashMap<Endpoint, Uuid>>,
 }
 
@@ -104,6 +116,15 @@ pub struct HIDSpecifier {
   product_id: u16,
 }
 
+impl HIDSpecifier {
+  pub fn new(vendor_id: u16, product_id: u16) -> Self {
+    Self {
+      vendor_id,
+      product_id,
+    }
+  }
+}
+
 #[derive(Deserialize, Debug, Clone, Default)]
 pub struct SerialSpecifier {
   #[serde(rename = "baud-rate")]
@@ -147,11 +168,52 @@ pub enum DeviceSpecifier {
   XInput(XInputSpecifier),
 }
 
+/// Placeholders that can be used in a [ProtocolTemplate] byte template.
+/// Checksums are calculated over all bytes that come before them in the
+/// template.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum TemplatePlaceholder {
+  /// Step value for the feature being set.
+  Step,
+  /// Index of the feature being set.
+  Index,
+  /// Wrapping sum of the preceding bytes.
+  Sum,
+  /// XOR of the preceding bytes.
+  Xor,
+  /// CRC-8 (polynomial 0x07, initial value 0x00) of the preceding bytes.
+  Crc8,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(untagged)]
+pub enum TemplateByte {
+  Value(u8),
+  Placeholder(TemplatePlaceholder),
+}
+
+/// Byte templates used by the template protocol, for devices that can be
+/// described completely in the configuration file.
+#[derive(Deserialize, Debug, Clone)]
+pub struct ProtocolTemplate {
+  /// Endpoint commands are written to. Defaults to tx.
+  pub endpoint: Option<Endpoint>,
+  /// Byte arrays written, in order, when the device is connected.
+  #[serde(default)]
+  pub init: Vec<Vec<u8>>,
+  /// Templates for VibrateCmd, one per feature. If only one template is
+  /// given, it is used for all features.
+  #[serde(default)]
+  pub vibrate: Vec<Vec<TemplateByte>>,
+}
+
 #[derive(Deserialize, Debug, Clone)]
 pub struct ProtocolAttributes {
   identifier: Option<Vec<String>>,
   name: Option<HashMap<String, String>>,
   messages: Option<MessageAttributesMap>,
+  template: Option<ProtocolTemplate>,
 }
 
 #[derive(Deserialize, Debug, Clone)]
@@ -168,11 +230,191 @@ pub struct ProtocolDefinition {
   pub configurations: Vec<ProtocolAttributes>,
 }
 
+/// Protocol definition from a user device configuration file. User
+/// configuration is merged on top of the base configuration, with the user
+/// configuration taking precedence:
+///
+/// - If `disabled` is true, the protocol is removed entirely and all other
+///   fields are ignored.
+/// - For protocols that don't exist in the base configuration (i.e. ones added
+///   via [DeviceConfigurationManager::add_protocol] or using the template
+///   protocol), this is a full protocol definition, and needs at least one
+///   configuration.
+/// - For protocols that do exist, BLE names and services, USB/HID IDs and
+///   serial ports are added to those in the base configuration, and XInput is
+///   replaced. Default and per-identifier names and message attributes are
+///   overridden by message type (and language, for names). Configurations for
+///   identifiers that don't exist in the base configuration are added.
 #[derive(Deserialize, Debug, Clone)]
 pub struct UserProtocolDefinition {
-  // Right now, we only allow users to specify serial ports through this
-  // interface. It will contain more additions in the future.
+  #[serde(default)]
+  pub disabled: bool,
+  pub usb: Option<Vec<USBSpecifier>>,
+  pub btle: Option<BluetoothLESpecifier>,
   pub serial: Option<Vec<SerialSpecifier>>,
+  pub hid: Option<Vec<HIDSpecifier>>,
+  pub xinput: Option<XInputSpecifier>,
+  pub defaults: Option<ProtocolAttributes>,
+  pub configurations: Option<Vec<ProtocolAttributes>>,
+}
+
+fn user_config_error(protocol: &str, msg: &str) -> ButtplugDeviceError {
+  ButtplugDeviceError::DeviceConfigurationFileError(format!(
+    "User configuration for protocol {}: {}",
+    protocol, msg
+  ))
+}
+
+fn extend_specifiers<T>(ours: &mut Option<Vec<T>>, other: Option<Vec<T>>) {
+  if let Some(other_specifiers) = other {
+    ours.get_or_insert_with(Vec::new).extend(other_specifiers);
+  }
+}
+
+impl ProtocolAttributes {
+  // Overrides our names and message attributes with anything set in other.
+  // Identifiers are left alone.
+  fn merge(&mut self, other: ProtocolAttributes) {
+    if let Some(other_name) = other.name {
+      self
+        .name
+        .get_or_insert_with(HashMap::new)
+        .extend(other_name);
+    }
+    if let Some(other_messages) = other.messages {
+      self
+        .messages
+        .get_or_insert_with(MessageAttributesMap::new)
+        .extend(other_messages);
+    }
+    if other.template.is_some() {
+      self.template = other.template;
+    }
+  }
+}
+
+impl ProtocolDefinition {
+  fn new_from_user_definition(
+    protocol: &str,
+    other: UserProtocolDefinition,
+  ) -> Result<Self, ButtplugDeviceError> {
+    // New protocols need at least one configuration, otherwise there's
+    // nothing to name the device or describe its messages with.
+    let configurations = other
+      .configurations
+      .ok_or_else(|| user_config_error(protocol, "new protocols require configurations"))?;
+    if configurations.iter().any(|config| config.name.is_none()) {
+      return Err(user_config_error(
+        protocol,
+        "configurations for new protocols require names",
+      ));
+    }
+    if let Some(btle) = &other.btle {
+      if btle.services.is_empty() {
+        return Err(user_config_error(
+          protocol,
+          "new bluetooth definitions require services",
+        ));
+      }
+    }
+    Ok(Self {
+      usb: other.usb,
+      btle: other.btle,
+      serial: other.serial,
+      hid: other.hid,
+      xinput: other.xinput,
+      defaults: other.defaults,
+      configurations,
+    })
+  }
+
+  fn merge_user_definition(
+    &mut self,
+    protocol: &str,
+    other: UserProtocolDefinition,
+  ) -> Result<(), ButtplugDeviceError> {
+    if let Some(other_btle) = other.btle {
+      match &mut self.btle {
+        Some(btle) => {
+          btle.names.extend(other_btle.names);
+          for (service, endpoints) in other_btle.services {
+            btle
+              .services
+              .entry(service)
+              .or_insert_with(HashMap::new)
+              .extend(endpoints);
+          }
+        }
+        None => {
+          if other_btle.services.is_empty() {
+            return Err(user_config_error(
+              protocol,
+              "new bluetooth definitions require services",
+            ));
+          }
+          self.btle = Some(other_btle);
+        }
+      }
+    }
+    extend_specifiers(&mut self.usb, other.usb);
+    extend_specifiers(&mut self.hid, other.hid);
+    extend_specifiers(&mut self.serial, other.serial);
+    if other.xinput.is_some() {
+      self.xinput = other.xinput;
+    }
+    if let Some(other_defaults) = other.defaults {
+      match &mut self.defaults {
+        Some(defaults) => defaults.merge(other_defaults),
+        None => self.defaults = Some(other_defaults),
+      }
+    }
+    for other_config in other.configurations.unwrap_or_default() {
+      let identifiers = other_config
+        .identifier
+        .clone()
+        .ok_or_else(|| user_config_error(protocol, "configurations require identifiers"))?;
+      for identifier in identifiers {
+        // Identifiers can share a configuration in the base file, so split
+        // out the one we're overriding before changing anything.
+        let existing = self.configurations.iter().position(|config| {
+          config
+            .identifier
+            .as_ref()
+            .map_or(false, |ids| ids.contains(&identifier))
+        });
+        let mut config = match existing {
+          Some(index) => {
+            let ids = self.configurations[index].identifier.as_mut().unwrap();
+            if ids.len() == 1 {
+              self.configurations.remove(index)
+            } else {
+              ids.retain(|id| *id != identifier);
+              let mut config = self.configurations[index].clone();
+              config.identifier = Some(vec![identifier.clone()]);
+              config
+            }
+          }
+          None => {
+            if other_config.name.is_none() {
+              return Err(user_config_error(
+                protocol,
+                &format!("new identifier {} requires a name", identifier),
+              ));
+            }
+            ProtocolAttributes {
+              identifier: Some(vec![identifier.clone()]),
+              name: None,
+              messages: None,
+              template: None,
+            }
+          }
+        };
+        config.merge(other_config.clone());
+        self.configurations.push(config);
+      }
+    }
+    Ok(())
+  }
 }
 
 fn option_some_eq<T>(a: &Option<T>, b: &T) -> bool
@@ -205,27 +447,45 @@ impl PartialEq<DeviceSpecifier> for ProtocolDefinition {
 #[derive(Deserialize, Debug)]
 pub struct ProtocolConfiguration {
   pub(self) protocols: HashMap<String, ProtocolDefinition>,
+  // Allow and deny lists from the user configuration. Kept with the protocols
+  // so both are replaced together on reload.
+  #[serde(skip)]
+  pub(self) user_device_filter: DeviceFilter,
 }
 
 #[derive(Deserialize, Debug)]
 pub struct UserProtocolConfiguration {
+  #[serde(default)]
   pub protocols: HashMap<String, UserProtocolDefinition>,
+  #[serde(default)]
+  pub allow: DeviceFilterList,
+  #[serde(default)]
+  pub deny: DeviceFilterList,
 }
 
 impl ProtocolConfiguration {
-  pub fn merge_user_config(&mut self, other: UserProtocolConfiguration) {
-    // For now, we're only merging serial info in.
+  pub fn merge_user_config(
+    &mut self,
+    other: UserProtocolConfiguration,
+  ) -> Result<(), ButtplugDeviceError> {
+    self.user_device_filter.allow.extend(other.allow);
+    self.user_device_filter.deny.extend(other.deny);
     for (protocol, conf) in other.protocols {
-      if self.protocols.contains_key(&protocol) {
-        let our_serial_conf_option = &mut self.protocols.get_mut(&protocol).unwrap().serial;
-        let mut other_serial_conf = conf.serial;
-        if let Some(ref mut our_serial_config) = our_serial_conf_option {
-          our_serial_config.extend(other_serial_conf.unwrap());
-        } else {
-          mem::swap(our_serial_conf_option, &mut other_serial_conf);
+      if conf.disabled {
+        info!("Protocol {} disabled by user configuration", protocol);
+        self.protocols.remove(&protocol);
+        continue;
+      }
+      match self.protocols.get_mut(&protocol) {
+        Some(definition) => definition.merge_user_definition(&protocol, conf)?,
+        None => {
+          info!("Adding user defined protocol {}", protocol);
+          let definition = ProtocolDefinition::new_from_user_definition(&protocol, conf)?;
+          self.protocols.insert(protocol, definition);
         }
       }
     }
+    Ok(())
   }
 }
 
@@ -249,11 +509,36 @@ impl DeviceProtocolConfiguration {
     }
   }
 
+  /// Returns the byte templates for a device, preferring those set for its
+  /// identifier over those in the protocol defaults.
+  pub fn get_template(&self, identifier: &str) -> Option<ProtocolTemplate> {
+    self
+      .configurations
+      .iter()
+      .find(|attrs| {
+        attrs
+          .identifier
+          .as_ref()
+          .map_or(false, |ids| ids.contains(&identifier.to_owned()))
+      })
+      .and_then(|attrs| attrs.template.clone())
+      .or_else(|| {
+        self
+          .defaults
+          .as_ref()
+          .and_then(|attrs| attrs.template.clone())
+      })
+  }
+
+  /// Returns the names and message attributes for a device identifier. If the
+  /// identifier isn't in the configuration (usually a new model of a device we
+  /// already support), the protocol defaults are used with a generic name,
+  /// and the returned flag is set.
   pub fn get_attributes(
     &self,
     identifier: &str,
     endpoints: &[Endpoint],
-  ) -> Result<(HashMap<String, String>, MessageAttributesMap), ButtplugError> {
+  ) -> Result<(HashMap<String, String>, MessageAttributesMap, bool), ButtplugError> {
     let mut attributes = MessageAttributesMap::new();
     // If we find defaults, set those up first.
     if let Some(ref attrs) = self.defaults {
@@ -261,58 +546,81 @@ impl DeviceProtocolConfiguration {
         attributes = msg_attrs.clone();
       }
     }
-    match self.configurations.iter().find(|attrs| {
+    let (names, used_defaults) = match self.configurations.iter().find(|attrs| {
       attrs
         .identifier
         .as_ref()
-        .unwrap()
-        .contains(&identifier.to_owned())
+        .map_or(false, |ids| ids.contains(&identifier.to_owned()))
     }) {
       Some(ref attrs) => {
         if let Some(ref msg_attrs) = attrs.messages {
           attributes.extend(msg_attrs.clone());
         }
-        attributes
-          .entry(ButtplugDeviceMessageType::StopDeviceCmd)
-          .or_insert_with(
-            MessageAttributes::default
-          );
-        if self.allow_raw_messages {
-          let mut endpoint_attributes = MessageAttributes::default();
-          endpoint_attributes.endpoints = Some(endpoints.to_owned());
-          attributes.insert(
-            ButtplugDeviceMessageType::RawReadCmd,
-            endpoint_attributes.clone(),
-          );
-          attributes.insert(
-            ButtplugDeviceMessageType::RawWriteCmd,
-            endpoint_attributes.clone(),
-          );
-          attributes.insert(
-            ButtplugDeviceMessageType::RawSubscribeCmd,
-            endpoint_attributes.clone(),
-          );
-          attributes.insert(
-            ButtplugDeviceMessageType::RawUnsubscribeCmd,
-            endpoint_attributes,
+        (attrs.name.as_ref().unwrap().clone(), false)
+      }
+      None => match &self.defaults {
+        Some(defaults) if defaults.messages.is_some() => {
+          warn!(
+            "Cannot find identifier {} in protocol, using protocol defaults.",
+            identifier
           );
+          let names = defaults.name.clone().unwrap_or_else(|| {
+            let mut names = HashMap::new();
+            names.insert("en-us".to_owned(), GENERIC_DEVICE_NAME.to_owned());
+            names
+          });
+          (names, true)
         }
-        Ok((attrs.name.as_ref().unwrap().clone(), attributes))
-      }
-      None => Err(
-        ButtplugDeviceError::ProtocolAttributesNotFound(format!(
-          "Cannot find identifier {} in protocol.",
-          identifier
-        ))
-        .into(),
-      ),
+        _ => {
+          return Err(
+            ButtplugDeviceError::ProtocolAttributesNotFound(format!(
+              "Cannot find identifier {} in protocol.",
+              identifier
+            ))
+            .into(),
+          )
+        }
+      },
+    };
+    attributes
+      .entry(ButtplugDeviceMessageType::StopDeviceCmd)
+      .or_default();
+    if self.allow_raw_messages {
+      let mut endpoint_attributes = MessageAttributes::default();
+      endpoint_attributes.endpoints = Some(endpoints.to_owned());
+      attributes.insert(
+        ButtplugDeviceMessageType::RawReadCmd,
+        endpoint_attributes.clone(),
+      );
+      attributes.insert(
+        ButtplugDeviceMessageType::RawWriteCmd,
+        endpoint_attributes.clone(),
+      );
+      attributes.insert(
+        ButtplugDeviceMessageType::RawSubscribeCmd,
+        endpoint_attributes.clone(),
+      );
+      attributes.insert(
+        ButtplugDeviceMessageType::RawUnsubscribeCmd,
+        endpoint_attributes,
+      );
     }
+    Ok((names, attributes, used_defaults))
   }
 }
 
 pub struct DeviceConfigurationManager {
   allow_raw_messages: bool,
-  pub(self) config: ProtocolConfiguration,
+  // Replaced as a whole when configuration is reloaded.
+  pub(self) config: RwLock<ProtocolConfiguration>,
+  // Protocols added at runtime, keyed by the protocol name used in the
+  // configuration. These are checked before the protocols built into the
+  // library.
+  protocol_creators: DashMap<String, TryCreateProtocolFunc>,
+  // Set through set_device_filter, either from server options or at runtime.
+  // Checked for every device found, before connecting to it, along with the
+  // filter from the user configuration. Not affected by reloads.
+  device_filter: RwLock<DeviceFilter>,
 }
 
 unsafe impl Send for DeviceConfigurationManager {
@@ -324,74 +632,216 @@ impl Default for DeviceConfigurationManager {
   fn default() -> Self {
     // Unwrap allowed here because we assume our built in device config will
     // always work. System won't pass tests or possibly even build otherwise.
-    Self::new_with_options(false, &None, &None).unwrap()
+    Self::new_with_options(false, &None, &None, &HashMap::new()).unwrap()
+  }
+}
+
+fn configuration_file_error<T: std::fmt::Display>(err: T) -> ButtplugDeviceError {
+  ButtplugDeviceError::DeviceConfigurationFileError(format!("{}", err))
+}
+
+// Configuration files can be either JSON or YAML. Both are validated against
+// the same schema, YAML after being converted to JSON. Our configuration files
+// are always objects, so anything that doesn't start with a brace is treated
+// as YAML.
+fn parse_configuration<T>(config_str: &str, schema: &str) -> Result<T, ButtplugDeviceError>
+where
+  T: DeserializeOwned,
+{
+  let validator = JSONValidator::new(schema);
+  if config_str.trim_start().starts_with('{') {
+    validator
+      .validate(config_str)
+      .map_err(configuration_file_error)?;
+    return serde_json::from_str(config_str).map_err(configuration_file_error);
+  }
+  let document = YamlDocument::parse(config_str).map_err(configuration_file_error)?;
+  let errors = validator.validation_errors(document.value());
+  if !errors.is_empty() {
+    let descriptions: Vec<String> = errors
+      .iter()
+      .map(|(path, description)| match document.position(path) {
+        Some(position) => format!("{} ({}) at {}", description, path, position),
+        None => format!("{} ({})", description, path),
+      })
+      .collect();
+    return Err(configuration_file_error(descriptions.join(", ")));
+  }
+  serde_json::from_value(document.into_value()).map_err(configuration_file_error)
+}
+
+// Loads and validates the base configuration, then merges the user
+// configuration on top of it.
+fn load_configuration(
+  external_config: &Option<String>,
+  user_config: &Option<String>,
+) -> Result<ProtocolConfiguration, ButtplugDeviceError> {
+  let config_str = external_config
+    .as_deref()
+    .unwrap_or(DEVICE_CONFIGURATION_JSON);
+  let mut config: ProtocolConfiguration =
+    parse_configuration(config_str, DEVICE_CONFIGURATION_JSON_SCHEMA)?;
+
+  if let Some(user_config_str) = user_config {
+    let user_cfg: UserProtocolConfiguration =
+      parse_configuration(user_config_str, USER_DEVICE_CONFIGURATION_JSON_SCHEMA)?;
+    config.merge_user_config(user_cfg)?;
   }
+  Ok(config)
+}
+
+// Finds the protocol a device belongs to.
+fn find_protocol<'a>(
+  config: &'a ProtocolConfiguration,
+  specifier: &DeviceSpecifier,
+) -> Option<(&'a String, &'a ProtocolDefinition)> {
+  // Exact BLE name matches take precedence over wildcard matches, so that
+  // names added by user configuration can claim devices that would otherwise
+  // match another protocol's wildcard.
+  if let DeviceSpecifier::BluetoothLE(btle_specifier) = specifier {
+    let exact_match = config.protocols.iter().find(|(_, def)| {
+      def.btle.as_ref().map_or(false, |btle| {
+        btle.names.intersection(&btle_specifier.names).count() > 0
+      })
+    });
+    if exact_match.is_some() {
+      return exact_match;
+    }
+  }
+  config.protocols.iter().find(|(_, def)| *def == specifier)
 }
 
 impl DeviceConfigurationManager {
+  /// Creates a configuration manager from the base configuration (the one
+  /// built into the library if `external_config` is None) and an optional
+  /// user configuration. Either can be given as JSON or YAML.
+  /// `protocols` are added as if by [DeviceConfigurationManager::add_protocol],
+  /// before checking the configuration for unimplemented protocols.
   pub fn new_with_options(
     allow_raw_messages: bool,
     external_config: &Option<String>,
     user_config: &Option<String>,
+    protocols: &HashMap<String, TryCreateProtocolFunc>,
   ) -> Result<Self, ButtplugDeviceError> {
-    // TODO Handling references incorrectly here.
-    let config_str = if let Some(cfg) = external_config {
-      cfg
-    } else {
-      DEVICE_CONFIGURATION_JSON
+    let config = load_configuration(external_config, user_config)?;
+    let manager = DeviceConfigurationManager {
+      allow_raw_messages,
+      config: RwLock::new(config),
+      protocol_creators: protocols
+        .iter()
+        .map(|(name, creator)| (name.clone(), *creator))
+        .collect(),
+      device_filter: RwLock::new(DeviceFilter::default()),
     };
+    manager.warn_unimplemented_protocols();
+    Ok(manager)
+  }
 
-    let config_validator = JSONValidator::new(DEVICE_CONFIGURATION_JSON_SCHEMA);
-    let mut config: ProtocolConfiguration = match config_validator.validate(&config_str) {
-      Ok(_) => match serde_json::from_str(&config_str) {
-        Ok(config) => config,
-        Err(err) => {
-          return Err(ButtplugDeviceError::DeviceConfigurationFileError(format!(
-            "{}",
-            err
-          )))
-        }
-      },
-      Err(err) => {
-        return Err(ButtplugDeviceError::DeviceConfigurationFileError(format!(
-          "{}",
-          err
-        )))
-      }
-    };
+  /// Replaces the device configuration with a newly loaded base and user
+  /// configuration. Both are fully validated before anything is replaced, so
+  /// on error the current configuration is left as is. Only affects devices
+  /// found after the reload.
+  ///
+  /// Allow and deny lists from the new user configuration replace the old
+  /// ones at the same time as the protocols. The filter set via
+  /// [DeviceConfigurationManager::set_device_filter] is kept.
+  pub fn reload(
+    &self,
+    external_config: &Option<String>,
+    user_config: &Option<String>,
+  ) -> Result<(), ButtplugDeviceError> {
+    let config = load_configuration(external_config, user_config)?;
+    *self.config.write().unwrap() = config;
+    info!("Device configuration reloaded.");
+    self.warn_unimplemented_protocols();
+    Ok(())
+  }
 
-    if let Some(user_config_str) = user_config {
-      let user_validator = JSONValidator::new(USER_DEVICE_CONFIGURATION_JSON_SCHEMA);
-      match user_validator.validate(&user_config_str) {
-        Ok(_) => match serde_json::from_str(&user_config_str) {
-          Ok(user_cfg) => config.merge_user_config(user_cfg),
-          Err(err) => {
-            return Err(ButtplugDeviceError::DeviceConfigurationFileError(format!(
-              "{}",
-              err
-            )))
-          }
-        },
-        Err(err) => {
-          return Err(ButtplugDeviceError::DeviceConfigurationFileError(format!(
-            "{}",
-            err
-          )))
-        }
-      }
+  fn warn_unimplemented_protocols(&self) {
+    // Protocol names in the configuration file are just strings, so a typo or
+    // a protocol that hasn't been ported yet won't show up until a device
+    // matching it is found. Call them out now instead.
+    let unimplemented = self.unimplemented_protocols();
+    if !unimplemented.is_empty() {
+      warn!(
+        "Device configuration contains protocols with no implementation in library, devices using these protocols will not be connected unless the protocols are added before devices are found: {}",
+        unimplemented.join(", ")
+      );
     }
+  }
 
-    Ok(DeviceConfigurationManager {
-      allow_raw_messages,
-      config,
-    })
+  /// Returns a copy of the internal protocol/identifier map. Mainly used for
+  /// WebBluetooth filter construction, but could also be handy for listing
+  /// capabilities in UI, etc.
+  ///
+  /// The configuration can be reloaded at any time, so this is a snapshot
+  /// rather than a reference, and won't see later reloads.
+  pub fn protocol_configurations(&self) -> HashMap<String, ProtocolDefinition> {
+    self.config.read().unwrap().protocols.clone()
   }
 
-  /// Provides read-only access to the internal protocol/identifier map. Mainly
-  /// used for WebBluetooth filter construction, but could also be handy for
-  /// listing capabilities in UI, etc.
-  pub fn protocol_configurations(&self) -> &HashMap<String, ProtocolDefinition> {
-    &self.config.protocols
+  /// Adds a protocol implementation from outside of the library. Devices
+  /// whose configuration matches `protocol_name` will be created using `T`,
+  /// even if the library has its own implementation of the protocol.
+  ///
+  /// The protocol still needs a definition in the device configuration (base
+  /// or user) for devices to be matched to it.
+  pub fn add_protocol<T>(&self, protocol_name: &str) -> Result<(), ButtplugDeviceError>
+  where
+    T: ButtplugProtocol,
+  {
+    if self.protocol_creators.contains_key(protocol_name) {
+      return Err(ButtplugDeviceError::ProtocolAlreadyAdded(
+        protocol_name.to_owned(),
+      ));
+    }
+    let has_config = self
+      .config
+      .read()
+      .unwrap()
+      .protocols
+      .contains_key(protocol_name);
+    if !has_config {
+      warn!(
+        "Protocol {} added, but has no device configuration. No devices will be matched to it.",
+        protocol_name
+      );
+    }
+    self
+      .protocol_creators
+      .insert(protocol_name.to_owned(), T::try_create);
+    Ok(())
+  }
+
+  /// Returns the creation function for a protocol added via
+  /// [DeviceConfigurationManager::add_protocol], if one exists.
+  pub fn get_protocol_creator(&self, protocol_name: &str) -> Option<TryCreateProtocolFunc> {
+    self
+      .protocol_creators
+      .get(protocol_name)
+      .map(|creator| *creator.value())
+  }
+
+  /// Returns the names of all protocols in the loaded configuration (including
+  /// anything merged in from user configuration) that have no implementation
+  /// in the library and haven't been added via
+  /// [DeviceConfigurationManager::add_protocol]. Devices matching these
+  /// protocols can't be connected.
+  pub fn unimplemented_protocols(&self) -> Vec<String> {
+    let mut names: Vec<String> = self
+      .config
+      .read()
+      .unwrap()
+      .protocols
+      .keys()
+      .filter(|name| {
+        !self.protocol_creators.contains_key(name.as_str())
+          && ProtocolTypes::try_from(name.as_str()).is_err()
+      })
+      .cloned()
+      .collect();
+    names.sort();
+    names
   }
 
   pub fn find_configuration(
@@ -399,21 +849,53 @@ impl DeviceConfigurationManager {
     specifier: &DeviceSpecifier,
   ) -> Option<(bool, String, ProtocolDefinition)> {
     info!("Looking for protocol that matches spec: {:?}", specifier);
-    for (name, def) in self.config.protocols.iter() {
-      if def == specifier {
+    let config = self.config.read().unwrap();
+    match find_protocol(&config, specifier) {
+      Some((name, def)) => {
         debug!("Found protocol for spec!");
-        return Some((self.allow_raw_messages, name.clone(), def.clone()));
+        Some((self.allow_raw_messages, name.clone(), def.clone()))
+      }
+      None => {
+        info!("No protocol found for spec!");
+        None
       }
     }
-    info!("No protocol found for spec!");
-    None
+  }
+
+  /// Returns a copy of the device filter set via
+  /// [DeviceConfigurationManager::set_device_filter]. Allow and deny lists
+  /// from the user configuration are checked as well, but aren't included.
+  pub fn device_filter(&self) -> DeviceFilter {
+    self.device_filter.read().unwrap().clone()
+  }
+
+  /// Replaces the device filter, which is checked along with allow and deny
+  /// lists from the user configuration. Only affects devices found after the
+  /// filter is set.
+  pub fn set_device_filter(&self, filter: DeviceFilter) {
+    *self.device_filter.write().unwrap() = filter;
+  }
+
+  /// Returns true if the device filter and the user configuration allow
+  /// connecting to a device with the given address and specifier.
+  pub fn device_allowed(&self, address: &str, specifier: &DeviceSpecifier) -> bool {
+    // Hold the configuration lock throughout, so the filter and protocol
+    // always come from the same configuration.
+    let config = self.config.read().unwrap();
+    let mut filter = config.user_device_filter.clone();
+    filter.extend(self.device_filter.read().unwrap().clone());
+    if filter.is_empty() {
+      return true;
+    }
+    let protocol = find_protocol(&config, specifier).map(|(name, _)| name.as_str());
+    filter.is_allowed(address, specifier, protocol)
   }
 
   pub fn get_protocol_config(&self, name: &str) -> Option<DeviceProtocolConfiguration> {
     info!("Looking for protocol {}", name);
     // TODO It feels like maybe there should be a cleaner way to do this,
     // but I'm not really sure what it is?
-    if let Some(proto) = self.config.protocols.get(name) {
+    if let Some(proto) = self.config.read().unwrap().protocols.get(name) {
       info!("Found a protocol definition for {}", name);
       Some(DeviceProtocolConfiguration::new(
         self.allow_raw_messages,
@@ -434,13 +916,16 @@ mod test {
     DeviceConfigurationManager,
     DeviceProtocolConfiguration,
     DeviceSpecifier,
+    HIDSpecifier,
+    GENERIC_DEVICE_NAME,
   };
-  use crate::core::messages::ButtplugDeviceMessageType;
+  use crate::core::{errors::ButtplugDeviceError, messages::ButtplugDeviceMessageType};
+  use std::collections::HashMap;
 
   #[test]
   fn test_load_config() {
     let config = DeviceConfigurationManager::default();
-    debug!("{:?}", config.config);
+    debug!("{:?}", config.config.read().unwrap());
   }
 
   #[test]
@@ -466,7 +951,7 @@ mod test {
     let proto = config.find_configuration(&lovense).unwrap();
     let proto_config =
       DeviceProtocolConfiguration::new(false, proto.2.defaults.clone(), proto.2.configurations);
-    let (name_map, message_map) = proto_config.get_attributes("P", &vec![]).unwrap();
+    let (name_map, message_map, _) = proto_config.get_attributes("P", &vec![]).unwrap();
     // Make sure we got the right name
     assert_eq!(name_map.get("en-us").unwrap(), "Lovense Edge");
     // Make sure we overwrote the default of 1
@@ -482,13 +967,14 @@ mod test {
 
   #[test]
   fn test_raw_device_config_creation() {
-    let config = DeviceConfigurationManager::new_with_options(true, &None, &None).unwrap();
+    let config =
+      DeviceConfigurationManager::new_with_options(true, &None, &None, &HashMap::new()).unwrap();
     let lovense =
       DeviceSpecifier::BluetoothLE(BluetoothLESpecifier::new_from_device("LVS-Whatever"));
     let proto = config.find_configuration(&lovense).unwrap();
     let proto_config =
       DeviceProtocolConfiguration::new(true, proto.2.defaults.clone(), proto.2.configurations);
-    let (name_map, message_map) = proto_config.get_attributes("P", &vec![]).unwrap();
+    let (name_map, message_map, _) = proto_config.get_attributes("P", &vec![]).unwrap();
     // Make sure we got the right name
     assert_eq!(name_map.get("en-us").unwrap(), "Lovense Edge");
     // Make sure we overwrote the default of 1
@@ -506,7 +992,7 @@ mod test {
     let proto = config.find_configuration(&lovense).unwrap();
     let proto_config =
       DeviceProtocolConfiguration::new(false, proto.2.defaults.clone(), proto.2.configurations);
-    let (name_map, message_map) = proto_config.get_attributes("P", &vec![]).unwrap();
+    let (name_map, message_map, _) = proto_config.get_attributes("P", &vec![]).unwrap();
     // Make sure we got the right name
     assert_eq!(name_map.get("en-us").unwrap(), "Lovense Edge");
     // Make sure we overwrote the default of 1
@@ -516,12 +1002,43 @@ mod test {
     assert!(!message_map.contains_key(&ButtplugDeviceMessageType::RawUnsubscribeCmd));
   }
 
+  #[test]
+  fn test_unknown_identifier_uses_defaults() {
+    let config = DeviceConfigurationManager::default();
+    let lovense =
+      DeviceSpecifier::BluetoothLE(BluetoothLESpecifier::new_from_device("LVS-Whatever"));
+    let proto = config.find_configuration(&lovense).unwrap();
+    let proto_config =
+      DeviceProtocolConfiguration::new(false, proto.2.defaults.clone(), proto.2.configurations);
+    let (_, _, used_defaults) = proto_config.get_attributes("P", &vec![]).unwrap();
+    assert!(!used_defaults);
+    let (name_map, message_map, used_defaults) = proto_config.get_attributes("Q", &vec![]).unwrap();
+    assert!(used_defaults);
+    assert_eq!(name_map.get("en-us").unwrap(), GENERIC_DEVICE_NAME);
+    assert_eq!(
+      message_map
+        .get(&ButtplugDeviceMessageType::VibrateCmd)
+        .unwrap()
+        .feature_count
+        .unwrap(),
+      1
+    );
+    assert!(message_map.contains_key(&ButtplugDeviceMessageType::StopDeviceCmd));
+  }
+
   #[test]
   fn test_user_config_loading() {
     let mut config = DeviceConfigurationManager::default();
-    assert!(config.config.protocols.contains_key("erostek-et312"));
     assert!(config
       .config
+      .read()
+      .unwrap()
+      .protocols
+      .contains_key("erostek-et312"));
+    assert!(config
+      .config
+      .read()
+      .unwrap()
       .protocols
       .get("erostek-et312")
       .unwrap()
@@ -531,6 +1048,8 @@ mod test {
     assert_eq!(
       config
         .config
+        .read()
+        .unwrap()
         .protocols
         .get("erostek-et312")
         .unwrap()
@@ -563,11 +1082,19 @@ mod test {
         "#
         .to_string(),
       ),
+      &HashMap::new(),
     )
     .unwrap();
-    assert!(config.config.protocols.contains_key("erostek-et312"));
     assert!(config
       .config
+      .read()
+      .unwrap()
+      .protocols
+      .contains_key("erostek-et312"));
+    assert!(config
+      .config
+      .read()
+      .unwrap()
       .protocols
       .get("erostek-et312")
       .unwrap()
@@ -577,6 +1104,8 @@ mod test {
     assert_eq!(
       config
         .config
+        .read()
+        .unwrap()
         .protocols
         .get("erostek-et312")
         .unwrap()
@@ -588,6 +1117,8 @@ mod test {
     );
     assert!(config
       .config
+      .read()
+      .unwrap()
       .protocols
       .get("erostek-et312")
       .unwrap()
@@ -597,4 +1128,340 @@ mod test {
       .iter()
       .any(|x| x.port == "COM1"));
   }
+
+  #[test]
+  fn test_unimplemented_protocols() {
+    let config = DeviceConfigurationManager::default();
+    let unimplemented = config.unimplemented_protocols();
+    // Protocols that exist in the bundled config but haven't been ported yet.
+    assert!(unimplemented.contains(&"cueme".to_owned()));
+    assert!(!unimplemented.contains(&"lovense".to_owned()));
+    // A misspelled protocol name should be flagged, since it can never match
+    // an implementation.
+    let config = DeviceConfigurationManager::new_with_options(
+      false,
+      &Some(
+        r#"
+        {
+          "protocols": {
+            "lovense": {
+              "btle": {
+                "names": ["LVS-*"],
+                "services": {
+                  "0000fff0-0000-1000-8000-00805f9b34fb": {
+                    "tx": "0000fff1-0000-1000-8000-00805f9b34fb"
+                  }
+                }
+              },
+              "configurations": [
+                {
+                  "identifier": ["LVS-A"],
+                  "name": { "en-us": "Lovense Test" }
+                }
+              ]
+            },
+            "lovnese": {
+              "btle": {
+                "names": ["LVX-*"],
+                "services": {
+                  "0000fff0-0000-1000-8000-00805f9b34fb": {
+                    "tx": "0000fff1-0000-1000-8000-00805f9b34fb"
+                  }
+                }
+              },
+              "configurations": [
+                {
+                  "identifier": ["LVX-A"],
+                  "name": { "en-us": "Typo Test" }
+                }
+              ]
+            }
+          }
+        }
+        "#
+        .to_string(),
+      ),
+      &None,
+      &HashMap::new(),
+    )
+    .unwrap();
+    assert_eq!(config.unimplemented_protocols(), vec!["lovnese".to_owned()]);
+  }
+
+  fn config_with_user_config(user_config: &str) -> DeviceConfigurationManager {
+    DeviceConfigurationManager::new_with_options(
+      false,
+      &None,
+      &Some(user_config.to_owned()),
+      &HashMap::new(),
+    )
+    .unwrap()
+  }
+
+  #[test]
+  fn test_user_config_extends_protocol() {
+    let config = config_with_user_config(
+      r#"
+      {
+        "protocols": {
+          "aneros": {
+            "btle": {
+              "names": ["Rebadged Vivi"]
+            },
+            "configurations": [
+              {
+                "identifier": ["Rebadged Vivi"],
+                "name": { "en-us": "Rebadged Vivi" }
+              },
+              {
+                "identifier": ["Massage Demo"],
+                "name": { "en-us": "My Vivi" },
+                "messages": {
+                  "VibrateCmd": {
+                    "FeatureCount": 1,
+                    "StepCount": [10]
+                  }
+                }
+              }
+            ]
+          },
+          "erostek-et312": {
+            "hid": [{ "vendor-id": 4660, "product-id": 22136 }]
+          }
+        }
+      }
+      "#,
+    );
+    let rebadged =
+      DeviceSpecifier::BluetoothLE(BluetoothLESpecifier::new_from_device("Rebadged Vivi"));
+    assert_eq!(config.find_configuration(&rebadged).unwrap().1, "aneros");
+    let aneros = config.get_protocol_config("aneros").unwrap();
+    // New identifiers get the protocol defaults.
+    let (names, messages, _) = aneros.get_attributes("Rebadged Vivi", &[]).unwrap();
+    assert_eq!(names.get("en-us").unwrap(), "Rebadged Vivi");
+    assert_eq!(
+      messages
+        .get(&ButtplugDeviceMessageType::VibrateCmd)
+        .unwrap()
+        .feature_count
+        .unwrap(),
+      2
+    );
+    // Existing identifiers are overridden.
+    let (names, messages, _) = aneros.get_attributes("Massage Demo", &[]).unwrap();
+    assert_eq!(names.get("en-us").unwrap(), "My Vivi");
+    assert_eq!(
+      messages
+        .get(&ButtplugDeviceMessageType::VibrateCmd)
+        .unwrap()
+        .feature_count
+        .unwrap(),
+      1
+    );
+    // Adding specifiers to a protocol that only has serial ports shouldn't
+    // touch the serial ports.
+    let hid = DeviceSpecifier::HID(HIDSpecifier::new(4660, 22136));
+    let (_, name, definition) = config.find_configuration(&hid).unwrap();
+    assert_eq!(name, "erostek-et312");
+    assert_eq!(definition.serial.unwrap().len(), 1);
+  }
+
+  #[test]
+  fn test_user_config_disable_protocol() {
+    let config = config_with_user_config(
+      r#"
+      {
+        "protocols": {
+          "lovense": {
+            "disabled": true
+          }
+        }
+      }
+      "#,
+    );
+    let lovense =
+      DeviceSpecifier::BluetoothLE(BluetoothLESpecifier::new_from_device("LVS-Whatever"));
+    assert!(config.find_configuration(&lovense).is_none());
+    assert!(config.get_protocol_config("lovense").is_none());
+  }
+
+  #[test]
+  fn test_user_config_device_filter() {
+    let config = config_with_user_config(
+      r#"
+      {
+        "deny": {
+          "names": ["LVS-*"]
+        }
+      }
+      "#,
+    );
+    let lovense =
+      DeviceSpecifier::BluetoothLE(BluetoothLESpecifier::new_from_device("LVS-Whatever"));
+    let aneros =
+      DeviceSpecifier::BluetoothLE(BluetoothLESpecifier::new_from_device("Massage Demo"));
+    assert!(!config.device_allowed("00:11", &lovense));
+    assert!(config.device_allowed("00:11", &aneros));
+    // Protocol rules are checked against the protocol the device matches.
+    // Filters set at runtime are checked along with the user configuration.
+    let mut filter = config.device_filter();
+    assert!(filter.is_empty());
+    filter.allow.protocols.push("aneros".to_owned());
+    config.set_device_filter(filter);
+    assert!(!config.device_allowed("00:11", &lovense));
+    assert!(config.device_allowed("00:11", &aneros));
+  }
+
+  #[test]
+  fn test_reload_configuration() {
+    let config = DeviceConfigurationManager::default();
+    let rebadged =
+      DeviceSpecifier::BluetoothLE(BluetoothLESpecifier::new_from_device("Rebadged Vivi"));
+    assert!(config.find_configuration(&rebadged).is_none());
+    let user_config = Some(
+      r#"
+      {
+        "protocols": {
+          "aneros": {
+            "btle": {
+              "names": ["Rebadged Vivi"]
+            }
+          }
+        }
+      }
+      "#
+      .to_owned(),
+    );
+    config.reload(&None, &user_config).unwrap();
+    assert!(config.find_configuration(&rebadged).is_some());
+    // Invalid configuration should leave the current configuration in place.
+    let invalid_config = Some(r#"{ "protocols": { "aneros": { "bogus": 1 } } }"#.to_owned());
+    assert!(config.reload(&None, &invalid_config).is_err());
+    assert!(config.find_configuration(&rebadged).is_some());
+    config.reload(&None, &None).unwrap();
+    assert!(config.find_configuration(&rebadged).is_none());
+  }
+
+  #[test]
+  fn test_reload_keeps_device_filter() {
+    let config = config_with_user_config(
+      r#"
+      {
+        "deny": {
+          "names": ["LVS-*"]
+        }
+      }
+      "#,
+    );
+    let lovense =
+      DeviceSpecifier::BluetoothLE(BluetoothLESpecifier::new_from_device("LVS-Whatever"));
+    let aneros =
+      DeviceSpecifier::BluetoothLE(BluetoothLESpecifier::new_from_device("Massage Demo"));
+    let mut filter = config.device_filter();
+    filter.deny.addresses.push("00:22".to_owned());
+    config.set_device_filter(filter.clone());
+    // The user configuration filter is replaced, the one set at runtime stays.
+    config.reload(&None, &None).unwrap();
+    assert_eq!(config.device_filter(), filter);
+    assert!(config.device_allowed("00:11", &lovense));
+    assert!(!config.device_allowed("00:22", &aneros));
+  }
+
+  #[test]
+  fn test_yaml_config() {
+    let yaml_config = DeviceConfigurationManager::new_with_options(
+      false,
+      &Some(
+        include_str!("../../dependencies/buttplug-device-config/buttplug-device-config.yml")
+          .to_owned(),
+      ),
+      &None,
+      &HashMap::new(),
+    )
+    .unwrap();
+    let json_config = DeviceConfigurationManager::default();
+    let mut yaml_protocols: Vec<String> = yaml_config
+      .protocol_configurations()
+      .keys()
+      .cloned()
+      .collect();
+    let mut json_protocols: Vec<String> = json_config
+      .protocol_configurations()
+      .keys()
+      .cloned()
+      .collect();
+    yaml_protocols.sort();
+    json_protocols.sort();
+    assert_eq!(yaml_protocols, json_protocols);
+    let lovense =
+      DeviceSpecifier::BluetoothLE(BluetoothLESpecifier::new_from_device("LVS-Whatever"));
+    assert_eq!(
+      yaml_config.find_configuration(&lovense).unwrap().1,
+      "lovense"
+    );
+
+    let user_config = config_with_user_config(
+      r#"
+protocols:
+  aneros:
+    btle:
+      names:
+        - Rebadged Vivi
+"#,
+    );
+    let rebadged =
+      DeviceSpecifier::BluetoothLE(BluetoothLESpecifier::new_from_device("Rebadged Vivi"));
+    assert_eq!(
+      user_config.find_configuration(&rebadged).unwrap().1,
+      "aneros"
+    );
+  }
+
+  #[test]
+  fn test_yaml_config_errors() {
+    let error_message = |config: &str| match DeviceConfigurationManager::new_with_options(
+      false,
+      &None,
+      &Some(config.to_owned()),
+      &HashMap::new(),
+    ) {
+      Err(ButtplugDeviceError::DeviceConfigurationFileError(msg)) => msg,
+      Err(e) => panic!("Wrong error type: {:?}", e),
+      Ok(_) => panic!("Invalid configuration loaded"),
+    };
+    // Syntax errors
+    assert!(error_message("protocols: aneros: 1").contains("line 1 column 18"));
+    // Schema errors point at the offending value.
+    let msg = error_message(
+      r#"
+protocols:
+  aneros:
+    btle:
+      names: []
+"#,
+    );
+    assert!(msg.contains("/protocols/aneros/btle/names"), "{}", msg);
+    assert!(msg.contains("line 5 column 7"), "{}", msg);
+  }
+
+  #[test]
+  fn test_user_config_errors() {
+    let invalid_configs = [
+      // Unknown fields fail schema validation.
+      r#"{ "protocols": { "lovense": { "bluetooth": { "names": ["Test"] } } } }"#,
+      // New protocols need configurations.
+      r#"{ "protocols": { "new-protocol": { "btle": { "names": ["Test"] } } } }"#,
+      // New identifiers need names.
+      r#"{ "protocols": { "aneros": { "configurations": [{ "identifier": ["Test"] }] } } }"#,
+    ];
+    for invalid_config in &invalid_configs {
+      assert!(DeviceConfigurationManager::new_with_options(
+        false,
+        &None,
+        &Some(invalid_config.to_string()),
+        &HashMap::new()
+      )
+      .is_err());
+    }
+  }
 }
@@ -0,0 +1,161 @@
+// Buttplug Rust Source Code File - See https://buttplug.io for more info.
+//
+// Copyright 2016-2020 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+
+//! Allow and deny lists for choosing which devices the server connects to.
+
+use super::configuration_manager::DeviceSpecifier;
+use serde::Deserialize;
+
+/// A set of rules for matching devices, by address, advertised name or the
+/// protocol the device matched in the device configuration. Names can end in
+/// `*` to match any name starting with the prefix, the same as BLE names in
+/// the device configuration.
+#[derive(Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct DeviceFilterList {
+  #[serde(default)]
+  pub addresses: Vec<String>,
+  #[serde(default)]
+  pub names: Vec<String>,
+  #[serde(default)]
+  pub protocols: Vec<String>,
+}
+
+fn name_matches(pattern: &str, name: &str) -> bool {
+  if let Some(prefix) = pattern.strip_suffix('*') {
+    name.starts_with(prefix)
+  } else {
+    pattern == name
+  }
+}
+
+impl DeviceFilterList {
+  pub fn is_empty(&self) -> bool {
+    self.addresses.is_empty() && self.names.is_empty() && self.protocols.is_empty()
+  }
+
+  pub fn extend(&mut self, other: DeviceFilterList) {
+    self.addresses.extend(other.addresses);
+    self.names.extend(other.names);
+    self.protocols.extend(other.protocols);
+  }
+
+  /// Returns true if any rule in the list matches the device. An empty
+  /// address means the address isn't known, so no address rule matches it.
+  pub fn matches(&self, address: &str, names: &[String], protocol: Option<&str>) -> bool {
+    (!address.is_empty() && self.addresses.iter().any(|a| a == address))
+      || self
+        .names
+        .iter()
+        .any(|pattern| names.iter().any(|name| name_matches(pattern, name)))
+      || protocol.map_or(false, |protocol| {
+        self.protocols.iter().any(|p| p == protocol)
+      })
+  }
+}
+
+/// Decides which devices the server will try to connect to. Devices matching
+/// the deny list are always ignored. If the allow list has any entries, only
+/// devices matching it will be connected.
+///
+/// Filters are only checked when a device is found, so changing them does not
+/// affect devices that are already connected.
+#[derive(Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct DeviceFilter {
+  #[serde(default)]
+  pub allow: DeviceFilterList,
+  #[serde(default)]
+  pub deny: DeviceFilterList,
+}
+
+impl DeviceFilter {
+  pub fn is_empty(&self) -> bool {
+    self.allow.is_empty() && self.deny.is_empty()
+  }
+
+  /// Adds all rules from other to this filter.
+  pub fn extend(&mut self, other: DeviceFilter) {
+    self.allow.extend(other.allow);
+    self.deny.extend(other.deny);
+  }
+
+  /// Returns true if a device with the given address and specifier, matched
+  /// to `protocol` (if it matched anything), should be connected.
+  pub fn is_allowed(
+    &self,
+    address: &str,
+    specifier: &DeviceSpecifier,
+    protocol: Option<&str>,
+  ) -> bool {
+    let names = specifier_names(specifier);
+    if self.deny.matches(address, &names, protocol) {
+      return false;
+    }
+    self.allow.is_empty() || self.allow.matches(address, &names, protocol)
+  }
+}
+
+// HID, USB and XInput devices don't advertise names, so they can only be
+// filtered by address or protocol.
+fn specifier_names(specifier: &DeviceSpecifier) -> Vec<String> {
+  match specifier {
+    DeviceSpecifier::BluetoothLE(btle) => btle.names.iter().cloned().collect(),
+    DeviceSpecifier::Serial(serial) => vec![serial.port.clone()],
+    _ => vec![],
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::{DeviceFilter, DeviceFilterList};
+  use crate::device::configuration_manager::{BluetoothLESpecifier, DeviceSpecifier, HIDSpecifier};
+
+  fn ble(name: &str) -> DeviceSpecifier {
+    DeviceSpecifier::BluetoothLE(BluetoothLESpecifier::new_from_device(name))
+  }
+
+  #[test]
+  fn test_empty_filter_allows_everything() {
+    let filter = DeviceFilter::default();
+    assert!(filter.is_allowed("00:11", &ble("LVS-Test"), Some("lovense")));
+    assert!(filter.is_allowed("00:11", &ble("Unknown"), None));
+  }
+
+  #[test]
+  fn test_deny_list() {
+    let mut filter = DeviceFilter::default();
+    filter.deny = DeviceFilterList {
+      addresses: vec!["00:11".to_owned()],
+      names: vec!["LVS-*".to_owned()],
+      protocols: vec!["aneros".to_owned()],
+    };
+    assert!(!filter.is_allowed("00:11", &ble("Massage Demo"), None));
+    assert!(!filter.is_allowed("00:22", &ble("LVS-Test"), Some("lovense")));
+    assert!(!filter.is_allowed("00:22", &ble("Massage Demo"), Some("aneros")));
+    assert!(filter.is_allowed("00:22", &ble("LVS"), Some("lovense")));
+    let hid = DeviceSpecifier::HID(HIDSpecifier::new(0x1234, 0x5678));
+    assert!(filter.is_allowed("hid-1", &hid, Some("erostek-et312")));
+  }
+
+  #[test]
+  fn test_allow_list_with_deny_precedence() {
+    let mut filter = DeviceFilter::default();
+    filter.allow.protocols.push("lovense".to_owned());
+    filter.deny.addresses.push("00:11".to_owned());
+    assert!(filter.is_allowed("00:22", &ble("LVS-Test"), Some("lovense")));
+    assert!(!filter.is_allowed("00:22", &ble("Massage Demo"), Some("aneros")));
+    assert!(!filter.is_allowed("00:11", &ble("LVS-Test"), Some("lovense")));
+  }
+
+  #[test]
+  fn test_unknown_address() {
+    let mut filter = DeviceFilter::default();
+    filter.allow.addresses.push("".to_owned());
+    filter.allow.names.push("LVS-*".to_owned());
+    assert!(!filter.is_allowed("", &ble("Massage Demo"), None));
+    assert!(filter.is_allowed("", &ble("LVS-Test"), Some("lovense")));
+  }
+}
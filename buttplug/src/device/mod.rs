@@ -1,4 +1,5 @@
 pub mod configuration_manager;
+pub mod filter;
 pub mod protocol;
 use serde::{
   de::{self, Visitor},
@@ -38,7 +39,6 @@ use configuration_manager::DeviceProtocolConfiguration;
 use core::hash::{Hash, Hasher};
 use futures::future::BoxFuture;
 
-
 // We need this array to be exposed in our WASM FFI, but the only way to do that
 // is to expose it at the declaration level. Therefore, we use the WASM feature
 // to assume we're building for WASM and attach our bindgen. The serde
@@ -330,6 +330,15 @@ pub trait DeviceImpl: Sync + Send {
 #[async_trait]
 pub trait ButtplugDeviceImplCreator: Sync + Send {
   fn get_specifier(&self) -> DeviceSpecifier;
+  /// Address of the device, as it will be reported by [DeviceImpl::address]
+  /// once the device is created. Used to filter devices before connecting.
+  ///
+  /// Defaults to an empty string, for creators that can't know the address
+  /// before connecting. Address rules in device filters never match those
+  /// devices.
+  fn address(&self) -> String {
+    String::new()
+  }
   async fn try_create_device_impl(
     &mut self,
     protocol: ProtocolDefinition,
@@ -337,7 +346,11 @@ pub trait ButtplugDeviceImplCreator: Sync + Send {
 }
 
 pub struct ButtplugDevice {
+  protocol_name: String,
   protocol: Box<dyn ButtplugProtocol>,
+  // Set if the device's identifier wasn't in the configuration, meaning the
+  // protocol is running on its default attributes.
+  unknown_identifier: Option<String>,
   device: Arc<Box<dyn DeviceImpl>>,
 }
 
@@ -357,9 +370,15 @@ impl PartialEq for ButtplugDevice {
 }
 
 impl ButtplugDevice {
-  pub fn new(protocol: Box<dyn ButtplugProtocol>, device: Box<dyn DeviceImpl>) -> Self {
+  pub fn new(
+    protocol_name: &str,
+    protocol: Box<dyn ButtplugProtocol>,
+    device: Box<dyn DeviceImpl>,
+  ) -> Self {
     Self {
+      protocol_name: protocol_name.to_owned(),
       protocol,
+      unknown_identifier: None,
       device: Arc::new(device),
     }
   }
@@ -368,6 +387,20 @@ impl ButtplugDevice {
     self.device.address()
   }
 
+  /// Name of the protocol in the device configuration that this device was
+  /// matched to.
+  pub fn protocol_name(&self) -> &str {
+    &self.protocol_name
+  }
+
+  /// If the device identified itself with an identifier that isn't in the
+  /// configuration for its protocol, returns that identifier. These devices
+  /// are set up using the protocol defaults, and may not have the correct
+  /// features.
+  pub fn unknown_identifier(&self) -> Option<&str> {
+    self.unknown_identifier.as_deref()
+  }
+
   pub async fn try_create_device(
     device_config_mgr: Arc<DeviceConfigurationManager>,
     mut device_creator: Box<dyn ButtplugDeviceImplCreator>,
@@ -389,38 +422,93 @@ impl ButtplugDevice {
           config.defaults.clone(),
           config.configurations.clone(),
         );
-        if let Ok(proto_type) = ProtocolTypes::try_from(&*config_name) {
-          match device_creator.try_create_device_impl(config).await {
-            Ok(device_impl) => {
-              info!("Found Buttplug Device {}", device_impl.name());
-              // If we've made it this far, we now have a connected device
-              // implementation with endpoints set up. We now need to run whatever
-              // protocol initialization might need to happen. We'll fetch a protocol
-              // creator, pass the device implementation to it, then let it do
-              // whatever it needs. For most protocols, this is a no-op. However, for
-              // devices like Lovense, some Kiiroo, etc, this can get fairly
-              // complicated.
-              match protocol::try_create_protocol(
-                &proto_type,
-                &*device_impl,
-                device_protocol_config,
-              )
-              .await
-              {
-                Ok(protocol_impl) => Ok(Some(ButtplugDevice::new(protocol_impl, device_impl))),
-                Err(e) => Err(e),
+        // Protocols added at runtime take precedence over anything built into
+        // the library. If we have neither for the protocol the configuration
+        // file references, we can't do anything with the device. This is an
+        // error rather than Ok(None) so the device manager can tell the
+        // difference between "not a device we know" and "a device we know but
+        // can't talk to".
+        let protocol_creator = device_config_mgr.get_protocol_creator(&config_name);
+        let proto_type = if protocol_creator.is_some() {
+          None
+        } else {
+          match ProtocolTypes::try_from(&*config_name) {
+            Ok(proto_type) => Some(proto_type),
+            Err(e) => {
+              error!(
+                "Device {:?} matched protocol {}, which is not implemented.",
+                device_creator.get_specifier(),
+                config_name
+              );
+              return Err(e);
+            }
+          }
+        };
+        match device_creator.try_create_device_impl(config).await {
+          Ok(device_impl) => {
+            info!("Found Buttplug Device {}", device_impl.name());
+            // If we've made it this far, we now have a connected device
+            // implementation with endpoints set up. We now need to run whatever
+            // protocol initialization might need to happen. We'll fetch a protocol
+            // creator, pass the device implementation to it, then let it do
+            // whatever it needs. For most protocols, this is a no-op. However, for
+            // devices like Lovense, some Kiiroo, etc, this can get fairly
+            // complicated.
+            let protocol_fut = match (protocol_creator, proto_type) {
+              (Some(create_protocol), _) => create_protocol(&*device_impl, device_protocol_config),
+              (None, Some(proto_type)) => {
+                protocol::try_create_protocol(&proto_type, &*device_impl, device_protocol_config)
               }
+              // We returned above if neither of these resolved.
+              (None, None) => unreachable!(),
+            };
+            match protocol_fut.await {
+              Ok((protocol_impl, unknown_identifier)) => {
+                let mut device = ButtplugDevice::new(&config_name, protocol_impl, device_impl);
+                device.unknown_identifier = unknown_identifier;
+                Ok(Some(device))
+              }
+              Err(e) => Err(e),
             }
-            Err(e) => Err(e),
           }
-        } else {
-          Ok(None)
+          Err(e) => Err(e),
         }
       }
       None => Ok(None),
     }
   }
 
+  /// Rebuilds the protocol for this device from the current configuration,
+  /// keeping the existing connection. This reruns protocol initialization, so
+  /// the device may be sent its setup commands again.
+  ///
+  /// Returns None if the device's protocol no longer has a configuration or an
+  /// implementation, in which case the device should be left as is.
+  pub fn reconfigure(
+    &self,
+    device_config_mgr: &DeviceConfigurationManager,
+  ) -> Option<BoxFuture<'static, Result<ButtplugDevice, ButtplugError>>> {
+    let device_protocol_config = device_config_mgr.get_protocol_config(&self.protocol_name)?;
+    let protocol_fut =
+      if let Some(create_protocol) = device_config_mgr.get_protocol_creator(&self.protocol_name) {
+        create_protocol(&**self.device, device_protocol_config)
+      } else {
+        let proto_type = ProtocolTypes::try_from(&*self.protocol_name).ok()?;
+        protocol::try_create_protocol(&proto_type, &**self.device, device_protocol_config)
+      };
+    let protocol_name = self.protocol_name.clone();
+    let device = self.device.clone();
+    Some(Box::pin(async move {
+      let (protocol, unknown_identifier) = protocol_fut.await?;
+      Ok(ButtplugDevice {
+        protocol_name,
+        protocol,
+        unknown_identifier,
+        device,
+      })
+    }))
+  }
+
   pub fn name(&self) -> String {
     // Instead of checking for raw messages at the protocol level, add the raw
     // call here, since this is the only way to access devices in the library
@@ -1,5 +1,5 @@
 mod aneros;
-mod fleshlight_launch_helper;
+pub(crate) mod fleshlight_launch_helper;
 mod generic_command_manager;
 mod kiiroo_v2;
 mod kiiroo_v21;
@@ -12,18 +12,25 @@ mod magic_motion_v2;
 mod magic_motion_v3;
 mod maxpro;
 mod motorbunny;
+mod muse;
 mod picobong;
 mod prettylove;
 mod raw_protocol;
 mod realov;
+mod sayberx;
 mod svakom;
+mod template;
+mod twerkingbutt;
 mod vibratissimo;
+mod vorze_cyclone_x;
 mod vorze_sa;
 mod wevibe;
 mod wevibe8bit;
+mod wevibe_legacy;
 mod xinput;
 mod youcups;
 mod youou;
+mod zalo;
 
 use super::DeviceImpl;
 use crate::{
@@ -41,7 +48,7 @@ use crate::{
     },
   },
   device::{
-    configuration_manager::DeviceProtocolConfiguration,
+    configuration_manager::{DeviceProtocolConfiguration, GENERIC_DEVICE_NAME},
     ButtplugDeviceResultFuture,
     DeviceReadCmd,
     Endpoint,
@@ -64,18 +71,25 @@ pub enum ProtocolTypes {
   MagicMotionV3,
   Maxpro,
   Motorbunny,
+  Muse,
   Picobong,
   PrettyLove,
   RawProtocol,
   Realov,
+  SayberX,
   Svakom,
+  Template,
+  TwerkingButt,
   Vibratissimo,
+  VorzeCycloneX,
   VorzeSA,
   WeVibe,
   WeVibe8Bit,
+  WeVibeLegacy,
   XInput,
   Youcups,
   Youou,
+  Zalo,
 }
 
 impl TryFrom<&str> for ProtocolTypes {
@@ -95,31 +109,53 @@ impl TryFrom<&str> for ProtocolTypes {
       "magic-motion-3" => Ok(ProtocolTypes::MagicMotionV3),
       "maxpro" => Ok(ProtocolTypes::Maxpro),
       "motorbunny" => Ok(ProtocolTypes::Motorbunny),
+      "muse" => Ok(ProtocolTypes::Muse),
       "picobong" => Ok(ProtocolTypes::Picobong),
       "prettylove" => Ok(ProtocolTypes::PrettyLove),
       "raw" => Ok(ProtocolTypes::RawProtocol),
       "realov" => Ok(ProtocolTypes::Realov),
+      "sayberx" => Ok(ProtocolTypes::SayberX),
       "svakom" => Ok(ProtocolTypes::Svakom),
+      "twerkingbutt" => Ok(ProtocolTypes::TwerkingButt),
       "vibratissimo" => Ok(ProtocolTypes::Vibratissimo),
+      "vorze-cyclone-x" => Ok(ProtocolTypes::VorzeCycloneX),
       "vorze-sa" => Ok(ProtocolTypes::VorzeSA),
       "wevibe" => Ok(ProtocolTypes::WeVibe),
       "wevibe-8bit" => Ok(ProtocolTypes::WeVibe8Bit),
+      "wevibe-legacy" => Ok(ProtocolTypes::WeVibeLegacy),
       "xinput" => Ok(ProtocolTypes::XInput),
       "youcups" => Ok(ProtocolTypes::Youcups),
       "youou" => Ok(ProtocolTypes::Youou),
-      _ => {
-        error!("Protocol {} not implemented.", protocol_name);
-        Err(ButtplugDeviceError::ProtocolNotImplemented(protocol_name.to_owned()).into())
-      }
+      "zalo" => Ok(ProtocolTypes::Zalo),
+      // Template protocols are fully described by the device configuration,
+      // so there can be any number of them.
+      "template" => Ok(ProtocolTypes::Template),
+      name if name.starts_with("template-") => Ok(ProtocolTypes::Template),
+      _ => Err(ButtplugDeviceError::ProtocolNotImplemented(protocol_name.to_owned()).into()),
     }
   }
 }
 
+/// Protocol instance created for a device, along with the identifier the
+/// device reported if it wasn't in the configuration and the protocol defaults
+/// were used instead.
+pub type ButtplugProtocolCreationResult =
+  Result<(Box<dyn ButtplugProtocol>, Option<String>), ButtplugError>;
+
+/// Function used to create a protocol instance for a device. Every
+/// [ButtplugProtocol] implementation provides one via
+/// [ButtplugProtocol::try_create], which is what gets stored when protocols are
+/// added at runtime.
+pub type TryCreateProtocolFunc = fn(
+  &dyn DeviceImpl,
+  DeviceProtocolConfiguration,
+) -> BoxFuture<'static, ButtplugProtocolCreationResult>;
+
 pub fn try_create_protocol(
   protocol_type: &ProtocolTypes,
   device: &dyn DeviceImpl,
   config: DeviceProtocolConfiguration,
-) -> BoxFuture<'static, Result<Box<dyn ButtplugProtocol>, ButtplugError>> {
+) -> BoxFuture<'static, ButtplugProtocolCreationResult> {
   match protocol_type {
     ProtocolTypes::Aneros => aneros::Aneros::try_create(device, config),
     ProtocolTypes::KiirooV2 => kiiroo_v2::KiirooV2::try_create(device, config),
@@ -135,18 +171,25 @@ pub fn try_create_protocol(
     ProtocolTypes::MagicMotionV3 => magic_motion_v3::MagicMotionV3::try_create(device, config),
     ProtocolTypes::Maxpro => maxpro::Maxpro::try_create(device, config),
     ProtocolTypes::Motorbunny => motorbunny::Motorbunny::try_create(device, config),
+    ProtocolTypes::Muse => muse::Muse::try_create(device, config),
     ProtocolTypes::Picobong => picobong::Picobong::try_create(device, config),
     ProtocolTypes::PrettyLove => prettylove::PrettyLove::try_create(device, config),
     ProtocolTypes::RawProtocol => raw_protocol::RawProtocol::try_create(device, config),
     ProtocolTypes::Realov => realov::Realov::try_create(device, config),
+    ProtocolTypes::SayberX => sayberx::SayberX::try_create(device, config),
     ProtocolTypes::Svakom => svakom::Svakom::try_create(device, config),
+    ProtocolTypes::Template => template::Template::try_create(device, config),
+    ProtocolTypes::TwerkingButt => twerkingbutt::TwerkingButt::try_create(device, config),
     ProtocolTypes::Vibratissimo => vibratissimo::Vibratissimo::try_create(device, config),
+    ProtocolTypes::VorzeCycloneX => vorze_cyclone_x::VorzeCycloneX::try_create(device, config),
     ProtocolTypes::VorzeSA => vorze_sa::VorzeSA::try_create(device, config),
     ProtocolTypes::WeVibe => wevibe::WeVibe::try_create(device, config),
     ProtocolTypes::WeVibe8Bit => wevibe8bit::WeVibe8Bit::try_create(device, config),
+    ProtocolTypes::WeVibeLegacy => wevibe_legacy::WeVibeLegacy::try_create(device, config),
     ProtocolTypes::XInput => xinput::XInput::try_create(device, config),
     ProtocolTypes::Youcups => youcups::Youcups::try_create(device, config),
     ProtocolTypes::Youou => youou::Youou::try_create(device, config),
+    ProtocolTypes::Zalo => zalo::Zalo::try_create(device, config),
   }
 }
 
@@ -154,7 +197,7 @@ pub trait ButtplugProtocol: ButtplugProtocolCommandHandler + Sync {
   fn try_create(
     device_impl: &dyn DeviceImpl,
     config: DeviceProtocolConfiguration,
-  ) -> BoxFuture<'static, Result<Box<dyn ButtplugProtocol>, ButtplugError>>
+  ) -> BoxFuture<'static, ButtplugProtocolCreationResult>
   where
     Self: Sized,
   {
@@ -166,11 +209,17 @@ pub trait ButtplugProtocol: ButtplugProtocolCommandHandler + Sync {
         Ok(maybe_ident) => maybe_ident.unwrap_or(name),
         Err(err) => return Err(err),
       };
-      let (names, attrs) = config
-        .get_attributes(&device_identifier, &endpoints)
-        .unwrap();
-      let name = names.get("en-us").unwrap().clone();
-      Ok(Self::new_protocol(&name, attrs))
+      let (names, attrs, used_defaults) = config.get_attributes(&device_identifier, &endpoints)?;
+      let name = names
+        .get("en-us")
+        .cloned()
+        .unwrap_or_else(|| GENERIC_DEVICE_NAME.to_owned());
+      let unknown_identifier = if used_defaults {
+        Some(device_identifier)
+      } else {
+        None
+      };
+      Ok((Self::new_protocol(&name, attrs), unknown_identifier))
     })
   }
 
@@ -0,0 +1,218 @@
+use super::{ButtplugDeviceResultFuture, ButtplugProtocol, ButtplugProtocolCommandHandler};
+use crate::{
+  core::{
+    errors::ButtplugError,
+    messages::{self, ButtplugDeviceCommandMessageUnion, MessageAttributesMap},
+  },
+  device::{
+    protocol::{generic_command_manager::GenericCommandManager, ButtplugProtocolProperties},
+    DeviceImpl,
+    DeviceWriteCmd,
+    Endpoint,
+  },
+};
+use async_lock::Mutex;
+use futures::future::{self, BoxFuture};
+use std::sync::Arc;
+
+#[derive(ButtplugProtocolProperties)]
+pub struct SayberX {
+  name: String,
+  message_attributes: MessageAttributesMap,
+  manager: Arc<Mutex<GenericCommandManager>>,
+  stop_commands: Vec<ButtplugDeviceCommandMessageUnion>,
+}
+
+impl ButtplugProtocol for SayberX {
+  fn new_protocol(
+    name: &str,
+    message_attributes: MessageAttributesMap,
+  ) -> Box<dyn ButtplugProtocol> {
+    let manager = GenericCommandManager::new(&message_attributes);
+
+    Box::new(Self {
+      name: name.to_owned(),
+      message_attributes,
+      stop_commands: manager.get_stop_commands(),
+      manager: Arc::new(Mutex::new(manager)),
+    })
+  }
+
+  fn initialize(
+    device_impl: &dyn DeviceImpl,
+  ) -> BoxFuture<'static, Result<Option<String>, ButtplugError>> {
+    // X-Rings advertise with a per-device suffix ("X-Ring 1A2B"), so strip
+    // that to get back to the configuration identifier.
+    let identifier = if device_impl.name().starts_with("X-Ring") {
+      Some("X-Ring".to_owned())
+    } else {
+      None
+    };
+    Box::pin(future::ready(Ok(identifier)))
+  }
+}
+
+impl ButtplugProtocolCommandHandler for SayberX {
+  fn handle_vibrate_cmd(
+    &self,
+    device: Arc<Box<dyn DeviceImpl>>,
+    message: messages::VibrateCmd,
+  ) -> ButtplugDeviceResultFuture {
+    let manager = self.manager.clone();
+    Box::pin(async move {
+      // Both X-Ring motors are set in the same packet, so we always need both
+      // values. The SayberX only has the first motor.
+      let result = manager.lock().await.update_vibration(&message, true)?;
+      if let Some(cmds) = result {
+        let speed0 = cmds[0].unwrap_or(0) as u8;
+        let speed1 = cmds.get(1).copied().flatten().unwrap_or(0) as u8;
+        device
+          .write_value(DeviceWriteCmd::new(
+            Endpoint::Tx,
+            vec![0xa0, 0x03, speed0, speed1],
+            false,
+          ))
+          .await?;
+      }
+      Ok(messages::Ok::default().into())
+    })
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use crate::{
+    core::messages::{ButtplugDeviceMessageType, StopDeviceCmd, VibrateCmd, VibrateSubcommand},
+    device::{DeviceImplCommand, DeviceWriteCmd, Endpoint},
+    test::{check_recv_value, new_bluetoothle_test_device},
+    util::async_manager,
+  };
+
+  #[test]
+  pub fn test_sayberx_protocol() {
+    async_manager::block_on(async move {
+      let (device, test_device) = new_bluetoothle_test_device("SayberX").await.unwrap();
+      assert_eq!(device.name(), "SayberX");
+      assert_eq!(
+        device.message_attributes()[&ButtplugDeviceMessageType::VibrateCmd].feature_count,
+        Some(1)
+      );
+      let command_receiver = test_device
+        .get_endpoint_channel(&Endpoint::Tx)
+        .unwrap()
+        .receiver;
+      device
+        .parse_message(VibrateCmd::new(0, vec![VibrateSubcommand::new(0, 0.5)]).into())
+        .await
+        .unwrap();
+      check_recv_value(
+        &command_receiver,
+        DeviceImplCommand::Write(DeviceWriteCmd::new(
+          Endpoint::Tx,
+          vec![0xa0, 0x03, 0x02, 0x00],
+          false,
+        )),
+      )
+      .await;
+      // Same speed, so no new command should be sent.
+      device
+        .parse_message(VibrateCmd::new(0, vec![VibrateSubcommand::new(0, 0.5)]).into())
+        .await
+        .unwrap();
+      assert!(command_receiver.is_empty());
+      device
+        .parse_message(VibrateCmd::new(0, vec![VibrateSubcommand::new(0, 1.0)]).into())
+        .await
+        .unwrap();
+      check_recv_value(
+        &command_receiver,
+        DeviceImplCommand::Write(DeviceWriteCmd::new(
+          Endpoint::Tx,
+          vec![0xa0, 0x03, 0x04, 0x00],
+          false,
+        )),
+      )
+      .await;
+      device
+        .parse_message(StopDeviceCmd::new(0).into())
+        .await
+        .unwrap();
+      check_recv_value(
+        &command_receiver,
+        DeviceImplCommand::Write(DeviceWriteCmd::new(
+          Endpoint::Tx,
+          vec![0xa0, 0x03, 0x00, 0x00],
+          false,
+        )),
+      )
+      .await;
+      assert!(command_receiver.is_empty());
+    });
+  }
+
+  #[test]
+  pub fn test_sayberx_xring_protocol() {
+    async_manager::block_on(async move {
+      let (device, test_device) = new_bluetoothle_test_device("X-Ring 4C1D").await.unwrap();
+      assert_eq!(device.name(), "Sayber X-Ring");
+      assert_eq!(
+        device.message_attributes()[&ButtplugDeviceMessageType::VibrateCmd].feature_count,
+        Some(2)
+      );
+      let command_receiver = test_device
+        .get_endpoint_channel(&Endpoint::Tx)
+        .unwrap()
+        .receiver;
+      device
+        .parse_message(
+          VibrateCmd::new(
+            0,
+            vec![
+              VibrateSubcommand::new(0, 0.25),
+              VibrateSubcommand::new(1, 0.75),
+            ],
+          )
+          .into(),
+        )
+        .await
+        .unwrap();
+      check_recv_value(
+        &command_receiver,
+        DeviceImplCommand::Write(DeviceWriteCmd::new(
+          Endpoint::Tx,
+          vec![0xa0, 0x03, 0x01, 0x03],
+          false,
+        )),
+      )
+      .await;
+      // Updating one motor still sends the other motor's last speed.
+      device
+        .parse_message(VibrateCmd::new(0, vec![VibrateSubcommand::new(1, 1.0)]).into())
+        .await
+        .unwrap();
+      check_recv_value(
+        &command_receiver,
+        DeviceImplCommand::Write(DeviceWriteCmd::new(
+          Endpoint::Tx,
+          vec![0xa0, 0x03, 0x01, 0x04],
+          false,
+        )),
+      )
+      .await;
+      device
+        .parse_message(StopDeviceCmd::new(0).into())
+        .await
+        .unwrap();
+      check_recv_value(
+        &command_receiver,
+        DeviceImplCommand::Write(DeviceWriteCmd::new(
+          Endpoint::Tx,
+          vec![0xa0, 0x03, 0x00, 0x00],
+          false,
+        )),
+      )
+      .await;
+      assert!(command_receiver.is_empty());
+    });
+  }
+}
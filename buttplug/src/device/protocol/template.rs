@@ -0,0 +1,403 @@
+use super::{
+  ButtplugDeviceResultFuture,
+  ButtplugProtocol,
+  ButtplugProtocolCommandHandler,
+  ButtplugProtocolCreationResult,
+};
+use crate::{
+  core::{
+    errors::ButtplugDeviceError,
+    messages::{
+      self,
+      ButtplugDeviceCommandMessageUnion,
+      ButtplugDeviceMessageType,
+      MessageAttributesMap,
+    },
+  },
+  device::{
+    configuration_manager::{
+      DeviceProtocolConfiguration,
+      ProtocolTemplate,
+      TemplateByte,
+      TemplatePlaceholder,
+      GENERIC_DEVICE_NAME,
+    },
+    protocol::{generic_command_manager::GenericCommandManager, ButtplugProtocolProperties},
+    DeviceImpl,
+    DeviceWriteCmd,
+    Endpoint,
+  },
+};
+use async_lock::Mutex;
+use futures::future::{self, BoxFuture};
+use std::sync::Arc;
+
+// CRC-8 with polynomial 0x07 and initial value 0x00 (aka CRC-8/SMBUS), which
+// seems to be what most of the cheap BLE toy firmwares use.
+fn crc8(data: &[u8]) -> u8 {
+  data.iter().fold(0u8, |crc, byte| {
+    (0..8).fold(crc ^ byte, |crc, _| {
+      if crc & 0x80 != 0 {
+        (crc << 1) ^ 0x07
+      } else {
+        crc << 1
+      }
+    })
+  })
+}
+
+fn fill_template(template: &[TemplateByte], index: u32, step: u32) -> Vec<u8> {
+  let mut data = vec![];
+  for byte in template {
+    let value = match byte {
+      TemplateByte::Value(value) => *value,
+      TemplateByte::Placeholder(placeholder) => match placeholder {
+        TemplatePlaceholder::Step => step as u8,
+        TemplatePlaceholder::Index => index as u8,
+        TemplatePlaceholder::Sum => data.iter().fold(0u8, |sum: u8, x| sum.wrapping_add(*x)),
+        TemplatePlaceholder::Xor => data.iter().fold(0u8, |xor, x| xor ^ x),
+        TemplatePlaceholder::Crc8 => crc8(&data),
+      },
+    };
+    data.push(value);
+  }
+  data
+}
+
+// Protocol for devices whose commands are described entirely by byte
+// templates in the device configuration. Used for any protocol named
+// "template" or starting with "template-".
+#[derive(ButtplugProtocolProperties)]
+pub struct Template {
+  name: String,
+  message_attributes: MessageAttributesMap,
+  manager: Arc<Mutex<GenericCommandManager>>,
+  stop_commands: Vec<ButtplugDeviceCommandMessageUnion>,
+  template: ProtocolTemplate,
+}
+
+impl Template {
+  fn new_with_template(
+    name: &str,
+    message_attributes: MessageAttributesMap,
+    template: ProtocolTemplate,
+  ) -> Self {
+    let manager = GenericCommandManager::new(&message_attributes);
+
+    Self {
+      name: name.to_owned(),
+      message_attributes,
+      stop_commands: manager.get_stop_commands(),
+      manager: Arc::new(Mutex::new(manager)),
+      template,
+    }
+  }
+}
+
+impl ButtplugProtocol for Template {
+  // The templates live in the device configuration, so we need our own
+  // creation function to get at them.
+  fn try_create(
+    device_impl: &dyn DeviceImpl,
+    config: DeviceProtocolConfiguration,
+  ) -> BoxFuture<'static, ButtplugProtocolCreationResult> {
+    let identifier = device_impl.name().to_owned();
+    let (names, attrs, used_defaults) =
+      match config.get_attributes(&identifier, &device_impl.endpoints()) {
+        Ok(result) => result,
+        Err(err) => return Box::pin(future::ready(Err(err))),
+      };
+    let template = match config.get_template(&identifier) {
+      Some(template) => template,
+      None => {
+        return Box::pin(future::ready(Err(
+          ButtplugDeviceError::ProtocolRequirementError(format!(
+            "No template found for device {}",
+            identifier
+          ))
+          .into(),
+        )))
+      }
+    };
+    if attrs.contains_key(&ButtplugDeviceMessageType::VibrateCmd) && template.vibrate.is_empty() {
+      return Box::pin(future::ready(Err(
+        ButtplugDeviceError::ProtocolRequirementError(format!(
+          "Device {} supports VibrateCmd but has no vibrate template",
+          identifier
+        ))
+        .into(),
+      )));
+    }
+    let endpoint = template.endpoint.unwrap_or(Endpoint::Tx);
+    let init_futs: Vec<_> = template
+      .init
+      .iter()
+      .map(|data| device_impl.write_value(DeviceWriteCmd::new(endpoint, data.clone(), false)))
+      .collect();
+    Box::pin(async move {
+      for fut in init_futs {
+        fut.await?;
+      }
+      let name = names
+        .get("en-us")
+        .cloned()
+        .unwrap_or_else(|| GENERIC_DEVICE_NAME.to_owned());
+      let protocol: Box<dyn ButtplugProtocol> =
+        Box::new(Self::new_with_template(&name, attrs, template));
+      let unknown_identifier = if used_defaults {
+        Some(identifier)
+      } else {
+        None
+      };
+      Ok((protocol, unknown_identifier))
+    })
+  }
+
+  fn new_protocol(
+    name: &str,
+    message_attributes: MessageAttributesMap,
+  ) -> Box<dyn ButtplugProtocol> {
+    // Only reachable without a configuration, so there are no templates to
+    // send.
+    Box::new(Self::new_with_template(
+      name,
+      message_attributes,
+      ProtocolTemplate {
+        endpoint: None,
+        init: vec![],
+        vibrate: vec![],
+      },
+    ))
+  }
+}
+
+impl ButtplugProtocolCommandHandler for Template {
+  fn handle_vibrate_cmd(
+    &self,
+    device: Arc<Box<dyn DeviceImpl>>,
+    message: messages::VibrateCmd,
+  ) -> ButtplugDeviceResultFuture {
+    let manager = self.manager.clone();
+    let endpoint = self.template.endpoint.unwrap_or(Endpoint::Tx);
+    let templates = self.template.vibrate.clone();
+    Box::pin(async move {
+      let result = manager.lock().await.update_vibration(&message, false)?;
+      if let Some(cmds) = result {
+        for (index, cmd) in cmds.iter().enumerate() {
+          if let Some(step) = cmd {
+            // A single template is shared between all features.
+            if let Some(template) = templates.get(index).or_else(|| templates.last()) {
+              device
+                .write_value(DeviceWriteCmd::new(
+                  endpoint,
+                  fill_template(template, index as u32, *step),
+                  false,
+                ))
+                .await?;
+            }
+          }
+        }
+      }
+      Ok(messages::Ok::default().into())
+    })
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::crc8;
+  use crate::{
+    core::messages::{StopDeviceCmd, VibrateCmd, VibrateSubcommand},
+    device::{
+      configuration_manager::DeviceConfigurationManager,
+      DeviceImplCommand,
+      DeviceWriteCmd,
+      Endpoint,
+    },
+    test::{check_recv_value, new_bluetoothle_test_device_with_cfg},
+    util::async_manager,
+  };
+  use std::{collections::HashMap, sync::Arc};
+
+  static TEMPLATE_USER_CONFIG: &str = r#"
+{
+  "protocols": {
+    "template-test": {
+      "btle": {
+        "names": ["TemplateTest*"],
+        "services": {
+          "0000fff0-0000-1000-8000-00805f9b34fb": {
+            "tx": "0000fff1-0000-1000-8000-00805f9b34fb"
+          }
+        }
+      },
+      "defaults": {
+        "messages": {
+          "VibrateCmd": {
+            "FeatureCount": 2,
+            "StepCount": [10, 10]
+          }
+        },
+        "template": {
+          "init": [[170, 1]],
+          "vibrate": [[85, "index", "step", "sum"]]
+        }
+      },
+      "configurations": [
+        {
+          "identifier": ["TemplateTest"],
+          "name": { "en-us": "Template Test" }
+        },
+        {
+          "identifier": ["TemplateTest2"],
+          "name": { "en-us": "Template Test 2" },
+          "template": {
+            "vibrate": [[1, "step", "xor"], [2, "step", "crc8"]]
+          }
+        }
+      ]
+    }
+  }
+}
+"#;
+
+  fn template_config() -> Arc<DeviceConfigurationManager> {
+    Arc::new(
+      DeviceConfigurationManager::new_with_options(
+        false,
+        &None,
+        &Some(TEMPLATE_USER_CONFIG.to_owned()),
+        &HashMap::new(),
+      )
+      .unwrap(),
+    )
+  }
+
+  #[test]
+  pub fn test_crc8() {
+    assert_eq!(crc8(b"123456789"), 0xf4);
+  }
+
+  #[test]
+  pub fn test_template_protocol_defaults() {
+    async_manager::block_on(async move {
+      let (device, test_device) =
+        new_bluetoothle_test_device_with_cfg("TemplateTest", Some(template_config()))
+          .await
+          .unwrap();
+      assert_eq!(device.name(), "Template Test");
+      let command_receiver = test_device
+        .get_endpoint_channel(&Endpoint::Tx)
+        .unwrap()
+        .receiver;
+      check_recv_value(
+        &command_receiver,
+        DeviceImplCommand::Write(DeviceWriteCmd::new(Endpoint::Tx, vec![0xaa, 0x01], false)),
+      )
+      .await;
+      device
+        .parse_message(
+          VibrateCmd::new(
+            0,
+            vec![
+              VibrateSubcommand::new(0, 0.5),
+              VibrateSubcommand::new(1, 1.0),
+            ],
+          )
+          .into(),
+        )
+        .await
+        .unwrap();
+      check_recv_value(
+        &command_receiver,
+        DeviceImplCommand::Write(DeviceWriteCmd::new(
+          Endpoint::Tx,
+          vec![0x55, 0x00, 0x05, 0x5a],
+          false,
+        )),
+      )
+      .await;
+      check_recv_value(
+        &command_receiver,
+        DeviceImplCommand::Write(DeviceWriteCmd::new(
+          Endpoint::Tx,
+          vec![0x55, 0x01, 0x0a, 0x60],
+          false,
+        )),
+      )
+      .await;
+      assert!(command_receiver.is_empty());
+    });
+  }
+
+  #[test]
+  pub fn test_template_protocol_identifier_override() {
+    async_manager::block_on(async move {
+      let (device, test_device) =
+        new_bluetoothle_test_device_with_cfg("TemplateTest2", Some(template_config()))
+          .await
+          .unwrap();
+      assert_eq!(device.name(), "Template Test 2");
+      let command_receiver = test_device
+        .get_endpoint_channel(&Endpoint::Tx)
+        .unwrap()
+        .receiver;
+      // Identifier templates replace the defaults entirely, so there's no init.
+      assert!(command_receiver.is_empty());
+      device
+        .parse_message(
+          VibrateCmd::new(
+            0,
+            vec![
+              VibrateSubcommand::new(0, 0.5),
+              VibrateSubcommand::new(1, 1.0),
+            ],
+          )
+          .into(),
+        )
+        .await
+        .unwrap();
+      check_recv_value(
+        &command_receiver,
+        DeviceImplCommand::Write(DeviceWriteCmd::new(
+          Endpoint::Tx,
+          vec![0x01, 0x05, 0x04],
+          false,
+        )),
+      )
+      .await;
+      check_recv_value(
+        &command_receiver,
+        DeviceImplCommand::Write(DeviceWriteCmd::new(
+          Endpoint::Tx,
+          vec![0x02, 0x0a, 0x1c],
+          false,
+        )),
+      )
+      .await;
+      device
+        .parse_message(StopDeviceCmd::new(0).into())
+        .await
+        .unwrap();
+      check_recv_value(
+        &command_receiver,
+        DeviceImplCommand::Write(DeviceWriteCmd::new(
+          Endpoint::Tx,
+          vec![0x01, 0x00, 0x01],
+          false,
+        )),
+      )
+      .await;
+      check_recv_value(
+        &command_receiver,
+        DeviceImplCommand::Write(DeviceWriteCmd::new(
+          Endpoint::Tx,
+          vec![0x02, 0x00, 0x2a],
+          false,
+        )),
+      )
+      .await;
+      assert!(command_receiver.is_empty());
+    });
+  }
+}
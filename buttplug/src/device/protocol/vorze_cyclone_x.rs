@@ -0,0 +1,211 @@
+use super::{ButtplugDeviceResultFuture, ButtplugProtocol, ButtplugProtocolCommandHandler};
+use crate::{
+  core::{
+    errors::ButtplugError,
+    messages::{
+      self,
+      ButtplugDeviceCommandMessageUnion,
+      MessageAttributesMap,
+      RotateCmd,
+      RotationSubcommand,
+    },
+  },
+  device::{
+    protocol::{
+      generic_command_manager::GenericCommandManager,
+      vorze_sa::{vorze_rotation_data, VorzeActions, VorzeDevices},
+      ButtplugProtocolProperties,
+    },
+    DeviceImpl,
+    DeviceWriteCmd,
+    Endpoint,
+  },
+};
+use async_lock::Mutex;
+use futures::future::{self, BoxFuture};
+use std::sync::Arc;
+
+// The X10 talks to its toy through a USB HID dongle, which takes the same
+// command packets as the Vorze SA series over BLE.
+#[derive(ButtplugProtocolProperties)]
+pub struct VorzeCycloneX {
+  name: String,
+  message_attributes: MessageAttributesMap,
+  manager: Arc<Mutex<GenericCommandManager>>,
+  stop_commands: Vec<ButtplugDeviceCommandMessageUnion>,
+}
+
+impl ButtplugProtocol for VorzeCycloneX {
+  fn new_protocol(
+    name: &str,
+    message_attributes: MessageAttributesMap,
+  ) -> Box<dyn ButtplugProtocol> {
+    let manager = GenericCommandManager::new(&message_attributes);
+
+    Box::new(Self {
+      name: name.to_owned(),
+      message_attributes,
+      stop_commands: manager.get_stop_commands(),
+      manager: Arc::new(Mutex::new(manager)),
+    })
+  }
+
+  fn initialize(
+    _device_impl: &dyn DeviceImpl,
+  ) -> BoxFuture<'static, Result<Option<String>, ButtplugError>> {
+    // HID product strings vary between dongle revisions, so always identify
+    // using the config identifier.
+    Box::pin(future::ready(Ok(Some("CycloneX10".to_owned()))))
+  }
+}
+
+impl ButtplugProtocolCommandHandler for VorzeCycloneX {
+  fn handle_rotate_cmd(
+    &self,
+    device: Arc<Box<dyn DeviceImpl>>,
+    msg: messages::RotateCmd,
+  ) -> ButtplugDeviceResultFuture {
+    let manager = self.manager.clone();
+    Box::pin(async move {
+      let result = manager.lock().await.update_rotation(&msg)?;
+      if let Some((speed, clockwise)) = result[0] {
+        device
+          .write_value(DeviceWriteCmd::new(
+            Endpoint::Tx,
+            vec![
+              VorzeDevices::Cyclone as u8,
+              VorzeActions::Rotate as u8,
+              vorze_rotation_data(speed, clockwise),
+            ],
+            false,
+          ))
+          .await?;
+      }
+      Ok(messages::Ok::default().into())
+    })
+  }
+
+  fn handle_vorze_a10_cyclone_cmd(
+    &self,
+    device: Arc<Box<dyn DeviceImpl>>,
+    msg: messages::VorzeA10CycloneCmd,
+  ) -> ButtplugDeviceResultFuture {
+    // VorzeA10CycloneCmd speeds are 0-99, so convert to a RotateCmd and let
+    // the command manager take care of step conversion and deduplication.
+    let speed = msg.speed.min(99) as f64 / 99f64;
+    self.handle_rotate_cmd(
+      device,
+      RotateCmd::new(
+        msg.device_index,
+        vec![RotationSubcommand::new(0, speed, msg.clockwise)],
+      ),
+    )
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use crate::{
+    core::messages::{RotateCmd, RotationSubcommand, StopDeviceCmd, VorzeA10CycloneCmd},
+    device::{DeviceImplCommand, DeviceWriteCmd, Endpoint},
+    test::{check_recv_value, new_hid_test_device},
+    util::async_manager,
+  };
+
+  #[test]
+  pub fn test_vorze_cyclone_x_rotation_protocol() {
+    async_manager::block_on(async move {
+      let (device, test_device) = new_hid_test_device("Cyclone X", 1155, 22352).await.unwrap();
+      assert_eq!(device.name(), "Rends Cyclone X10");
+      let command_receiver = test_device
+        .get_endpoint_channel(&Endpoint::Tx)
+        .unwrap()
+        .receiver;
+      device
+        .parse_message(RotateCmd::new(0, vec![RotationSubcommand::new(0, 0.5, false)]).into())
+        .await
+        .unwrap();
+      check_recv_value(
+        &command_receiver,
+        DeviceImplCommand::Write(DeviceWriteCmd::new(
+          Endpoint::Tx,
+          vec![0x01, 0x01, 5],
+          false,
+        )),
+      )
+      .await;
+      assert!(command_receiver.is_empty());
+
+      // Same speed, new direction, should still send.
+      device
+        .parse_message(RotateCmd::new(0, vec![RotationSubcommand::new(0, 0.5, true)]).into())
+        .await
+        .unwrap();
+      check_recv_value(
+        &command_receiver,
+        DeviceImplCommand::Write(DeviceWriteCmd::new(
+          Endpoint::Tx,
+          vec![0x01, 0x01, 0x85],
+          false,
+        )),
+      )
+      .await;
+      assert!(command_receiver.is_empty());
+
+      // Nothing changed, nothing sent.
+      device
+        .parse_message(RotateCmd::new(0, vec![RotationSubcommand::new(0, 0.5, true)]).into())
+        .await
+        .unwrap();
+      assert!(command_receiver.is_empty());
+
+      device
+        .parse_message(StopDeviceCmd::new(0).into())
+        .await
+        .unwrap();
+      check_recv_value(
+        &command_receiver,
+        DeviceImplCommand::Write(DeviceWriteCmd::new(
+          Endpoint::Tx,
+          vec![0x01, 0x01, 0x0],
+          false,
+        )),
+      )
+      .await;
+      assert!(command_receiver.is_empty());
+    });
+  }
+
+  #[test]
+  pub fn test_vorze_cyclone_x_legacy_protocol() {
+    async_manager::block_on(async move {
+      let (device, test_device) = new_hid_test_device("Cyclone X", 1155, 22352).await.unwrap();
+      let command_receiver = test_device
+        .get_endpoint_channel(&Endpoint::Tx)
+        .unwrap()
+        .receiver;
+      device
+        .parse_message(VorzeA10CycloneCmd::new(0, 99, true).into())
+        .await
+        .unwrap();
+      check_recv_value(
+        &command_receiver,
+        DeviceImplCommand::Write(DeviceWriteCmd::new(
+          Endpoint::Tx,
+          vec![0x01, 0x01, 0x8a],
+          false,
+        )),
+      )
+      .await;
+      assert!(command_receiver.is_empty());
+
+      // Legacy and generic commands share state, so an equivalent RotateCmd
+      // is a no-op.
+      device
+        .parse_message(RotateCmd::new(0, vec![RotationSubcommand::new(0, 1.0, true)]).into())
+        .await
+        .unwrap();
+      assert!(command_receiver.is_empty());
+    });
+  }
+}
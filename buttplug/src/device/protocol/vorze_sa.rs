@@ -36,18 +36,24 @@ impl ButtplugProtocol for VorzeSA {
 }
 
 #[repr(u8)]
-enum VorzeDevices {
+pub(super) enum VorzeDevices {
   Bach = 6,
   UFO = 2,
   Cyclone = 1,
 }
 
 #[repr(u8)]
-enum VorzeActions {
+pub(super) enum VorzeActions {
   Rotate = 1,
   Vibrate = 3,
 }
 
+// Vorze rotation commands pack direction into the high bit and speed into the
+// low 7 bits.
+pub(super) fn vorze_rotation_data(speed: u32, clockwise: bool) -> u8 {
+  (clockwise as u8) << 7 | (speed as u8)
+}
+
 impl ButtplugProtocolCommandHandler for VorzeSA {
   fn handle_vibrate_cmd(
     &self,
@@ -94,10 +100,13 @@ impl ButtplugProtocolCommandHandler for VorzeSA {
       let result = manager.lock().await.update_rotation(&msg)?;
       let mut fut_vec = vec![];
       if let Some((speed, clockwise)) = result[0] {
-        let data: u8 = (clockwise as u8) << 7 | (speed as u8);
         fut_vec.push(device.write_value(DeviceWriteCmd::new(
           Endpoint::Tx,
-          vec![dev_id as u8, VorzeActions::Rotate as u8, data],
+          vec![
+            dev_id as u8,
+            VorzeActions::Rotate as u8,
+            vorze_rotation_data(speed, clockwise),
+          ],
           false,
         )));
       }
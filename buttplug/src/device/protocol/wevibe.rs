@@ -63,6 +63,25 @@ impl ButtplugProtocol for WeVibe {
   }
 }
 
+// Packs internal and external motor speeds into the standard We-Vibe command
+// packet. An all-zero speed gets the dedicated stop packet instead.
+pub(super) fn wevibe_vibration_packet(speed_int: u8, speed_ext: u8) -> Vec<u8> {
+  if speed_int == 0 && speed_ext == 0 {
+    vec![0x0f, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00]
+  } else {
+    vec![
+      0x0f,
+      0x03,
+      0x00,
+      speed_ext | (speed_int << 4),
+      0x00,
+      0x03,
+      0x00,
+      0x00,
+    ]
+  }
+}
+
 impl ButtplugProtocolCommandHandler for WeVibe {
   fn handle_vibrate_cmd(
     &self,
@@ -76,20 +95,7 @@ impl ButtplugProtocolCommandHandler for WeVibe {
       if let Some(cmds) = result {
         let r_speed_int = cmds[0].unwrap_or(0) as u8;
         let r_speed_ext = cmds.last().unwrap_or(&None).unwrap_or(0u32) as u8;
-        let data = if r_speed_int == 0 && r_speed_ext == 0 {
-          vec![0x0f, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00]
-        } else {
-          vec![
-            0x0f,
-            0x03,
-            0x00,
-            r_speed_ext | (r_speed_int << 4),
-            0x00,
-            0x03,
-            0x00,
-            0x00,
-          ]
-        };
+        let data = wevibe_vibration_packet(r_speed_int, r_speed_ext);
         device
           .write_value(DeviceWriteCmd::new(Endpoint::Tx, data, false))
           .await?;
@@ -0,0 +1,170 @@
+use super::{ButtplugDeviceResultFuture, ButtplugProtocol, ButtplugProtocolCommandHandler};
+use crate::{
+  core::messages::{self, ButtplugDeviceCommandMessageUnion, MessageAttributesMap},
+  device::{
+    protocol::{
+      generic_command_manager::GenericCommandManager,
+      wevibe::wevibe_vibration_packet,
+      ButtplugProtocolProperties,
+    },
+    DeviceImpl,
+    DeviceWriteCmd,
+    Endpoint,
+  },
+};
+use async_lock::Mutex;
+use std::sync::Arc;
+
+// Realm era toys use the same packets as newer We-Vibes, but their firmware
+// doesn't expect the vibrate on/off wakeup sequence on connect, so we skip
+// initialization entirely.
+#[derive(ButtplugProtocolProperties)]
+pub struct WeVibeLegacy {
+  name: String,
+  message_attributes: MessageAttributesMap,
+  manager: Arc<Mutex<GenericCommandManager>>,
+  stop_commands: Vec<ButtplugDeviceCommandMessageUnion>,
+}
+
+impl ButtplugProtocol for WeVibeLegacy {
+  fn new_protocol(
+    name: &str,
+    message_attributes: MessageAttributesMap,
+  ) -> Box<dyn ButtplugProtocol> {
+    let manager = GenericCommandManager::new(&message_attributes);
+
+    Box::new(Self {
+      name: name.to_owned(),
+      message_attributes,
+      stop_commands: manager.get_stop_commands(),
+      manager: Arc::new(Mutex::new(manager)),
+    })
+  }
+}
+
+impl ButtplugProtocolCommandHandler for WeVibeLegacy {
+  fn handle_vibrate_cmd(
+    &self,
+    device: Arc<Box<dyn DeviceImpl>>,
+    message: messages::VibrateCmd,
+  ) -> ButtplugDeviceResultFuture {
+    let manager = self.manager.clone();
+    Box::pin(async move {
+      let result = manager.lock().await.update_vibration(&message, true)?;
+      if let Some(cmds) = result {
+        let r_speed_int = cmds[0].unwrap_or(0) as u8;
+        let r_speed_ext = cmds.last().unwrap_or(&None).unwrap_or(0u32) as u8;
+        device
+          .write_value(DeviceWriteCmd::new(
+            Endpoint::Tx,
+            wevibe_vibration_packet(r_speed_int, r_speed_ext),
+            false,
+          ))
+          .await?;
+      }
+      Ok(messages::Ok::default().into())
+    })
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use crate::{
+    core::messages::{StopDeviceCmd, VibrateCmd, VibrateSubcommand},
+    device::{DeviceImplCommand, DeviceWriteCmd, Endpoint},
+    test::{check_recv_value, new_bluetoothle_test_device},
+    util::async_manager,
+  };
+
+  #[test]
+  pub fn test_wevibe_legacy_protocol_one_feature() {
+    async_manager::block_on(async move {
+      let (device, test_device) = new_bluetoothle_test_device("Rey").await.unwrap();
+      assert_eq!(device.name(), "WeVibe Realm Rey");
+      let command_receiver = test_device
+        .get_endpoint_channel(&Endpoint::Tx)
+        .unwrap()
+        .receiver;
+      // No init sequence for legacy devices.
+      assert!(command_receiver.is_empty());
+      device
+        .parse_message(VibrateCmd::new(0, vec![VibrateSubcommand::new(0, 0.5)]).into())
+        .await
+        .unwrap();
+      check_recv_value(
+        &command_receiver,
+        DeviceImplCommand::Write(DeviceWriteCmd::new(
+          Endpoint::Tx,
+          vec![0x0f, 0x03, 0x00, 0x88, 0x00, 0x03, 0x00, 0x00],
+          false,
+        )),
+      )
+      .await;
+      device
+        .parse_message(VibrateCmd::new(0, vec![VibrateSubcommand::new(0, 0.5)]).into())
+        .await
+        .unwrap();
+      assert!(command_receiver.is_empty());
+      device
+        .parse_message(StopDeviceCmd::new(0).into())
+        .await
+        .unwrap();
+      check_recv_value(
+        &command_receiver,
+        DeviceImplCommand::Write(DeviceWriteCmd::new(
+          Endpoint::Tx,
+          vec![0x0f, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00],
+          false,
+        )),
+      )
+      .await;
+    });
+  }
+
+  #[test]
+  pub fn test_wevibe_legacy_protocol_two_features() {
+    async_manager::block_on(async move {
+      let (device, test_device) = new_bluetoothle_test_device("Reina").await.unwrap();
+      assert_eq!(device.name(), "WeVibe Realm Reina");
+      let command_receiver = test_device
+        .get_endpoint_channel(&Endpoint::Tx)
+        .unwrap()
+        .receiver;
+      device
+        .parse_message(
+          VibrateCmd::new(
+            0,
+            vec![
+              VibrateSubcommand::new(0, 0.25),
+              VibrateSubcommand::new(1, 0.75),
+            ],
+          )
+          .into(),
+        )
+        .await
+        .unwrap();
+      check_recv_value(
+        &command_receiver,
+        DeviceImplCommand::Write(DeviceWriteCmd::new(
+          Endpoint::Tx,
+          vec![0x0f, 0x03, 0x00, 0x4c, 0x00, 0x03, 0x00, 0x00],
+          false,
+        )),
+      )
+      .await;
+      device
+        .parse_message(StopDeviceCmd::new(0).into())
+        .await
+        .unwrap();
+      check_recv_value(
+        &command_receiver,
+        DeviceImplCommand::Write(DeviceWriteCmd::new(
+          Endpoint::Tx,
+          vec![0x0f, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00],
+          false,
+        )),
+      )
+      .await;
+    });
+  }
+}
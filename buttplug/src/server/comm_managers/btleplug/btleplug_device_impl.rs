@@ -60,6 +60,19 @@ impl<T: Peripheral> ButtplugDeviceImplCreator for BtlePlugDeviceImplCreator<T> {
     DeviceSpecifier::BluetoothLE(BluetoothLESpecifier::new_from_device(&name))
   }
 
+  fn address(&self) -> String {
+    if self.device.is_none() {
+      panic!("Cannot call address after device is taken!");
+    }
+    self
+      .device
+      .as_ref()
+      .unwrap()
+      .properties()
+      .address
+      .to_string()
+  }
+
   async fn try_create_device_impl(
     &mut self,
     protocol: ProtocolDefinition,
@@ -7,6 +7,8 @@ use crate::{
     DeviceCommunicationEvent,
     DeviceCommunicationManager,
     DeviceCommunicationManagerCreator,
+    DeviceCommunicationManagerStatus,
+    DeviceCommunicationManagerStatusReporter,
   },
   util::async_manager,
 };
@@ -41,6 +43,7 @@ pub struct BtlePlugCommunicationManager {
   scanning_sender: Sender<()>,
   scanning_receiver: Receiver<()>,
   is_scanning: Arc<AtomicBool>,
+  status_reporter: DeviceCommunicationManagerStatusReporter,
 }
 
 impl BtlePlugCommunicationManager {
@@ -93,6 +96,11 @@ impl DeviceCommunicationManagerCreator for BtlePlugCommunicationManager {
   fn new(device_sender: Sender<DeviceCommunicationEvent>) -> Self {
     let (scanning_sender, scanning_receiver) = bounded(256);
     let manager = Manager::new().unwrap();
+    let status_reporter = DeviceCommunicationManagerStatusReporter::new(
+      "BtlePlugCommunicationManager",
+      DeviceCommunicationManagerStatus::Unknown,
+      device_sender.clone(),
+    );
     let mut comm_mgr = Self {
       manager,
       adapter: None,
@@ -101,8 +109,20 @@ impl DeviceCommunicationManagerCreator for BtlePlugCommunicationManager {
       scanning_sender,
       scanning_receiver,
       is_scanning: Arc::new(AtomicBool::new(false)),
+      status_reporter,
     };
     comm_mgr.setup_adapter();
+    let status = if comm_mgr.adapter.is_some() {
+      DeviceCommunicationManagerStatus::Available
+    } else {
+      warn!("No bluetooth adapter found, bluetooth devices will not be available.");
+      DeviceCommunicationManagerStatus::Unavailable
+    };
+    let status_reporter = comm_mgr.status_reporter.clone();
+    async_manager::spawn(async move {
+      status_reporter.set_status(status).await;
+    })
+    .unwrap();
     comm_mgr
   }
 }
@@ -115,7 +135,8 @@ impl DeviceCommunicationManager for BtlePlugCommunicationManager {
   fn start_scanning(&self) -> ButtplugResultFuture {
     // get the first bluetooth adapter
     debug!("Bringing up adapter.");
-    // TODO What happens if we don't have a radio?
+    // We only look for the radio on creation, so if there wasn't one then,
+    // there isn't one now.
     if self.adapter.is_none() {
       error!("No adapter, can't scan.");
       return ButtplugDeviceError::UnhandledCommand(
@@ -154,12 +175,20 @@ impl DeviceCommunicationManager for BtlePlugCommunicationManager {
 
     let central = self.adapter.clone().unwrap();
     let adapter_event_handler_clone = self.adapter_event_stream.clone();
+    let status_reporter = self.status_reporter.clone();
     Box::pin(async move {
       info!("Starting scan.");
       if let Err(err) = central.start_scan() {
+        // The radio may have been turned off or unplugged since we found it.
+        status_reporter
+          .set_status(DeviceCommunicationManagerStatus::Unavailable)
+          .await;
         // TODO Explain the setcap issue on linux here.
         return Err(ButtplugDeviceError::DevicePermissionError(format!("BTLEPlug cannot start scanning. This may be a permissions error (on linux) or an issue with finding the radio. Reason: {}", err)).into());
       }
+      status_reporter
+        .set_status(DeviceCommunicationManagerStatus::Available)
+        .await;
       is_scanning.store(true, Ordering::SeqCst);
       async_manager::spawn(async move {
         // When stop_scanning is called, this will get false and stop the
@@ -222,6 +251,10 @@ impl DeviceCommunicationManager for BtlePlugCommunicationManager {
   fn scanning_status(&self) -> Arc<AtomicBool> {
     self.is_scanning.clone()
   }
+
+  fn status(&self) -> DeviceCommunicationManagerStatus {
+    self.status_reporter.status()
+  }
 }
 
 impl Drop for BtlePlugCommunicationManager {
@@ -74,6 +74,10 @@ impl ButtplugDeviceImplCreator for LovenseDongleDeviceImplCreator {
     self.specifier.clone()
   }
 
+  fn address(&self) -> String {
+    self.id.clone()
+  }
+
   async fn try_create_device_impl(
     &mut self,
     _protocol: ProtocolDefinition,
@@ -12,6 +12,8 @@ use crate::{
     DeviceCommunicationEvent,
     DeviceCommunicationManager,
     DeviceCommunicationManagerCreator,
+    DeviceCommunicationManagerStatus,
+    DeviceCommunicationManagerStatusReporter,
   },
   util::async_manager,
 };
@@ -117,6 +119,7 @@ pub struct LovenseHIDDongleCommunicationManager {
   machine_sender: Sender<LovenseDeviceCommand>,
   read_thread: Arc<Mutex<Option<thread::JoinHandle<()>>>>,
   write_thread: Arc<Mutex<Option<thread::JoinHandle<()>>>>,
+  status_reporter: DeviceCommunicationManagerStatusReporter,
 }
 
 impl LovenseHIDDongleCommunicationManager {
@@ -128,6 +131,7 @@ impl LovenseHIDDongleCommunicationManager {
     let machine_sender_clone = self.machine_sender.clone();
     let held_read_thread = self.read_thread.clone();
     let held_write_thread = self.write_thread.clone();
+    let status_reporter = self.status_reporter.clone();
     Box::pin(async move {
       let (writer_sender, writer_receiver) = bounded(256);
       let (reader_sender, reader_receiver) = bounded(256);
@@ -168,6 +172,9 @@ impl LovenseHIDDongleCommunicationManager {
         ))
         .await
         .unwrap();
+      status_reporter
+        .set_status(DeviceCommunicationManagerStatus::Available)
+        .await;
       info!("Found Lovense HID Dongle");
       Ok(())
     })
@@ -182,11 +189,21 @@ impl DeviceCommunicationManagerCreator for LovenseHIDDongleCommunicationManager
       machine_sender,
       read_thread: Arc::new(Mutex::new(None)),
       write_thread: Arc::new(Mutex::new(None)),
+      status_reporter: DeviceCommunicationManagerStatusReporter::new(
+        "LovenseHIDDongleCommunicationManager",
+        DeviceCommunicationManagerStatus::Unknown,
+        event_sender.clone(),
+      ),
     };
     let dongle_fut = mgr.find_dongle();
+    let status_reporter = mgr.status_reporter.clone();
     async_manager::spawn(
       async move {
-        let _ = dongle_fut.await;
+        if dongle_fut.await.is_err() {
+          status_reporter
+            .set_status(DeviceCommunicationManagerStatus::Unavailable)
+            .await;
+        }
       }
       .instrument(tracing::info_span!("Lovense HID Dongle Finder Task")),
     )
@@ -236,4 +253,8 @@ impl DeviceCommunicationManager for LovenseHIDDongleCommunicationManager {
   fn scanning_status(&self) -> Arc<AtomicBool> {
     Arc::new(AtomicBool::new(false))
   }
+
+  fn status(&self) -> DeviceCommunicationManagerStatus {
+    self.status_reporter.status()
+  }
 }
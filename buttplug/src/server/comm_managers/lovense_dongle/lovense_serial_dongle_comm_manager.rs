@@ -12,6 +12,8 @@ use crate::{
     DeviceCommunicationEvent,
     DeviceCommunicationManager,
     DeviceCommunicationManagerCreator,
+    DeviceCommunicationManagerStatus,
+    DeviceCommunicationManagerStatusReporter,
   },
   util::async_manager,
 };
@@ -109,6 +111,7 @@ pub struct LovenseSerialDongleCommunicationManager {
   //port: Arc<Mutex<Option<Box<dyn SerialPort>>>>,
   read_thread: Arc<Mutex<Option<thread::JoinHandle<()>>>>,
   write_thread: Arc<Mutex<Option<thread::JoinHandle<()>>>>,
+  status_reporter: DeviceCommunicationManagerStatusReporter,
 }
 
 impl LovenseSerialDongleCommunicationManager {
@@ -120,7 +123,9 @@ impl LovenseSerialDongleCommunicationManager {
     let machine_sender_clone = self.machine_sender.clone();
     let held_read_thread = self.read_thread.clone();
     let held_write_thread = self.write_thread.clone();
+    let status_reporter = self.status_reporter.clone();
     Box::pin(async move {
+      let mut dongle_found = false;
       // TODO Does this block? Should it run in one of our threads?
       match available_ports() {
         Ok(ports) => {
@@ -167,6 +172,7 @@ impl LovenseSerialDongleCommunicationManager {
                       ))
                       .await
                       .unwrap();
+                    dongle_found = true;
                   }
                   Err(e) => error!("{:?}", e),
                 };
@@ -178,6 +184,12 @@ impl LovenseSerialDongleCommunicationManager {
           info!("No serial ports found");
         }
       }
+      let status = if dongle_found {
+        DeviceCommunicationManagerStatus::Available
+      } else {
+        DeviceCommunicationManagerStatus::Unavailable
+      };
+      status_reporter.set_status(status).await;
       Ok(())
     })
   }
@@ -191,6 +203,11 @@ impl DeviceCommunicationManagerCreator for LovenseSerialDongleCommunicationManag
       machine_sender,
       read_thread: Arc::new(Mutex::new(None)),
       write_thread: Arc::new(Mutex::new(None)),
+      status_reporter: DeviceCommunicationManagerStatusReporter::new(
+        "LovenseSerialDongleCommunicationManager",
+        DeviceCommunicationManagerStatus::Unknown,
+        event_sender.clone(),
+      ),
     };
     let dongle_fut = mgr.find_dongle();
     // TODO If we don't find a dongle before scanning, what happens?
@@ -245,4 +262,8 @@ impl DeviceCommunicationManager for LovenseSerialDongleCommunicationManager {
   fn scanning_status(&self) -> Arc<AtomicBool> {
     Arc::new(AtomicBool::new(false))
   }
+
+  fn status(&self) -> DeviceCommunicationManagerStatus {
+    self.status_reporter.status()
+  }
 }
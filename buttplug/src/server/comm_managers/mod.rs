@@ -13,15 +13,79 @@ pub mod serialport;
 
 use crate::{core::ButtplugResultFuture, device::ButtplugDeviceImplCreator};
 use async_channel::Sender;
-use std::sync::{atomic::AtomicBool, Arc};
+use std::sync::{atomic::AtomicBool, Arc, Mutex};
 use thiserror::Error;
 
 pub enum DeviceCommunicationEvent {
   // This event only means that a device has been found. The work still needs
   // to be done to make sure we can use it.
   DeviceFound(Box<dyn ButtplugDeviceImplCreator>),
+  // No longer sent or needed, since the device manager checks the scanning
+  // status of the comm managers it holds directly. Ignored if received.
   DeviceManagerAdded(Arc<AtomicBool>),
   ScanningFinished,
+  // Sent when the hardware a comm manager needs appears or disappears, with
+  // the name of the comm manager.
+  StatusChanged(&'static str, DeviceCommunicationManagerStatus),
+}
+
+/// Whether the hardware a [DeviceCommunicationManager] relies on, like a
+/// bluetooth radio or a dongle, can be used.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeviceCommunicationManagerStatus {
+  /// Hardware is present, or the comm manager doesn't need any.
+  Available,
+  /// Hardware is missing, turned off, or failed.
+  Unavailable,
+  /// The comm manager hasn't finished looking for its hardware yet.
+  Unknown,
+}
+
+/// Keeps track of a comm manager's status, and lets the device manager know
+/// when it changes. Clones share the same status.
+#[derive(Clone)]
+pub struct DeviceCommunicationManagerStatusReporter {
+  name: &'static str,
+  status: Arc<Mutex<DeviceCommunicationManagerStatus>>,
+  sender: Sender<DeviceCommunicationEvent>,
+}
+
+impl DeviceCommunicationManagerStatusReporter {
+  pub fn new(
+    name: &'static str,
+    status: DeviceCommunicationManagerStatus,
+    sender: Sender<DeviceCommunicationEvent>,
+  ) -> Self {
+    Self {
+      name,
+      status: Arc::new(Mutex::new(status)),
+      sender,
+    }
+  }
+
+  pub fn status(&self) -> DeviceCommunicationManagerStatus {
+    *self.status.lock().unwrap()
+  }
+
+  /// Updates the status, sending a [DeviceCommunicationEvent::StatusChanged]
+  /// if it's different from the last status.
+  pub async fn set_status(&self, status: DeviceCommunicationManagerStatus) {
+    {
+      let mut current = self.status.lock().unwrap();
+      if *current == status {
+        return;
+      }
+      *current = status;
+    }
+    if self
+      .sender
+      .send(DeviceCommunicationEvent::StatusChanged(self.name, status))
+      .await
+      .is_err()
+    {
+      error!("Device manager disappeared, cannot send status change.");
+    }
+  }
 }
 
 // Storing this in a Vec<Box<dyn T>> causes a associated function issue due to
@@ -37,6 +101,12 @@ pub trait DeviceCommunicationManager: Send + Sync {
   fn scanning_status(&self) -> Arc<AtomicBool> {
     Arc::new(AtomicBool::new(false))
   }
+  /// Whether the hardware this comm manager needs is usable. Comm managers
+  /// that need hardware should also send
+  /// [DeviceCommunicationEvent::StatusChanged] when this changes.
+  fn status(&self) -> DeviceCommunicationManagerStatus {
+    DeviceCommunicationManagerStatus::Available
+  }
   // Events happen via channel senders passed to the comm manager.
 }
 
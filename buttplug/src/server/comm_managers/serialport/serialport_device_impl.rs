@@ -50,6 +50,10 @@ impl ButtplugDeviceImplCreator for SerialPortDeviceImplCreator {
     self.specifier.clone()
   }
 
+  fn address(&self) -> String {
+    self.port_info.port_name.clone()
+  }
+
   async fn try_create_device_impl(
     &mut self,
     protocol: ProtocolDefinition,
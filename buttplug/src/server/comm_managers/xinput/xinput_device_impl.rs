@@ -43,6 +43,10 @@ impl ButtplugDeviceImplCreator for XInputDeviceImplCreator {
     DeviceSpecifier::XInput(XInputSpecifier::default())
   }
 
+  fn address(&self) -> String {
+    format!("XInput Controller {}", self.index)
+  }
+
   async fn try_create_device_impl(
     &mut self,
     _protocol: ProtocolDefinition,
@@ -13,12 +13,14 @@ use super::{
     DeviceCommunicationEvent,
     DeviceCommunicationManager,
     DeviceCommunicationManagerCreator,
+    DeviceCommunicationManagerStatus,
   },
+  ButtplugServerOptions,
   ButtplugServerStartupError,
 };
 use crate::{
   core::{
-    errors::{ButtplugDeviceError, ButtplugMessageError, ButtplugUnknownError},
+    errors::{ButtplugDeviceError, ButtplugError, ButtplugMessageError, ButtplugUnknownError},
     messages::{
       self,
       ButtplugClientMessage,
@@ -32,10 +34,14 @@ use crate::{
       DeviceMessageInfo,
       DeviceRemoved,
       ScanningFinished,
+      StartScanning,
     },
+    ButtplugResultFuture,
   },
   device::{
-    configuration_manager::DeviceConfigurationManager,
+    configuration_manager::{DeviceConfigurationManager, DeviceSpecifier},
+    filter::DeviceFilter,
+    protocol::ButtplugProtocol,
     ButtplugDevice,
     ButtplugDeviceEvent,
   },
@@ -43,7 +49,7 @@ use crate::{
   test::{TestDeviceCommunicationManager, TestDeviceCommunicationManagerHelper},
   util::async_manager,
 };
-use async_channel::{bounded, Receiver, Sender};
+use async_channel::{bounded, Receiver, Sender, TrySendError};
 use async_lock::Semaphore;
 use dashmap::DashMap;
 use futures::{
@@ -51,13 +57,148 @@ use futures::{
   FutureExt,
   StreamExt,
 };
+use futures_timer::Delay;
 use std::{
   convert::TryFrom,
   sync::{
     atomic::{AtomicBool, AtomicU32, Ordering},
     Arc,
+    Mutex,
+    RwLock,
   },
+  time::Duration,
 };
+use uuid::Uuid;
+
+/// A device that was seen while scanning, but didn't match any protocol in
+/// the device configuration. Reported if
+/// [ButtplugServerOptions::report_unsupported_devices][crate::server::ButtplugServerOptions::report_unsupported_devices]
+/// is set, so users can be told their device was found but isn't supported
+/// yet, and the information needed to add it can be collected.
+#[derive(Debug, Clone)]
+pub struct UnsupportedDevice {
+  address: String,
+  specifier: DeviceSpecifier,
+}
+
+impl UnsupportedDevice {
+  pub fn new(address: &str, specifier: DeviceSpecifier) -> Self {
+    Self {
+      address: address.to_owned(),
+      specifier,
+    }
+  }
+
+  pub fn address(&self) -> &str {
+    &self.address
+  }
+
+  pub fn specifier(&self) -> &DeviceSpecifier {
+    &self.specifier
+  }
+
+  /// The type of the specifier, using the same names as the device
+  /// configuration file.
+  pub fn specifier_type(&self) -> &'static str {
+    match self.specifier {
+      DeviceSpecifier::BluetoothLE(_) => "btle",
+      DeviceSpecifier::HID(_) => "hid",
+      DeviceSpecifier::USB(_) => "usb",
+      DeviceSpecifier::Serial(_) => "serial",
+      DeviceSpecifier::XInput(_) => "xinput",
+    }
+  }
+
+  /// The name the device advertised for bluetooth devices, or the port for
+  /// serial devices. Other device types don't have names.
+  pub fn name(&self) -> Option<String> {
+    match &self.specifier {
+      DeviceSpecifier::BluetoothLE(btle) => btle.names.iter().next().cloned(),
+      DeviceSpecifier::Serial(serial) => Some(serial.port.clone()),
+      _ => None,
+    }
+  }
+
+  /// Services the device advertised, for bluetooth devices. May be empty if
+  /// the bluetooth library only finds services after connecting.
+  pub fn services(&self) -> Vec<Uuid> {
+    match &self.specifier {
+      DeviceSpecifier::BluetoothLE(btle) => btle.services.keys().cloned().collect(),
+      _ => vec![],
+    }
+  }
+}
+
+/// A device that matched a protocol in the device configuration, but that
+/// protocol isn't implemented by this version of the library, so the device
+/// can't be used.
+#[derive(Debug, Clone)]
+pub struct UnimplementedProtocolDevice {
+  address: String,
+  protocol_name: String,
+}
+
+impl UnimplementedProtocolDevice {
+  pub fn new(address: &str, protocol_name: &str) -> Self {
+    Self {
+      address: address.to_owned(),
+      protocol_name: protocol_name.to_owned(),
+    }
+  }
+
+  pub fn address(&self) -> &str {
+    &self.address
+  }
+
+  pub fn protocol_name(&self) -> &str {
+    &self.protocol_name
+  }
+}
+
+// Gives every listener its own receiver for an event, so that e.g. the remote
+// server listening doesn't take events away from library users.
+struct EventSubscribers<T> {
+  senders: Arc<Mutex<Vec<Sender<T>>>>,
+}
+
+impl<T> Clone for EventSubscribers<T> {
+  fn clone(&self) -> Self {
+    Self {
+      senders: self.senders.clone(),
+    }
+  }
+}
+
+impl<T: Clone> EventSubscribers<T> {
+  fn new() -> Self {
+    Self {
+      senders: Arc::new(Mutex::new(vec![])),
+    }
+  }
+
+  fn subscribe(&self) -> Receiver<T> {
+    let (sender, receiver) = bounded(256);
+    self.senders.lock().unwrap().push(sender);
+    receiver
+  }
+
+  fn send(&self, event: T) {
+    // Listeners may never read their receiver, so don't wait if it's full,
+    // just drop the event for that listener. Dropped receivers are removed.
+    self
+      .senders
+      .lock()
+      .unwrap()
+      .retain(|sender| !matches!(sender.try_send(event.clone()), Err(TrySendError::Closed(_))));
+  }
+}
+
+type UnsupportedDeviceReporter = (
+  Arc<DashMap<String, UnsupportedDevice>>,
+  EventSubscribers<UnsupportedDevice>,
+);
+
+type DeviceCommunicationManagerMap = Arc<DashMap<String, Box<dyn DeviceCommunicationManager>>>;
 
 enum DeviceEvent {
   DeviceCommunicationEvent(Option<DeviceCommunicationEvent>),
@@ -65,22 +206,47 @@ enum DeviceEvent {
   PingTimeout,
 }
 
-fn wait_for_manager_events(
+// State the device manager event loop shares with the DeviceManager that
+// spawned it.
+struct DeviceManagerEventLoopContext {
   device_config_manager: Arc<DeviceConfigurationManager>,
-  ping_receiver: Option<Receiver<()>>,
   server_sender: Sender<ButtplugServerMessage>,
+  report_unsupported_devices: bool,
+  unsupported_device_reporter: UnsupportedDeviceReporter,
+  scanning: Arc<AtomicBool>,
+  scan_starting: Arc<AtomicBool>,
+  scan_protocols: Arc<RwLock<Vec<String>>>,
+  comm_managers: DeviceCommunicationManagerMap,
+  comm_manager_status_subscribers: EventSubscribers<(String, DeviceCommunicationManagerStatus)>,
+  unimplemented_protocol_subscribers: EventSubscribers<UnimplementedProtocolDevice>,
+}
+
+fn wait_for_manager_events(
+  context: DeviceManagerEventLoopContext,
+  ping_receiver: Option<Receiver<()>>,
 ) -> (
   impl Future<Output = ()>,
   Arc<DashMap<u32, ButtplugDevice>>,
   Sender<DeviceCommunicationEvent>,
 ) {
+  let DeviceManagerEventLoopContext {
+    device_config_manager,
+    server_sender,
+    report_unsupported_devices,
+    unsupported_device_reporter,
+    scanning,
+    scan_starting,
+    scan_protocols,
+    comm_managers,
+    comm_manager_status_subscribers,
+    unimplemented_protocol_subscribers,
+  } = context;
   let main_device_index = Arc::new(AtomicU32::new(0));
   let device_index_map: Arc<DashMap<String, u32>> = Arc::new(DashMap::new());
   let (device_event_sender, mut device_event_receiver) = bounded::<(u32, ButtplugDeviceEvent)>(256);
   let device_map = Arc::new(DashMap::new());
   let (device_comm_sender, mut device_comm_receiver) = bounded(256);
   let device_map_return = device_map.clone();
-  let mut device_manager_status: Vec<Arc<AtomicBool>> = vec![];
   let device_addition_semaphore = Arc::new(Semaphore::new(1));
   let event_loop = async move {
     loop {
@@ -107,6 +273,26 @@ fn wait_for_manager_events(
         DeviceEvent::DeviceCommunicationEvent(e) => match e {
           Some(event) => match event {
             DeviceCommunicationEvent::DeviceFound(device_creator) => {
+              let address = device_creator.address();
+              let specifier = device_creator.get_specifier();
+              if !device_config_manager.device_allowed(&address, &specifier) {
+                info!("Device {} blocked by device filter, ignoring.", address);
+                continue;
+              }
+              {
+                let scan_protocols = scan_protocols.read().unwrap();
+                if !scan_protocols.is_empty()
+                  && !device_config_manager
+                    .find_configuration(&specifier)
+                    .map_or(false, |(_, name, _)| scan_protocols.contains(&name))
+                {
+                  debug!(
+                    "Device {} doesn't match protocols selected for scanning, ignoring.",
+                    address
+                  );
+                  continue;
+                }
+              }
               // Pull and increment the device index now. If connection fails,
               // we'll just iterate to the next one.
               let generated_device_index = main_device_index.load(Ordering::SeqCst);
@@ -121,6 +307,9 @@ fn wait_for_manager_events(
               let device_config_mgr_clone = device_config_manager.clone();
               let device_index_map_clone = device_index_map.clone();
               let device_addition_semaphore_clone = device_addition_semaphore.clone();
+              let unsupported_device_reporter_clone = unsupported_device_reporter.clone();
+              let unimplemented_protocol_subscribers_clone =
+                unimplemented_protocol_subscribers.clone();
               async_manager::spawn(async move {
                 match ButtplugDevice::try_create_device(device_config_mgr_clone, device_creator)
                   .await
@@ -132,12 +321,14 @@ fn wait_for_manager_events(
                       // buttplugs. :(
                       let _guard = device_addition_semaphore_clone.acquire_arc().await;
                       // See if we have a reusable device index here.
-                      let device_index = if let Some(id) = device_index_map_clone.get(device.address()) {
-                        id.value().clone()
-                      } else {
-                        device_index_map_clone.insert(device.address().to_owned(), generated_device_index);
-                        generated_device_index
-                      };
+                      let device_index =
+                        if let Some(id) = device_index_map_clone.get(device.address()) {
+                          *id.value()
+                        } else {
+                          device_index_map_clone
+                            .insert(device.address().to_owned(), generated_device_index);
+                          generated_device_index
+                        };
                       // Since we can now reuse device indexes, this means we
                       // might possibly stomp on devices already in the map if
                       // they don't register a disconnect before we try to
@@ -151,7 +342,8 @@ fn wait_for_manager_events(
                         info!("Device map contains key!");
                         // We just checked that the key exists, so we can unwrap
                         // here.
-                        let old_device: dashmap::ElementGuard<u32, ButtplugDevice> = device_map_clone.remove_take(&device_index).unwrap();
+                        let old_device: dashmap::ElementGuard<u32, ButtplugDevice> =
+                          device_map_clone.remove_take(&device_index).unwrap();
                         // After removing the device from the array, manually
                         // disconnect it to make sure the event is thrown.
                         if let Err(err) = old_device.value().disconnect().await {
@@ -166,11 +358,25 @@ fn wait_for_manager_events(
 
                       info!("Assigning index {} to {}", device_index, device.name());
                       let mut recv = device.get_event_receiver();
-                      let device_added_message = DeviceAdded::new(
+                      let mut device_added_message = DeviceAdded::new(
                         device_index,
                         &device.name(),
                         &device.message_attributes(),
                       );
+                      // Devices that aren't in the configuration still work
+                      // on protocol defaults, but flag them so users can
+                      // report the identifier.
+                      if let Some(identifier) = device.unknown_identifier() {
+                        warn!(
+                          "{}",
+                          ButtplugDeviceError::UnknownDeviceIdentifier(
+                            device.address().to_owned(),
+                            identifier.to_owned(),
+                            device.protocol_name().to_owned(),
+                          )
+                        );
+                        device_added_message.unknown_identifier = Some(identifier.to_owned());
+                      }
                       device_map_clone.insert(device_index, device);
                       let sender_clone = device_event_sender_clone.clone();
                       let idx_clone = device_index;
@@ -191,21 +397,73 @@ fn wait_for_manager_events(
                         .is_err()
                       {
                         error!("Server disappeared, exiting loop.");
-                        return;
                       }
                     }
-                    None => debug!("Device could not be matched to a protocol."),
+                    None => {
+                      debug!("Device could not be matched to a protocol.");
+                      if report_unsupported_devices {
+                        let (unsupported_devices, unsupported_subscribers) =
+                          unsupported_device_reporter_clone;
+                        // Scanning will usually find the same device many
+                        // times, only report it once.
+                        if unsupported_devices.contains_key(&address) {
+                          return;
+                        }
+                        let device = UnsupportedDevice::new(&address, specifier);
+                        unsupported_devices.insert(address, device.clone());
+                        unsupported_subscribers.send(device);
+                      }
+                    }
                   },
+                  Err(ButtplugError::ButtplugDeviceError(
+                    ButtplugDeviceError::ProtocolNotImplemented(protocol_name),
+                  )) => {
+                    // The device matched our configuration, but we have no way
+                    // to talk to it. Let the server owner and clients know,
+                    // since otherwise the device will just silently never show
+                    // up.
+                    warn!(
+                      "Device {} uses protocol {}, which is not implemented.",
+                      address, protocol_name
+                    );
+                    unimplemented_protocol_subscribers_clone
+                      .send(UnimplementedProtocolDevice::new(&address, &protocol_name));
+                    if server_sender_clone
+                      .send(
+                        messages::Error::from(ButtplugError::from(
+                          ButtplugDeviceError::ProtocolNotImplemented(protocol_name),
+                        ))
+                        .into(),
+                      )
+                      .await
+                      .is_err()
+                    {
+                      error!("Server disappeared, exiting loop.");
+                    }
+                  }
                   Err(e) => error!("Device errored while trying to connect: {}", e),
                 }
               })
               .unwrap();
             }
             DeviceCommunicationEvent::ScanningFinished => {
-              for comm_mgr_status in &device_manager_status {
-                if comm_mgr_status.load(Ordering::SeqCst) {
-                  continue;
-                }
+              // Every comm manager sends ScanningFinished when it stops, so
+              // wait until none of them are still scanning. Comm managers that
+              // finish while others are still starting up would otherwise look
+              // like the last one, so also wait for start_scanning to finish,
+              // which checks again afterwards.
+              if scan_starting.load(Ordering::SeqCst)
+                || comm_managers
+                  .iter()
+                  .any(|mgr| mgr.value().scanning_status().load(Ordering::SeqCst))
+              {
+                continue;
+              }
+              // Only send one ScanningFinished per scanning session, even if
+              // multiple comm managers finish at the same time, or a comm
+              // manager finishes without us having started it.
+              if !scanning.swap(false, Ordering::SeqCst) {
+                continue;
               }
               if server_sender
                 .send(ScanningFinished::default().into())
@@ -216,8 +474,12 @@ fn wait_for_manager_events(
                 return;
               }
             }
-            DeviceCommunicationEvent::DeviceManagerAdded(status) => {
-              device_manager_status.push(status);
+            DeviceCommunicationEvent::StatusChanged(name, status) => {
+              info!("Device communication manager {} status: {:?}", name, status);
+              comm_manager_status_subscribers.send((name.to_owned(), status));
+            }
+            DeviceCommunicationEvent::DeviceManagerAdded(_) => {
+              debug!("Ignoring DeviceManagerAdded, comm manager status is tracked directly.");
             }
           },
           None => break,
@@ -270,9 +532,26 @@ fn wait_for_manager_events(
 pub struct DeviceManager {
   // This uses a map to make sure we don't have 2 comm managers of the same type
   // register. Also means we can do lockless access since it's a Dashmap.
-  comm_managers: Arc<DashMap<String, Box<dyn DeviceCommunicationManager>>>,
+  comm_managers: DeviceCommunicationManagerMap,
   devices: Arc<DashMap<u32, ButtplugDevice>>,
   sender: Sender<DeviceCommunicationEvent>,
+  server_sender: Sender<ButtplugServerMessage>,
+  config: Arc<DeviceConfigurationManager>,
+  unsupported_devices: Arc<DashMap<String, UnsupportedDevice>>,
+  unsupported_device_subscribers: EventSubscribers<UnsupportedDevice>,
+  // True from StartScanning until ScanningFinished is sent.
+  scanning: Arc<AtomicBool>,
+  // True while comm managers are being started by StartScanning.
+  scan_starting: Arc<AtomicBool>,
+  // Incremented every time scanning starts, so a timed scan doesn't stop
+  // scanning sessions started after it.
+  scan_session: Arc<AtomicU32>,
+  scan_duration: u64,
+  // Protocols selected by the last StartScanning, devices that don't match
+  // them are ignored. Empty if all protocols were selected.
+  scan_protocols: Arc<RwLock<Vec<String>>>,
+  comm_manager_status_subscribers: EventSubscribers<(String, DeviceCommunicationManagerStatus)>,
+  unimplemented_protocol_subscribers: EventSubscribers<UnimplementedProtocolDevice>,
 }
 
 unsafe impl Send for DeviceManager {
@@ -285,42 +564,261 @@ impl DeviceManager {
   pub fn new_with_options(
     event_sender: Sender<ButtplugServerMessage>,
     ping_receiver: Option<Receiver<()>>,
-    allow_raw_messages: bool,
-    device_config_json: &Option<String>,
-    user_device_config_json: &Option<String>,
+    options: &ButtplugServerOptions,
   ) -> Result<Self, ButtplugDeviceError> {
     let config = Arc::new(DeviceConfigurationManager::new_with_options(
-      allow_raw_messages,
-      device_config_json,
-      user_device_config_json,
+      options.allow_raw_messages,
+      &options.device_configuration_json,
+      &options.user_device_configuration_json,
+      &options.protocols,
     )?);
+    // Set before the event loop starts, so no device is found without it.
+    config.set_device_filter(options.device_filter.clone());
+    let unsupported_devices = Arc::new(DashMap::new());
+    let scanning = Arc::new(AtomicBool::new(false));
+    let scan_starting = Arc::new(AtomicBool::new(false));
+    let scan_protocols = Arc::new(RwLock::new(vec![]));
+    let unsupported_device_subscribers = EventSubscribers::new();
+    let comm_managers = Arc::new(DashMap::new());
+    let comm_manager_status_subscribers = EventSubscribers::new();
+    let unimplemented_protocol_subscribers = EventSubscribers::new();
+    let context = DeviceManagerEventLoopContext {
+      device_config_manager: config.clone(),
+      server_sender: event_sender.clone(),
+      report_unsupported_devices: options.report_unsupported_devices,
+      unsupported_device_reporter: (
+        unsupported_devices.clone(),
+        unsupported_device_subscribers.clone(),
+      ),
+      scanning: scanning.clone(),
+      scan_starting: scan_starting.clone(),
+      scan_protocols: scan_protocols.clone(),
+      comm_managers: comm_managers.clone(),
+      comm_manager_status_subscribers: comm_manager_status_subscribers.clone(),
+      unimplemented_protocol_subscribers: unimplemented_protocol_subscribers.clone(),
+    };
     let (event_loop_fut, device_map, device_event_sender) =
-      wait_for_manager_events(config, ping_receiver, event_sender);
+      wait_for_manager_events(context, ping_receiver);
     async_manager::spawn(event_loop_fut).unwrap();
     Ok(Self {
       sender: device_event_sender,
+      server_sender: event_sender,
       devices: device_map,
-      comm_managers: Arc::new(DashMap::new()),
+      comm_managers,
+      config,
+      unsupported_devices,
+      unsupported_device_subscribers,
+      scanning,
+      scan_starting,
+      scan_session: Arc::new(AtomicU32::new(0)),
+      scan_duration: options.scan_duration,
+      scan_protocols,
+      comm_manager_status_subscribers,
+      unimplemented_protocol_subscribers,
+    })
+  }
+
+  /// Loads new base and user device configuration, which will be used for all
+  /// devices found from now on. Allow and deny lists from the new user
+  /// configuration replace the old ones, while the device filter set via
+  /// [DeviceManager::set_device_filter] is kept.
+  ///
+  /// If `reconfigure_devices` is true, connected devices are rebuilt using
+  /// the new configuration. Devices whose name or message attributes change
+  /// are announced to clients as removed, then added again with the same
+  /// index.
+  pub fn reload_configuration(
+    &self,
+    device_config_json: &Option<String>,
+    user_device_config_json: &Option<String>,
+    reconfigure_devices: bool,
+  ) -> ButtplugResultFuture {
+    if let Err(e) = self
+      .config
+      .reload(device_config_json, user_device_config_json)
+    {
+      return e.into();
+    }
+    if !reconfigure_devices {
+      return Box::pin(future::ready(Ok(())));
+    }
+    let reconfigure_futs: Vec<_> = self
+      .devices
+      .iter()
+      .filter_map(|dev| {
+        let device = dev.value();
+        device.reconfigure(&self.config).map(|fut| {
+          (
+            *dev.key(),
+            device.name(),
+            device.message_attributes(),
+            device.unknown_identifier().map(str::to_owned),
+            fut,
+          )
+        })
+      })
+      .collect();
+    let device_map = self.devices.clone();
+    let server_sender = self.server_sender.clone();
+    Box::pin(async move {
+      for (device_index, old_name, old_attributes, old_unknown_identifier, fut) in reconfigure_futs
+      {
+        let device = match fut.await {
+          Ok(device) => device,
+          Err(e) => {
+            error!(
+              "Error reconfiguring device {}, leaving it as is: {}",
+              device_index, e
+            );
+            continue;
+          }
+        };
+        if device.name() == old_name
+          && device.message_attributes() == old_attributes
+          && device.unknown_identifier() == old_unknown_identifier.as_deref()
+        {
+          continue;
+        }
+        // The device may have disconnected while we were rebuilding it.
+        if !device_map.contains_key(&device_index) {
+          continue;
+        }
+        info!("Device {} changed by configuration reload", device_index);
+        let mut device_added_message =
+          DeviceAdded::new(device_index, &device.name(), &device.message_attributes());
+        device_added_message.unknown_identifier = device.unknown_identifier().map(str::to_owned);
+        device_map.insert(device_index, device);
+        if server_sender
+          .send(DeviceRemoved::new(device_index).into())
+          .await
+          .is_err()
+          || server_sender
+            .send(device_added_message.into())
+            .await
+            .is_err()
+        {
+          error!("Server disappeared, cannot update about reconfigured devices.");
+          break;
+        }
+      }
+      Ok(())
     })
   }
 
-  fn start_scanning(&self) -> ButtplugServerResultFuture {
+  /// Returns all devices found while scanning that didn't match any protocol,
+  /// if unsupported device reporting is turned on.
+  pub fn unsupported_devices(&self) -> Vec<UnsupportedDevice> {
+    self
+      .unsupported_devices
+      .iter()
+      .map(|device| device.value().clone())
+      .collect()
+  }
+
+  /// Receives an event for each new unsupported device found while scanning,
+  /// if unsupported device reporting is turned on. Each receiver gets every
+  /// event.
+  pub fn unsupported_device_receiver(&self) -> Receiver<UnsupportedDevice> {
+    self.unsupported_device_subscribers.subscribe()
+  }
+
+  /// Names of protocols in the device configuration that have no
+  /// implementation. Devices matching them can't be connected.
+  pub fn unimplemented_protocols(&self) -> Vec<String> {
+    self.config.unimplemented_protocols()
+  }
+
+  /// Receives an event for each device found that matches a protocol with no
+  /// implementation. Each receiver gets every event.
+  pub fn unimplemented_protocol_receiver(&self) -> Receiver<UnimplementedProtocolDevice> {
+    self.unimplemented_protocol_subscribers.subscribe()
+  }
+
+  /// Names of the device communication managers that have been added, which
+  /// can be used to select comm managers in
+  /// [StartScanning::new_with_selection].
+  pub fn comm_manager_names(&self) -> Vec<String> {
+    self
+      .comm_managers
+      .iter()
+      .map(|mgr| mgr.key().clone())
+      .collect()
+  }
+
+  fn start_scanning(&self, msg: &StartScanning) -> ButtplugServerResultFuture {
     if self.comm_managers.is_empty() {
       ButtplugUnknownError::NoDeviceCommManagers.into()
     } else {
+      for name in msg.comm_managers() {
+        if !self.comm_managers.contains_key(name) {
+          return ButtplugDeviceError::DeviceCommunicationManagerNotFound(name.clone()).into();
+        }
+      }
+      let selected_mgrs: Vec<String> = if msg.comm_managers().is_empty() {
+        self.comm_manager_names()
+      } else {
+        msg.comm_managers().to_vec()
+      };
+      let selected_protocols = msg.protocols().to_vec();
+      let scan_protocols = self.scan_protocols.clone();
       let mgrs = self.comm_managers.clone();
+      let scanning = self.scanning.clone();
+      let scan_starting = self.scan_starting.clone();
+      let scan_session = self.scan_session.clone();
+      let comm_event_sender = self.sender.clone();
+      let scan_duration = self.scan_duration;
       Box::pin(async move {
         for mgr in mgrs.iter() {
           if mgr.value().scanning_status().load(Ordering::SeqCst) {
             return Err(ButtplugDeviceError::DeviceScanningAlreadyStarted.into());
           }
         }
-        let fut_vec: Vec<_> = mgrs
+        // Set these before starting the comm managers, as they may find
+        // devices or finish scanning before start_scanning returns.
+        *scan_protocols.write().unwrap() = selected_protocols;
+        scanning.store(true, Ordering::SeqCst);
+        scan_starting.store(true, Ordering::SeqCst);
+        let session = scan_session.fetch_add(1, Ordering::SeqCst) + 1;
+        let fut_vec: Vec<_> = selected_mgrs
           .iter()
-          .map(|guard| guard.value().start_scanning())
+          .filter_map(|name| mgrs.get(name).map(|mgr| mgr.value().start_scanning()))
           .collect();
         // TODO If start_scanning fails anywhere, this will ignore it. We should maybe at least log?
         future::join_all(fut_vec).await;
+        scan_starting.store(false, Ordering::SeqCst);
+        // Any comm manager that already finished had its ScanningFinished
+        // ignored while we were starting, so check again now.
+        if comm_event_sender
+          .send(DeviceCommunicationEvent::ScanningFinished)
+          .await
+          .is_err()
+        {
+          error!("Device manager event loop disappeared, cannot check scanning status.");
+        }
+        if scan_duration > 0 {
+          async_manager::spawn(async move {
+            Delay::new(Duration::from_millis(scan_duration)).await;
+            // Don't stop a session that was stopped, or started after this
+            // one.
+            if scan_session.load(Ordering::SeqCst) != session || !scanning.load(Ordering::SeqCst) {
+              return;
+            }
+            info!(
+              "Scan duration of {}ms reached, stopping scanning.",
+              scan_duration
+            );
+            let fut_vec: Vec<_> = selected_mgrs
+              .iter()
+              .filter_map(|name| mgrs.get(name).map(|mgr| mgr.value().stop_scanning()))
+              .collect();
+            for result in future::join_all(fut_vec).await {
+              if let Err(e) = result {
+                error!("Error stopping timed scan: {}", e);
+              }
+            }
+          })
+          .unwrap();
+        }
         Ok(messages::Ok::default().into())
       })
     }
@@ -400,6 +898,7 @@ impl DeviceManager {
               device_index: *device.key(),
               device_name: dev.name(),
               device_messages: dev.message_attributes(),
+              unknown_identifier: dev.unknown_identifier().map(str::to_owned),
             }
           })
           .collect();
@@ -408,7 +907,7 @@ impl DeviceManager {
         Box::pin(future::ready(Ok(device_list.into())))
       }
       ButtplugDeviceManagerMessageUnion::StopAllDevices(_) => self.stop_all_devices(),
-      ButtplugDeviceManagerMessageUnion::StartScanning(_) => self.start_scanning(),
+      ButtplugDeviceManagerMessageUnion::StartScanning(msg) => self.start_scanning(&msg),
       ButtplugDeviceManagerMessageUnion::StopScanning(_) => self.stop_scanning(),
     }
   }
@@ -435,22 +934,65 @@ impl DeviceManager {
         mgr.name().to_owned(),
       ));
     }
-    let status = mgr.scanning_status();
-    let sender = self.sender.clone();
-    // TODO This could run out of order and possibly cause weird scanning finished bugs?
-    async_manager::spawn(async move {
-      sender
-        .send(DeviceCommunicationEvent::DeviceManagerAdded(status))
-        .await
-        .unwrap();
-    })
-    .unwrap();
     self
       .comm_managers
       .insert(mgr.name().to_owned(), Box::new(mgr));
     Ok(())
   }
 
+  /// Removes a device communication manager, stopping it first if it's
+  /// scanning. Devices that were found by the comm manager stay connected.
+  pub fn remove_comm_manager(&self, name: &str) -> ButtplugResultFuture {
+    let mgr = match self.comm_managers.remove_take(name) {
+      Some(mgr) => mgr,
+      None => {
+        return ButtplugDeviceError::DeviceCommunicationManagerNotFound(name.to_owned()).into()
+      }
+    };
+    info!("Removing device communication manager {}", name);
+    let stop_fut = if mgr.value().scanning_status().load(Ordering::SeqCst) {
+      Some(mgr.value().stop_scanning())
+    } else {
+      None
+    };
+    Box::pin(async move {
+      if let Some(fut) = stop_fut {
+        fut.await?;
+      }
+      Ok(())
+    })
+  }
+
+  /// Returns the hardware status of a device communication manager, or None
+  /// if no comm manager with that name has been added.
+  pub fn comm_manager_status(&self, name: &str) -> Option<DeviceCommunicationManagerStatus> {
+    self.comm_managers.get(name).map(|mgr| mgr.value().status())
+  }
+
+  /// Receives the name and new status of comm managers when their hardware
+  /// status changes. Every receiver gets every change from the time it was
+  /// created on.
+  pub fn comm_manager_status_receiver(
+    &self,
+  ) -> Receiver<(String, DeviceCommunicationManagerStatus)> {
+    self.comm_manager_status_subscribers.subscribe()
+  }
+
+  pub fn add_protocol<T>(&self, protocol_name: &str) -> Result<(), ButtplugDeviceError>
+  where
+    T: ButtplugProtocol,
+  {
+    self.config.add_protocol::<T>(protocol_name)
+  }
+
+  pub fn device_filter(&self) -> DeviceFilter {
+    self.config.device_filter()
+  }
+
+  pub fn set_device_filter(&self, filter: DeviceFilter) {
+    self.config.set_device_filter(filter)
+  }
+
   pub fn add_test_comm_manager(
     &self,
   ) -> Result<TestDeviceCommunicationManagerHelper, ButtplugServerStartupError> {
@@ -460,16 +1002,6 @@ impl DeviceManager {
         mgr.name().to_owned(),
       ));
     }
-    let status = mgr.scanning_status();
-    let sender = self.sender.clone();
-    // TODO This could run out of order and possibly cause weird scanning finished bugs?
-    async_manager::spawn(async move {
-      sender
-        .send(DeviceCommunicationEvent::DeviceManagerAdded(status))
-        .await
-        .unwrap();
-    })
-    .unwrap();
     let helper = mgr.helper();
     self
       .comm_managers
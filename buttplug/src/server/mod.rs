@@ -23,21 +23,32 @@ use crate::{
       ButtplugDeviceCommandMessageUnion,
       ButtplugDeviceManagerMessageUnion,
       ButtplugMessage,
+      ButtplugMessageSpecVersion,
       ButtplugServerMessage,
       StopAllDevices,
       StopScanning,
       BUTTPLUG_CURRENT_MESSAGE_SPEC_VERSION,
     },
+    ButtplugResultFuture,
+  },
+  device::{
+    filter::DeviceFilter,
+    protocol::{ButtplugProtocol, TryCreateProtocolFunc},
   },
   test::TestDeviceCommunicationManagerHelper,
   util::async_manager,
 };
 use async_channel::{bounded, Receiver};
-use comm_managers::{DeviceCommunicationManager, DeviceCommunicationManagerCreator};
-use device_manager::DeviceManager;
+use comm_managers::{
+  DeviceCommunicationManager,
+  DeviceCommunicationManagerCreator,
+  DeviceCommunicationManagerStatus,
+};
+use device_manager::{DeviceManager, UnimplementedProtocolDevice, UnsupportedDevice};
 use futures::{future::BoxFuture, StreamExt};
 use ping_timer::PingTimer;
 use std::{
+  collections::HashMap,
   convert::{TryFrom, TryInto},
   sync::{
     atomic::{AtomicBool, Ordering},
@@ -60,8 +71,28 @@ pub struct ButtplugServerOptions {
   pub name: String,
   pub max_ping_time: u64,
   pub allow_raw_messages: bool,
+  /// Base device configuration, as JSON or YAML. Uses the configuration
+  /// built into the library if None.
   pub device_configuration_json: Option<String>,
+  /// User device configuration, as JSON or YAML.
   pub user_device_configuration_json: Option<String>,
+  /// Allow and deny lists for devices, added to any set in the user device
+  /// configuration.
+  pub device_filter: DeviceFilter,
+  /// Keep track of devices found while scanning that don't match any
+  /// protocol, see [ButtplugServer::unsupported_devices].
+  pub report_unsupported_devices: bool,
+  /// Milliseconds to scan for after StartScanning is received, after which
+  /// scanning is stopped. 0 scans until StopScanning is received.
+  pub scan_duration: u64,
+  /// Newest message spec version the server will speak. Clients asking for a
+  /// newer version are rejected during the handshake, and ServerInfo reports
+  /// this version. Lowering it makes the server act like an older release.
+  pub max_message_spec_version: ButtplugMessageSpecVersion,
+  /// Protocol implementations from outside of the library, keyed by the
+  /// protocol name used in the device configuration. See
+  /// [ButtplugServerOptions::add_protocol].
+  pub protocols: HashMap<String, TryCreateProtocolFunc>,
 }
 
 impl Default for ButtplugServerOptions {
@@ -72,15 +103,35 @@ impl Default for ButtplugServerOptions {
       allow_raw_messages: false,
       device_configuration_json: None,
       user_device_configuration_json: None,
+      device_filter: DeviceFilter::default(),
+      report_unsupported_devices: false,
+      scan_duration: 0,
+      max_message_spec_version: BUTTPLUG_CURRENT_MESSAGE_SPEC_VERSION,
+      protocols: HashMap::new(),
     }
   }
 }
 
+impl ButtplugServerOptions {
+  /// Adds a protocol implementation from outside of the library, the same as
+  /// [ButtplugServer::add_protocol] but available from server startup.
+  /// Replaces any protocol already added with the same name.
+  pub fn add_protocol<T>(&mut self, protocol_name: &str)
+  where
+    T: ButtplugProtocol,
+  {
+    self
+      .protocols
+      .insert(protocol_name.to_owned(), T::try_create);
+  }
+}
+
 /// Represents a ButtplugServer.
 pub struct ButtplugServer {
   server_name: String,
   client_name: String,
   max_ping_time: u64,
+  max_message_spec_version: ButtplugMessageSpecVersion,
   device_manager: DeviceManager,
   ping_timer: Option<PingTimer>,
   pinged_out: Arc<AtomicBool>,
@@ -134,18 +185,13 @@ impl ButtplugServer {
     } else {
       (None, None)
     };
-    let device_manager = DeviceManager::new_with_options(
-      send,
-      ping_receiver,
-      options.allow_raw_messages,
-      &options.device_configuration_json,
-      &options.user_device_configuration_json,
-    )?;
+    let device_manager = DeviceManager::new_with_options(send, ping_receiver, options)?;
     Ok((
       Self {
         server_name: options.name.clone(),
         client_name: String::default(),
         max_ping_time: options.max_ping_time,
+        max_message_spec_version: options.max_message_spec_version,
         device_manager,
         ping_timer,
         pinged_out,
@@ -166,6 +212,108 @@ impl ButtplugServer {
     self.device_manager.add_comm_manager::<T>()
   }
 
+  /// Removes a device communication manager, stopping it if it's scanning.
+  /// Devices it found stay connected.
+  pub fn remove_comm_manager(&self, name: &str) -> ButtplugResultFuture {
+    self.device_manager.remove_comm_manager(name)
+  }
+
+  /// Returns whether the hardware a comm manager uses is available, or None
+  /// if there's no comm manager with that name.
+  pub fn comm_manager_status(&self, name: &str) -> Option<DeviceCommunicationManagerStatus> {
+    self.device_manager.comm_manager_status(name)
+  }
+
+  /// Returns a receiver that gets the name and new status of a comm manager
+  /// whenever its hardware status changes. Each call returns a new receiver,
+  /// which gets every change from then on.
+  pub fn comm_manager_status_receiver(
+    &self,
+  ) -> Receiver<(String, DeviceCommunicationManagerStatus)> {
+    self.device_manager.comm_manager_status_receiver()
+  }
+
+  /// Names of the device communication managers added to the server, for
+  /// use with [StartScanning::new_with_selection][messages::StartScanning::new_with_selection].
+  pub fn comm_manager_names(&self) -> Vec<String> {
+    self.device_manager.comm_manager_names()
+  }
+
+  /// Adds a protocol implementation from outside of the library, which will be
+  /// used for devices matching `protocol_name` in the device configuration.
+  /// Protocols added this way take precedence over those built into the
+  /// library.
+  pub fn add_protocol<T>(&self, protocol_name: &str) -> Result<(), ButtplugDeviceError>
+  where
+    T: ButtplugProtocol,
+  {
+    self.device_manager.add_protocol::<T>(protocol_name)
+  }
+
+  /// Returns the device filter set through server options or
+  /// [ButtplugServer::set_device_filter]. Allow and deny lists from the user
+  /// device configuration are also checked, but aren't included.
+  pub fn device_filter(&self) -> DeviceFilter {
+    self.device_manager.device_filter()
+  }
+
+  /// Replaces the device filter, which is kept when device configuration is
+  /// reloaded. Devices that are already connected are not affected, the
+  /// filter is only checked when devices are found.
+  pub fn set_device_filter(&self, filter: DeviceFilter) {
+    self.device_manager.set_device_filter(filter)
+  }
+
+  /// Returns devices that were found while scanning but didn't match any
+  /// protocol in the device configuration. Always empty unless
+  /// [ButtplugServerOptions::report_unsupported_devices] is set.
+  pub fn unsupported_devices(&self) -> Vec<UnsupportedDevice> {
+    self.device_manager.unsupported_devices()
+  }
+
+  /// Returns a receiver that gets each unsupported device the first time it
+  /// is found. Each receiver gets every event.
+  pub fn unsupported_device_receiver(&self) -> Receiver<UnsupportedDevice> {
+    self.device_manager.unsupported_device_receiver()
+  }
+
+  /// Returns the names of protocols in the device configuration that have no
+  /// implementation, either in the library or added via
+  /// [ButtplugServer::add_protocol]. Devices matching these protocols can't be
+  /// connected.
+  pub fn unimplemented_protocols(&self) -> Vec<String> {
+    self.device_manager.unimplemented_protocols()
+  }
+
+  /// Returns a receiver that gets each device found that matches a protocol in
+  /// the device configuration that isn't implemented. Clients are also sent
+  /// a [ButtplugDeviceError::ProtocolNotImplemented] error when this happens.
+  pub fn unimplemented_protocol_receiver(&self) -> Receiver<UnimplementedProtocolDevice> {
+    self.device_manager.unimplemented_protocol_receiver()
+  }
+
+  /// Replaces the device configuration without restarting the server. The
+  /// new configuration is validated before anything changes, so on error the
+  /// server keeps using its current configuration. Configuration can be JSON
+  /// or YAML. Passing None for the base configuration uses the one built into
+  /// the library.
+  ///
+  /// If `reconfigure_devices` is true, connected devices are updated to the
+  /// new configuration, and clients are sent DeviceRemoved/DeviceAdded
+  /// messages for any device whose name or attributes changed.
+  pub fn reload_device_configuration(
+    &self,
+    device_configuration_json: &Option<String>,
+    user_device_configuration_json: &Option<String>,
+    reconfigure_devices: bool,
+  ) -> ButtplugResultFuture {
+    self.device_manager.reload_configuration(
+      device_configuration_json,
+      user_device_configuration_json,
+      reconfigure_devices,
+    )
+  }
+
   pub fn add_test_comm_manager(
     &self,
   ) -> Result<TestDeviceCommunicationManagerHelper, ButtplugServerStartupError> {
@@ -256,9 +404,9 @@ impl ButtplugServer {
     if self.connected() {
       return ButtplugHandshakeError::HandshakeAlreadyHappened.into();
     }
-    if BUTTPLUG_CURRENT_MESSAGE_SPEC_VERSION < msg.message_version {
+    if self.max_message_spec_version < msg.message_version {
       return ButtplugHandshakeError::MessageSpecVersionMismatch(
-        BUTTPLUG_CURRENT_MESSAGE_SPEC_VERSION,
+        self.max_message_spec_version,
         msg.message_version,
       )
       .into();
@@ -273,7 +421,7 @@ impl ButtplugServer {
     }
     let out_msg = messages::ServerInfo::new(
       &self.server_name,
-      BUTTPLUG_CURRENT_MESSAGE_SPEC_VERSION,
+      self.max_message_spec_version,
       self.max_ping_time.try_into().unwrap(),
     );
     let connected = self.connected.clone();
@@ -1,11 +1,22 @@
-use super::{ButtplugServer, ButtplugServerOptions, ButtplugServerStartupError};
+use super::{
+  device_manager::{UnimplementedProtocolDevice, UnsupportedDevice},
+  ButtplugServer,
+  ButtplugServerOptions,
+  ButtplugServerStartupError,
+};
 use crate::{
   connector::ButtplugConnector,
   core::{
-    errors::{ButtplugError, ButtplugServerError},
+    errors::{ButtplugDeviceError, ButtplugError, ButtplugServerError},
     messages::{self, ButtplugClientMessage, ButtplugServerMessage},
+    ButtplugResultFuture,
+  },
+  device::{filter::DeviceFilter, protocol::ButtplugProtocol},
+  server::{
+    comm_managers::DeviceCommunicationManagerStatus,
+    DeviceCommunicationManager,
+    DeviceCommunicationManagerCreator,
   },
-  server::{DeviceCommunicationManager, DeviceCommunicationManagerCreator},
   test::TestDeviceCommunicationManagerHelper,
   util::async_manager,
 };
@@ -19,6 +30,18 @@ pub enum ButtplugRemoteServerEvent {
   Connected(String),
   DeviceAdded(u32, String),
   DeviceRemoved(u32),
+  /// A device was found that doesn't match any protocol. Only sent if
+  /// [ButtplugServerOptions::report_unsupported_devices] is set.
+  UnsupportedDeviceFound(UnsupportedDevice),
+  /// A device was found that matches a protocol in the device configuration,
+  /// but the protocol isn't implemented.
+  UnimplementedProtocolDeviceFound(UnimplementedProtocolDevice),
+  /// The hardware a comm manager needs, like a bluetooth radio or dongle, can
+  /// now be used. Contains the comm manager name.
+  AdapterAvailable(String),
+  /// The hardware a comm manager needs is missing, off, or has failed.
+  /// Contains the comm manager name.
+  AdapterUnavailable(String),
   Disconnected,
 }
 
@@ -51,6 +74,9 @@ async fn run_server<ConnectorType>(
 {
   info!("Starting remote server loop");
   let shared_connector = Arc::new(connector);
+  let mut unsupported_device_receiver = server.unsupported_device_receiver();
+  let mut unimplemented_protocol_receiver = server.unimplemented_protocol_receiver();
+  let mut comm_manager_status_receiver = server.comm_manager_status_receiver();
   loop {
     select! {
       connector_msg = connector_receiver.next().fuse() => match connector_msg {
@@ -95,6 +121,33 @@ async fn run_server<ConnectorType>(
           break;
         }
       },
+      unsupported_device = unsupported_device_receiver.next().fuse() => {
+        if let Some(device) = unsupported_device {
+          if remote_event_sender.send(ButtplugRemoteServerEvent::UnsupportedDeviceFound(device)).await.is_err() {
+            error!("Cannot send event to owner, dropping and assuming local server thread has exited.");
+          }
+        }
+      },
+      unimplemented_protocol_device = unimplemented_protocol_receiver.next().fuse() => {
+        if let Some(device) = unimplemented_protocol_device {
+          if remote_event_sender.send(ButtplugRemoteServerEvent::UnimplementedProtocolDeviceFound(device)).await.is_err() {
+            error!("Cannot send event to owner, dropping and assuming local server thread has exited.");
+          }
+        }
+      },
+      comm_manager_status = comm_manager_status_receiver.next().fuse() => {
+        // Unknown is only a starting state, so there's nothing to report.
+        let event = match comm_manager_status {
+          Some((name, DeviceCommunicationManagerStatus::Available)) => Some(ButtplugRemoteServerEvent::AdapterAvailable(name)),
+          Some((name, DeviceCommunicationManagerStatus::Unavailable)) => Some(ButtplugRemoteServerEvent::AdapterUnavailable(name)),
+          _ => None,
+        };
+        if let Some(event) = event {
+          if remote_event_sender.send(event).await.is_err() {
+            error!("Cannot send event to owner, dropping and assuming local server thread has exited.");
+          }
+        }
+      },
       server_msg = server_receiver.next().fuse() => match server_msg {
         None => {
           info!("Server disconnected via server disappearance, exiting loop.");
@@ -194,6 +247,50 @@ impl ButtplugRemoteServer {
     self.server.add_comm_manager::<T>()
   }
 
+  pub fn comm_manager_names(&self) -> Vec<String> {
+    self.server.comm_manager_names()
+  }
+
+  pub fn remove_comm_manager(&self, name: &str) -> ButtplugResultFuture {
+    self.server.remove_comm_manager(name)
+  }
+
+  pub fn comm_manager_status(&self, name: &str) -> Option<DeviceCommunicationManagerStatus> {
+    self.server.comm_manager_status(name)
+  }
+
+  pub fn add_protocol<T>(&self, protocol_name: &str) -> Result<(), ButtplugDeviceError>
+  where
+    T: ButtplugProtocol,
+  {
+    self.server.add_protocol::<T>(protocol_name)
+  }
+
+  pub fn device_filter(&self) -> DeviceFilter {
+    self.server.device_filter()
+  }
+
+  pub fn set_device_filter(&self, filter: DeviceFilter) {
+    self.server.set_device_filter(filter)
+  }
+
+  pub fn unsupported_devices(&self) -> Vec<UnsupportedDevice> {
+    self.server.unsupported_devices()
+  }
+
+  pub fn reload_device_configuration(
+    &self,
+    device_configuration_json: &Option<String>,
+    user_device_configuration_json: &Option<String>,
+    reconfigure_devices: bool,
+  ) -> ButtplugResultFuture {
+    self.server.reload_device_configuration(
+      device_configuration_json,
+      user_device_configuration_json,
+      reconfigure_devices,
+    )
+  }
+
   pub fn add_test_comm_manager(
     &self,
   ) -> Result<TestDeviceCommunicationManagerHelper, ButtplugServerStartupError> {
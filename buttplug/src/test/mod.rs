@@ -13,6 +13,8 @@ pub use test_device::{
 #[cfg(feature = "server")]
 pub use test_device_comm_manager::{
   new_bluetoothle_test_device,
+  new_bluetoothle_test_device_with_cfg,
+  new_hid_test_device,
   TestDeviceCommunicationManager,
   TestDeviceCommunicationManagerHelper,
 };
@@ -45,6 +45,14 @@ impl ButtplugDeviceImplCreator for TestDeviceImplCreator {
     self.specifier.clone()
   }
 
+  fn address(&self) -> String {
+    self
+      .device_impl
+      .as_ref()
+      .expect("Cannot call address after device is taken!")
+      .address()
+  }
+
   async fn try_create_device_impl(
     &mut self,
     protocol: ProtocolDefinition,
@@ -57,6 +65,12 @@ impl ButtplugDeviceImplCreator for TestDeviceImplCreator {
         }
       }
     }
+    // HID devices don't describe their endpoints in the config, so just give
+    // them output and input report channels.
+    if protocol.hid.is_some() {
+      device.add_endpoint(&Endpoint::Tx).await;
+      device.add_endpoint(&Endpoint::Rx).await;
+    }
     Ok(Box::new(TestDevice::new(&device)))
   }
 }
@@ -2,7 +2,12 @@ use super::{TestDeviceImplCreator, TestDeviceInternal};
 use crate::{
   core::{errors::ButtplugError, ButtplugResultFuture},
   device::{
-    configuration_manager::{BluetoothLESpecifier, DeviceConfigurationManager, DeviceSpecifier},
+    configuration_manager::{
+      BluetoothLESpecifier,
+      DeviceConfigurationManager,
+      DeviceSpecifier,
+      HIDSpecifier,
+    },
     ButtplugDevice,
   },
   server::comm_managers::{
@@ -40,6 +45,29 @@ pub fn new_uninitialized_ble_test_device(
   (device_impl_clone, device_impl_creator)
 }
 
+#[allow(dead_code)]
+pub async fn new_hid_test_device(
+  name: &str,
+  vendor_id: u16,
+  product_id: u16,
+) -> Result<(ButtplugDevice, Arc<TestDeviceInternal>), ButtplugError> {
+  let config_mgr = Arc::new(DeviceConfigurationManager::default());
+  let address = SystemTime::now()
+    .duration_since(UNIX_EPOCH)
+    .unwrap()
+    .subsec_nanos()
+    .to_string();
+  let specifier = DeviceSpecifier::HID(HIDSpecifier::new(vendor_id, product_id));
+  let device_impl = Arc::new(TestDeviceInternal::new(name, &address));
+  let device_impl_creator = TestDeviceImplCreator::new(specifier, device_impl.clone());
+  let device: ButtplugDevice =
+    ButtplugDevice::try_create_device(config_mgr, Box::new(device_impl_creator))
+      .await
+      .unwrap()
+      .unwrap();
+  Ok((device, device_impl))
+}
+
 pub async fn new_bluetoothle_test_device_with_cfg(
   name: &str,
   device_config_mgr: Option<Arc<DeviceConfigurationManager>>,
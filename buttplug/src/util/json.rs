@@ -57,4 +57,27 @@ impl JSONValidator {
       )))
     }
   }
+
+  /// Validates an already parsed JSON value, based on the schema the validator
+  /// was created with. Returns the path and description of each
+  /// error found, so callers can map them back to their source.
+  ///
+  /// # Parameters
+  ///
+  /// - `value`: JSON value to validate.
+  pub fn validation_errors(&self, value: &Value) -> Vec<(String, String)> {
+    let schema = self.scope.resolve(&self.id).unwrap();
+    schema
+      .validate(value)
+      .errors
+      .iter()
+      .map(|err| {
+        let description = match err.get_detail() {
+          Some(detail) => format!("{}: {}", err.get_title(), detail),
+          None => err.get_title().to_owned(),
+        };
+        (err.get_path().to_owned(), description)
+      })
+      .collect()
+  }
 }
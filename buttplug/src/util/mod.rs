@@ -12,3 +12,4 @@ pub mod async_manager;
 pub mod future;
 pub mod json;
 pub mod logging;
+pub mod yaml;
@@ -0,0 +1,275 @@
+// Buttplug Rust Source Code File - See https://buttplug.io for more info.
+//
+// Copyright 2016-2020 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+
+//! YAML to JSON conversion, used by the
+//! [DeviceConfigurationManager][crate::device::configuration_manager::DeviceConfigurationManager]
+//! to load YAML configuration files. Keeps track of where each value was in
+//! the YAML source, so errors found when validating the converted JSON can be
+//! reported against the original file.
+
+use serde_json::{Map, Number, Value};
+use std::collections::HashMap;
+use yaml_rust::{
+  parser::{Event, MarkedEventReceiver, Parser},
+  scanner::{Marker, TScalarStyle, TokenType},
+  Yaml,
+};
+
+enum Frame {
+  // Mapping under construction, plus the key waiting for its value.
+  Mapping(Map<String, Value>, Option<String>, usize),
+  Sequence(Vec<Value>, usize),
+}
+
+#[derive(Default)]
+struct JsonBuilder {
+  stack: Vec<Frame>,
+  // Path segments for the value currently being built.
+  path: Vec<String>,
+  anchors: HashMap<usize, Value>,
+  positions: HashMap<String, Marker>,
+  root: Option<Value>,
+  error: Option<String>,
+}
+
+// Paths are built the same way as the paths in valico's validation errors,
+// which don't escape their segments.
+fn json_path(path: &[String]) -> String {
+  path.iter().map(|segment| format!("/{}", segment)).collect()
+}
+
+fn describe_position(marker: &Marker) -> String {
+  // Lines start at 1 in yaml-rust, columns start at 0.
+  format!("line {} column {}", marker.line(), marker.col() + 1)
+}
+
+fn scalar_to_json(value: String, style: TScalarStyle, tag: Option<TokenType>) -> Value {
+  let is_str_tag =
+    matches!(&tag, Some(TokenType::Tag(handle, suffix)) if handle == "!!" && suffix == "str");
+  if style != TScalarStyle::Plain || is_str_tag {
+    return Value::String(value);
+  }
+  match Yaml::from_str(&value) {
+    Yaml::Null => Value::Null,
+    Yaml::Boolean(b) => Value::Bool(b),
+    Yaml::Integer(i) => Value::Number(i.into()),
+    Yaml::Real(r) => r
+      .parse::<f64>()
+      .ok()
+      .and_then(Number::from_f64)
+      .map_or(Value::String(r), Value::Number),
+    _ => Value::String(value),
+  }
+}
+
+impl JsonBuilder {
+  fn expecting_key(&self) -> bool {
+    matches!(self.stack.last(), Some(Frame::Mapping(_, None, _)))
+  }
+
+  fn set_key(&mut self, key: String, mark: Marker) {
+    if let Some(Frame::Mapping(_, pending_key, _)) = self.stack.last_mut() {
+      *pending_key = Some(key.clone());
+    }
+    self.path.push(key);
+    self.positions.insert(json_path(&self.path), mark);
+  }
+
+  fn begin_value(&mut self, mark: Marker) {
+    match self.stack.last() {
+      Some(Frame::Sequence(items, _)) => {
+        self.path.push(items.len().to_string());
+        self.positions.insert(json_path(&self.path), mark);
+      }
+      // Mapping values are positioned at their key, which was recorded when
+      // the key was read.
+      Some(Frame::Mapping(..)) => {}
+      None => {
+        self.positions.insert(String::new(), mark);
+      }
+    }
+  }
+
+  fn end_value(&mut self, value: Value, anchor: usize) {
+    if anchor > 0 {
+      self.anchors.insert(anchor, value.clone());
+    }
+    match self.stack.last_mut() {
+      Some(Frame::Sequence(items, _)) => {
+        items.push(value);
+        self.path.pop();
+      }
+      Some(Frame::Mapping(map, key, _)) => {
+        map.insert(key.take().unwrap(), value);
+        self.path.pop();
+      }
+      None => self.root = Some(value),
+    }
+  }
+}
+
+impl MarkedEventReceiver for JsonBuilder {
+  fn on_event(&mut self, event: Event, mark: Marker) {
+    if self.error.is_some() {
+      return;
+    }
+    if self.expecting_key() {
+      match event {
+        // JSON keys can only be strings, so use scalar keys as written.
+        Event::Scalar(key, ..) => self.set_key(key, mark),
+        Event::MappingEnd => {
+          if let Some(Frame::Mapping(map, _, anchor)) = self.stack.pop() {
+            self.end_value(Value::Object(map), anchor);
+          }
+        }
+        _ => {
+          self.error = Some(format!(
+            "Only scalar mapping keys are supported, at {}",
+            describe_position(&mark)
+          ))
+        }
+      }
+      return;
+    }
+    match event {
+      Event::Scalar(value, style, anchor, tag) => {
+        self.begin_value(mark);
+        self.end_value(scalar_to_json(value, style, tag), anchor);
+      }
+      Event::Alias(anchor) => match self.anchors.get(&anchor).cloned() {
+        Some(value) => {
+          self.begin_value(mark);
+          self.end_value(value, 0);
+        }
+        None => self.error = Some(format!("Unknown alias at {}", describe_position(&mark))),
+      },
+      Event::MappingStart(anchor) => {
+        self.begin_value(mark);
+        self.stack.push(Frame::Mapping(Map::new(), None, anchor));
+      }
+      Event::SequenceStart(anchor) => {
+        self.begin_value(mark);
+        self.stack.push(Frame::Sequence(vec![], anchor));
+      }
+      Event::SequenceEnd => {
+        if let Some(Frame::Sequence(items, anchor)) = self.stack.pop() {
+          self.end_value(Value::Array(items), anchor);
+        }
+      }
+      _ => {}
+    }
+  }
+}
+
+/// A YAML document converted to a JSON value.
+#[derive(Debug)]
+pub struct YamlDocument {
+  value: Value,
+  positions: HashMap<String, Marker>,
+}
+
+impl YamlDocument {
+  /// Parses a single YAML document. Errors include the line and column of the
+  /// problem in the YAML source.
+  pub fn parse(yaml: &str) -> Result<Self, String> {
+    let mut builder = JsonBuilder::default();
+    Parser::new(yaml.chars())
+      .load(&mut builder, false)
+      .map_err(|err| err.to_string())?;
+    if let Some(err) = builder.error {
+      return Err(err);
+    }
+    Ok(Self {
+      value: builder.root.unwrap_or(Value::Null),
+      positions: builder.positions,
+    })
+  }
+
+  pub fn value(&self) -> &Value {
+    &self.value
+  }
+
+  pub fn into_value(self) -> Value {
+    self.value
+  }
+
+  /// Returns a description of where the value at `path` (as given in JSON
+  /// schema validation errors) is in the YAML source. If the value doesn't exist (for instance, a required
+  /// property that is missing), the position of the closest parent is used.
+  pub fn position(&self, path: &str) -> Option<String> {
+    let mut path = path;
+    loop {
+      if let Some(marker) = self.positions.get(path) {
+        return Some(describe_position(marker));
+      }
+      path = &path[..path.rfind('/')?];
+    }
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::YamlDocument;
+  use serde_json::json;
+
+  #[test]
+  fn test_yaml_to_json() {
+    let document = YamlDocument::parse(
+      r#"
+protocols:
+  test:
+    btle:
+      names:
+        - "03"
+        - Test*
+    count: 0x10
+    enabled: true
+    ratio: 0.5
+    empty:
+    base: &base
+      a: 1
+    copy: *base
+"#,
+    )
+    .unwrap();
+    assert_eq!(
+      document.value(),
+      &json!({
+        "protocols": {
+          "test": {
+            "btle": { "names": ["03", "Test*"] },
+            "count": 16,
+            "enabled": true,
+            "ratio": 0.5,
+            "empty": null,
+            "base": { "a": 1 },
+            "copy": { "a": 1 }
+          }
+        }
+      })
+    );
+    assert_eq!(
+      document.position("/protocols/test/btle/names/1"),
+      Some("line 7 column 11".to_owned())
+    );
+    assert_eq!(
+      document.position("/protocols/test/count"),
+      Some("line 8 column 5".to_owned())
+    );
+    // Missing values fall back to their parent.
+    assert_eq!(
+      document.position("/protocols/test/btle/services"),
+      Some("line 4 column 5".to_owned())
+    );
+  }
+
+  #[test]
+  fn test_yaml_parse_error_position() {
+    let err = YamlDocument::parse("protocols: test: 1").unwrap_err();
+    assert!(err.contains("line 1 column 16"), "{}", err);
+  }
+}
@@ -0,0 +1,85 @@
+#[cfg(feature = "server")]
+mod test {
+  use async_channel::unbounded;
+  use buttplug::{
+    client::{
+      blocking::{BlockingButtplugClient, BlockingButtplugClientDevice},
+      device::VibrateCommand,
+      ButtplugClientEvent,
+    },
+    connector::ButtplugInProcessClientConnector,
+    util::async_manager,
+  };
+  use std::sync::Arc;
+
+  fn connect_with_test_device() -> BlockingButtplugClient {
+    let connector = ButtplugInProcessClientConnector::default();
+    let helper = connector.server_ref().add_test_comm_manager().unwrap();
+    async_manager::block_on(helper.add_ble_device("Massage Demo"));
+    BlockingButtplugClient::connect("Test Client", connector).unwrap()
+  }
+
+  #[test]
+  fn test_blocking_client_connect_disconnect() {
+    let client = connect_with_test_device();
+    assert!(client.connected());
+    assert_eq!(client.server_name(), "Buttplug Server");
+    assert!(client.disconnect().is_ok());
+    assert!(!client.connected());
+  }
+
+  #[test]
+  fn test_blocking_client_event_iter() {
+    let client = connect_with_test_device();
+    client.start_scanning().unwrap();
+    let device = client
+      .event_iter()
+      .find_map(|event| {
+        if let ButtplugClientEvent::DeviceAdded(device) = event {
+          Some(BlockingButtplugClientDevice::from(device))
+        } else {
+          None
+        }
+      })
+      .unwrap();
+    assert_eq!(device.name(), "Aneros Vivi");
+    assert_eq!(client.devices().len(), 1);
+    assert!(device.vibrate(VibrateCommand::Speed(0.5)).is_ok());
+    assert!(device.stop().is_ok());
+    client.disconnect().unwrap();
+  }
+
+  #[test]
+  fn test_blocking_client_event_callback() {
+    let client = connect_with_test_device();
+    let (sender, receiver) = unbounded();
+    client.set_event_callback(Some(move |event| {
+      sender.try_send(event).unwrap();
+    }));
+    client.start_scanning().unwrap();
+    // ScanningFinished may show up before the device does.
+    assert!(
+      std::iter::from_fn(|| async_manager::block_on(receiver.recv()).ok())
+        .any(|event| matches!(event, ButtplugClientEvent::DeviceAdded(_)))
+    );
+    assert!(client.try_next_event().is_none());
+    client.disconnect().unwrap();
+  }
+
+  #[test]
+  fn test_blocking_client_event_callback_replaces_itself() {
+    let client = Arc::new(connect_with_test_device());
+    let callback_client = client.clone();
+    let (sender, receiver) = unbounded();
+    client.set_event_callback(Some(move |event| {
+      // Go back to queuing events after the first one.
+      callback_client.set_event_callback(None::<fn(ButtplugClientEvent)>);
+      sender.try_send(event).unwrap();
+    }));
+    client.start_scanning().unwrap();
+    assert!(async_manager::block_on(receiver.recv()).is_ok());
+    // Scanning sends at least one more event, which is queued instead.
+    assert!(client.event_iter().next().is_some());
+    client.disconnect().unwrap();
+  }
+}
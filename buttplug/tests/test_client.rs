@@ -1,23 +1,65 @@
 mod util;
 extern crate buttplug;
 
-use async_channel::Receiver;
+use async_channel::{bounded, Receiver, Sender};
 use buttplug::{
-  client::{ButtplugClient, ButtplugClientError, ButtplugClientEvent},
+  client::{
+    device::{ButtplugClientDevice, ButtplugClientDeviceCapability, LinearCommand, VibrateCommand},
+    device_group::ButtplugClientDeviceGroupCommand,
+    funscript::{Funscript, FunscriptMediaClock, FunscriptPlayerOptions},
+    pattern::{ButtplugClientPattern, PatternInterpolation},
+    ButtplugClient,
+    ButtplugClientError,
+    ButtplugClientEvent,
+    ButtplugClientReconnectPolicy,
+  },
   connector::{
+    transport::{
+      ButtplugConnectorTransport,
+      ButtplugConnectorTransportConnectResult,
+      ButtplugTransportMessage,
+    },
     ButtplugConnector,
     ButtplugConnectorError,
     ButtplugConnectorResultFuture,
     ButtplugInProcessClientConnector,
+    ButtplugRemoteClientConnector,
+    ButtplugRemoteServerConnector,
   },
   core::{
     errors::{ButtplugDeviceError, ButtplugError, ButtplugServerError},
-    messages::{ButtplugCurrentSpecClientMessage, ButtplugCurrentSpecServerMessage},
+    messages::{
+      serializer::ButtplugServerJSONSerializer,
+      ButtplugCurrentSpecClientMessage,
+      ButtplugCurrentSpecServerMessage,
+      ButtplugMessageSpecVersion,
+      ButtplugServerMessage,
+    },
   },
+  device::{DeviceImplCommand, Endpoint},
+  server::{ButtplugRemoteServer, ButtplugServer, ButtplugServerOptions},
   util::async_manager,
 };
-use futures::{future::BoxFuture, StreamExt};
-use util::DelayDeviceCommunicationManager;
+use futures::{
+  future::{self, BoxFuture},
+  select,
+  FutureExt,
+  StreamExt,
+};
+use futures_timer::Delay;
+use std::{
+  convert::TryInto,
+  sync::{
+    atomic::{AtomicU32, Ordering},
+    Arc,
+    Mutex,
+  },
+  time::Duration,
+};
+use util::{
+  channel_transport::{channel_transport_pair, ChannelTransport},
+  delay_device_communication_manager::DelayDeviceCommunicationManager,
+};
 
 #[derive(Default)]
 struct ButtplugFailingConnector {}
@@ -46,6 +88,140 @@ impl ButtplugConnector<ButtplugCurrentSpecClientMessage, ButtplugCurrentSpecServ
   }
 }
 
+#[cfg(feature = "server")]
+type ServerMessageSender = Sender<Result<ButtplugCurrentSpecServerMessage, ButtplugServerError>>;
+
+// Connects to a server shared between connectors, and drops the connection
+// when something is sent to drop_receiver. Used for testing reconnection.
+#[cfg(feature = "server")]
+struct ButtplugDroppableConnector {
+  server: Arc<ButtplugServer>,
+  server_receiver: Receiver<ButtplugServerMessage>,
+  drop_receiver: Receiver<()>,
+  fail_connect: bool,
+  sender: Arc<Mutex<Option<ServerMessageSender>>>,
+}
+
+#[cfg(feature = "server")]
+impl ButtplugDroppableConnector {
+  fn new(
+    server: Arc<ButtplugServer>,
+    server_receiver: Receiver<ButtplugServerMessage>,
+    drop_receiver: Receiver<()>,
+    fail_connect: bool,
+  ) -> Self {
+    Self {
+      server,
+      server_receiver,
+      drop_receiver,
+      fail_connect,
+      sender: Arc::new(Mutex::new(None)),
+    }
+  }
+}
+
+#[cfg(feature = "server")]
+impl ButtplugConnector<ButtplugCurrentSpecClientMessage, ButtplugCurrentSpecServerMessage>
+  for ButtplugDroppableConnector
+{
+  fn connect(
+    &mut self,
+  ) -> BoxFuture<
+    'static,
+    Result<
+      Receiver<Result<ButtplugCurrentSpecServerMessage, ButtplugServerError>>,
+      ButtplugConnectorError,
+    >,
+  > {
+    if self.fail_connect {
+      return ButtplugConnectorError::ConnectorNotConnected.into();
+    }
+    let (sender, receiver) = bounded(256);
+    *self.sender.lock().unwrap() = Some(sender.clone());
+    let held_sender = self.sender.clone();
+    let server = self.server.clone();
+    let mut server_receiver = self.server_receiver.clone();
+    let mut drop_receiver = self.drop_receiver.clone();
+    async_manager::spawn(async move {
+      loop {
+        select! {
+          msg = server_receiver.next().fuse() => match msg {
+            Some(msg) => {
+              let _ = sender.send(Ok(msg.try_into().unwrap())).await;
+            }
+            None => break,
+          },
+          _ = drop_receiver.next().fuse() => break,
+        };
+      }
+      // Disconnect the server before closing the channel, so it's ready for
+      // a new handshake by the time the client notices.
+      let _ = server.disconnect().await;
+      held_sender.lock().unwrap().take();
+    })
+    .unwrap();
+    Box::pin(future::ready(Ok(receiver)))
+  }
+
+  fn disconnect(&self) -> ButtplugConnectorResultFuture {
+    self.sender.lock().unwrap().take();
+    Box::pin(future::ready(Ok(())))
+  }
+
+  fn send(&self, msg: ButtplugCurrentSpecClientMessage) -> ButtplugConnectorResultFuture {
+    let sender = match self.sender.lock().unwrap().clone() {
+      Some(sender) => sender,
+      None => return ButtplugConnectorError::ConnectorNotConnected.into(),
+    };
+    let output_fut = self.server.parse_message(msg.into());
+    Box::pin(async move {
+      let output = output_fut.await.map(|msg| msg.try_into().unwrap());
+      sender
+        .send(output)
+        .await
+        .map_err(|_| ButtplugConnectorError::ConnectorNotConnected)
+    })
+  }
+}
+
+// Passes messages to an in-process server, except for device commands and
+// StopAllDevices, which the server never replies to. Used for testing
+// timeouts.
+#[cfg(feature = "server")]
+#[derive(Default)]
+struct ButtplugUnresponsiveConnector {
+  connector: ButtplugInProcessClientConnector,
+}
+
+#[cfg(feature = "server")]
+impl ButtplugConnector<ButtplugCurrentSpecClientMessage, ButtplugCurrentSpecServerMessage>
+  for ButtplugUnresponsiveConnector
+{
+  fn connect(
+    &mut self,
+  ) -> BoxFuture<
+    'static,
+    Result<
+      Receiver<Result<ButtplugCurrentSpecServerMessage, ButtplugServerError>>,
+      ButtplugConnectorError,
+    >,
+  > {
+    self.connector.connect()
+  }
+
+  fn disconnect(&self) -> ButtplugConnectorResultFuture {
+    self.connector.disconnect()
+  }
+
+  fn send(&self, msg: ButtplugCurrentSpecClientMessage) -> ButtplugConnectorResultFuture {
+    match msg {
+      ButtplugCurrentSpecClientMessage::VibrateCmd(_)
+      | ButtplugCurrentSpecClientMessage::StopAllDevices(_) => Box::pin(future::ready(Ok(()))),
+      msg => self.connector.send(msg),
+    }
+  }
+}
+
 #[cfg(feature = "server")]
 #[test]
 fn test_failing_connection() {
@@ -111,6 +287,35 @@ fn test_start_scanning() {
   });
 }
 
+#[cfg(feature = "server")]
+#[test]
+fn test_client_unimplemented_protocol_error() {
+  async_manager::block_on(async {
+    let connector = ButtplugInProcessClientConnector::default();
+    let test_mgr_helper = connector.server_ref().add_test_comm_manager().unwrap();
+    // Cueme devices are in the device configuration file, but the protocol
+    // isn't implemented yet.
+    test_mgr_helper.add_ble_device("FUNCODE_1234").await;
+    let (client, mut recv) = ButtplugClient::connect("Test Client", connector)
+      .await
+      .unwrap();
+    client.start_scanning().await.unwrap();
+    loop {
+      let event = select! {
+        event = recv.next().fuse() => event.unwrap(),
+        _ = Delay::new(Duration::from_secs(5)).fuse() => panic!("Timed out waiting for error"),
+      };
+      if let ButtplugClientEvent::Error(ButtplugError::ButtplugDeviceError(
+        ButtplugDeviceError::ProtocolNotImplemented(protocol_name),
+      )) = event
+      {
+        assert_eq!(protocol_name, "cueme");
+        break;
+      }
+    }
+  });
+}
+
 #[cfg(feature = "server")]
 #[test]
 fn test_stop_scanning_when_not_scanning() {
@@ -173,3 +378,850 @@ fn test_client_scanning_finished() {
     ));
   });
 }
+
+#[cfg(feature = "server")]
+#[test]
+fn test_client_timed_scanning() {
+  async_manager::block_on(async {
+    let connector = ButtplugInProcessClientConnector::default();
+    connector
+      .server_ref()
+      .add_comm_manager::<DelayDeviceCommunicationManager>()
+      .unwrap();
+    let (client, mut recv) = ButtplugClient::connect("Test Client", connector)
+      .await
+      .unwrap();
+    assert!(client
+      .start_scanning_with_duration(Duration::from_millis(100))
+      .await
+      .is_ok());
+    assert!(matches!(
+      recv.next().await.unwrap(),
+      ButtplugClientEvent::ScanningFinished
+    ));
+    // Scanning was stopped by the client, so there's nothing left to stop.
+    assert!(client.stop_scanning().await.is_err());
+  });
+}
+
+#[cfg(feature = "server")]
+#[test]
+fn test_client_scanning_selection() {
+  async_manager::block_on(async {
+    let connector = ButtplugInProcessClientConnector::default();
+    let test_mgr_helper = connector.server_ref().add_test_comm_manager().unwrap();
+    connector
+      .server_ref()
+      .add_comm_manager::<DelayDeviceCommunicationManager>()
+      .unwrap();
+    test_mgr_helper.add_ble_device("Massage Demo").await;
+    let (client, mut recv) = ButtplugClient::connect("Test Client", connector)
+      .await
+      .unwrap();
+    assert!(client
+      .start_scanning_with_selection(&["Bogus"], &[])
+      .await
+      .is_err());
+    assert!(client
+      .start_scanning_with_selection(&["TestDeviceCommunicationManager"], &["aneros"])
+      .await
+      .is_ok());
+    let mut added = false;
+    let mut finished = false;
+    while !added || !finished {
+      match recv.next().await.unwrap() {
+        ButtplugClientEvent::DeviceAdded(_) => added = true,
+        ButtplugClientEvent::ScanningFinished => finished = true,
+        _ => panic!("Unexpected event"),
+      }
+    }
+  });
+}
+
+// Wraps a channel transport, and closes the connection when something is sent
+// to drop_receiver. Used for testing reconnection to remote servers.
+#[cfg(feature = "server")]
+struct ButtplugDroppableTransport {
+  transport: ChannelTransport,
+  drop_receiver: Receiver<()>,
+}
+
+#[cfg(feature = "server")]
+impl ButtplugConnectorTransport for ButtplugDroppableTransport {
+  fn connect(&self) -> ButtplugConnectorTransportConnectResult {
+    let connect_fut = self.transport.connect();
+    let mut drop_receiver = self.drop_receiver.clone();
+    Box::pin(async move {
+      let (sender, mut transport_receiver) = connect_fut.await?;
+      let (forward_sender, receiver) = bounded(256);
+      async_manager::spawn(async move {
+        loop {
+          select! {
+            msg = transport_receiver.next().fuse() => match msg {
+              Some(msg) => {
+                if forward_sender.send(msg).await.is_err() {
+                  return;
+                }
+              }
+              None => break,
+            },
+            _ = drop_receiver.next().fuse() => break,
+          };
+        }
+        let _ = forward_sender
+          .send(ButtplugTransportMessage::Close("Dropped".to_owned()))
+          .await;
+      })
+      .unwrap();
+      Ok((sender, receiver))
+    })
+  }
+
+  fn disconnect(self) -> ButtplugConnectorResultFuture {
+    self.transport.disconnect()
+  }
+}
+
+#[cfg(feature = "server")]
+async fn setup_droppable_server() -> (
+  Arc<ButtplugServer>,
+  Receiver<ButtplugServerMessage>,
+  Sender<()>,
+  Receiver<()>,
+) {
+  let (server, server_receiver) = ButtplugServer::default();
+  let helper = server.add_test_comm_manager().unwrap();
+  helper.add_ble_device("Massage Demo").await;
+  let (drop_sender, drop_receiver) = bounded(256);
+  (
+    Arc::new(server),
+    server_receiver,
+    drop_sender,
+    drop_receiver,
+  )
+}
+
+#[cfg(feature = "server")]
+fn test_reconnect_policy(max_attempts: u32) -> ButtplugClientReconnectPolicy {
+  let mut policy = ButtplugClientReconnectPolicy::default();
+  policy.max_attempts = max_attempts;
+  policy.initial_delay = Duration::from_millis(10);
+  policy
+}
+
+#[cfg(feature = "server")]
+#[test]
+fn test_client_server_disconnect() {
+  async_manager::block_on(async {
+    let (server, server_receiver, drop_sender, drop_receiver) = setup_droppable_server().await;
+    let connector = ButtplugDroppableConnector::new(server, server_receiver, drop_receiver, false);
+    let (client, mut recv) = ButtplugClient::connect("Test Client", connector)
+      .await
+      .unwrap();
+    drop_sender.send(()).await.unwrap();
+    assert!(matches!(
+      recv.next().await.unwrap(),
+      ButtplugClientEvent::ServerDisconnect
+    ));
+    // Give the client time to shut down its event loop.
+    Delay::new(Duration::from_millis(100)).await;
+    assert!(!client.connected());
+    assert!(client.start_scanning().await.is_err());
+  });
+}
+
+#[cfg(feature = "server")]
+#[test]
+fn test_client_reconnect() {
+  async_manager::block_on(async {
+    let (server, server_receiver, drop_sender, drop_receiver) = setup_droppable_server().await;
+    let factory = move || {
+      ButtplugDroppableConnector::new(
+        server.clone(),
+        server_receiver.clone(),
+        drop_receiver.clone(),
+        false,
+      )
+    };
+    let (client, mut recv) =
+      ButtplugClient::connect_with_reconnect("Test Client", factory, test_reconnect_policy(5))
+        .await
+        .unwrap();
+    assert!(client.start_scanning().await.is_ok());
+    let mut device = None;
+    let mut finished = false;
+    while device.is_none() || !finished {
+      match recv.next().await.unwrap() {
+        ButtplugClientEvent::DeviceAdded(dev) => device = Some(dev),
+        ButtplugClientEvent::ScanningFinished => finished = true,
+        _ => panic!("Unexpected event"),
+      }
+    }
+    let device = device.unwrap();
+    drop_sender.send(()).await.unwrap();
+    assert!(matches!(
+      recv.next().await.unwrap(),
+      ButtplugClientEvent::Reconnecting
+    ));
+    assert!(matches!(
+      recv.next().await.unwrap(),
+      ButtplugClientEvent::Reconnected
+    ));
+    // The device is still on the server, so the old handle should work.
+    assert_eq!(client.devices().len(), 1);
+    assert!(device.vibrate(VibrateCommand::Speed(0.5)).await.is_ok());
+  });
+}
+
+#[cfg(feature = "server")]
+#[test]
+fn test_client_reconnect_spec_version() {
+  async_manager::block_on(async {
+    let (drop_sender, drop_receiver) = bounded(256);
+    // The first server speaks the current spec, the ones after it only v1.
+    let connection_count = Arc::new(AtomicU32::new(0));
+    let factory = move || {
+      let spec_version = if connection_count.fetch_add(1, Ordering::SeqCst) == 0 {
+        ButtplugMessageSpecVersion::Version2
+      } else {
+        ButtplugMessageSpecVersion::Version1
+      };
+      let options = ButtplugServerOptions {
+        max_message_spec_version: spec_version,
+        ..Default::default()
+      };
+      let (server, _) = ButtplugRemoteServer::new_with_options(&options).unwrap();
+      let (client_transport, server_transport) = channel_transport_pair();
+      async_manager::spawn(async move {
+        let connector = ButtplugRemoteServerConnector::<
+          ChannelTransport,
+          ButtplugServerJSONSerializer,
+        >::new(server_transport);
+        let _ = server.start(connector).await;
+      })
+      .unwrap();
+      ButtplugRemoteClientConnector::<ButtplugDroppableTransport>::new(ButtplugDroppableTransport {
+        transport: client_transport,
+        drop_receiver: drop_receiver.clone(),
+      })
+    };
+    let (client, mut recv) =
+      ButtplugClient::connect_with_reconnect("Test Client", factory, test_reconnect_policy(5))
+        .await
+        .unwrap();
+    assert_eq!(
+      client.server_spec_version(),
+      ButtplugMessageSpecVersion::Version2
+    );
+    drop_sender.send(()).await.unwrap();
+    assert!(matches!(
+      recv.next().await.unwrap(),
+      ButtplugClientEvent::Reconnecting
+    ));
+    assert!(matches!(
+      recv.next().await.unwrap(),
+      ButtplugClientEvent::Reconnected
+    ));
+    assert_eq!(
+      client.server_spec_version(),
+      ButtplugMessageSpecVersion::Version1
+    );
+  });
+}
+
+#[cfg(feature = "server")]
+#[test]
+fn test_client_reconnect_gives_up() {
+  async_manager::block_on(async {
+    let (server, server_receiver, drop_sender, drop_receiver) = setup_droppable_server().await;
+    // Only the first connection succeeds.
+    let connection_count = Arc::new(AtomicU32::new(0));
+    let factory = move || {
+      ButtplugDroppableConnector::new(
+        server.clone(),
+        server_receiver.clone(),
+        drop_receiver.clone(),
+        connection_count.fetch_add(1, Ordering::SeqCst) > 0,
+      )
+    };
+    let (client, mut recv) =
+      ButtplugClient::connect_with_reconnect("Test Client", factory, test_reconnect_policy(2))
+        .await
+        .unwrap();
+    drop_sender.send(()).await.unwrap();
+    assert!(matches!(
+      recv.next().await.unwrap(),
+      ButtplugClientEvent::Reconnecting
+    ));
+    assert!(matches!(
+      recv.next().await.unwrap(),
+      ButtplugClientEvent::ServerDisconnect
+    ));
+    // Give the client time to shut down its event loop.
+    Delay::new(Duration::from_millis(100)).await;
+    assert!(!client.connected());
+    assert!(client.start_scanning().await.is_err());
+  });
+}
+
+#[cfg(feature = "server")]
+async fn setup_unresponsive_client() -> (ButtplugClient, ButtplugClientDevice) {
+  let connector = ButtplugUnresponsiveConnector::default();
+  let helper = connector
+    .connector
+    .server_ref()
+    .add_test_comm_manager()
+    .unwrap();
+  helper.add_ble_device("Massage Demo").await;
+  let (client, mut recv) = ButtplugClient::connect("Test Client", connector)
+    .await
+    .unwrap();
+  assert!(client.start_scanning().await.is_ok());
+  loop {
+    if let ButtplugClientEvent::DeviceAdded(device) = recv.next().await.unwrap() {
+      return (client, device);
+    }
+  }
+}
+
+#[cfg(feature = "server")]
+#[test]
+fn test_client_default_timeout() {
+  async_manager::block_on(async {
+    let (client, device) = setup_unresponsive_client().await;
+    assert_eq!(client.default_timeout(), None);
+    client.set_default_timeout(Some(Duration::from_millis(50)));
+    assert!(matches!(
+      device.vibrate(VibrateCommand::Speed(0.5)).await,
+      Err(ButtplugClientError::MessageTimeout(_))
+    ));
+    assert!(matches!(
+      client.stop_all_devices().await,
+      Err(ButtplugClientError::MessageTimeout(_))
+    ));
+    // Messages the server replies to still work after a timeout.
+    assert!(device.stop().await.is_ok());
+  });
+}
+
+#[cfg(feature = "server")]
+#[test]
+fn test_client_per_call_timeout() {
+  async_manager::block_on(async {
+    let (client, device) = setup_unresponsive_client().await;
+    assert!(matches!(
+      device
+        .with_timeout(Duration::from_millis(50))
+        .vibrate(VibrateCommand::Speed(0.5))
+        .await,
+      Err(ButtplugClientError::MessageTimeout(_))
+    ));
+    assert!(matches!(
+      client
+        .with_timeout(Duration::from_millis(50))
+        .stop_all_devices()
+        .await,
+      Err(ButtplugClientError::MessageTimeout(_))
+    ));
+    // The per-call timeout overrides the default.
+    client.set_default_timeout(Some(Duration::from_secs(60)));
+    let timeout = Duration::from_millis(50);
+    match device
+      .with_timeout(timeout)
+      .vibrate(VibrateCommand::Speed(0.5))
+      .await
+    {
+      Err(ButtplugClientError::MessageTimeout(t)) => assert_eq!(t, timeout),
+      _ => panic!("Should've timed out"),
+    }
+  });
+}
+
+#[cfg(feature = "server")]
+async fn setup_device_with_tx(
+  name: &str,
+) -> (
+  ButtplugClient,
+  ButtplugClientDevice,
+  Receiver<DeviceImplCommand>,
+) {
+  let connector = ButtplugInProcessClientConnector::default();
+  let helper = connector.server_ref().add_test_comm_manager().unwrap();
+  let test_device = helper.add_ble_device(name).await;
+  let (client, mut recv) = ButtplugClient::connect("Test Client", connector)
+    .await
+    .unwrap();
+  client.start_scanning().await.unwrap();
+  let device = loop {
+    if let ButtplugClientEvent::DeviceAdded(device) = recv.next().await.unwrap() {
+      break device;
+    }
+  };
+  let command_receiver = test_device
+    .get_endpoint_channel(&Endpoint::Tx)
+    .unwrap()
+    .receiver;
+  (client, device, command_receiver)
+}
+
+#[cfg(feature = "server")]
+fn drain_writes(receiver: &Receiver<DeviceImplCommand>) -> Vec<Vec<u8>> {
+  let mut writes = vec![];
+  while let Ok(DeviceImplCommand::Write(cmd)) = receiver.try_recv() {
+    writes.push(cmd.data);
+  }
+  writes
+}
+
+#[cfg(feature = "server")]
+#[test]
+fn test_client_vibrate_pattern() {
+  async_manager::block_on(async {
+    let (_client, device, command_receiver) = setup_device_with_tx("Massage Demo").await;
+    let pattern = ButtplugClientPattern::ramp(
+      0.0,
+      1.0,
+      Duration::from_millis(100),
+      PatternInterpolation::Linear,
+    )
+    .update_interval(Duration::from_millis(10));
+    let handle = device.vibrate_pattern(pattern).unwrap();
+    assert!(handle.wait().await.is_ok());
+    let writes = drain_writes(&command_receiver);
+    assert!(writes.len() > 2);
+    assert_eq!(
+      writes[writes.len() - 2..],
+      [vec![0xF1, 127], vec![0xF2, 127]]
+    );
+  });
+}
+
+#[cfg(feature = "server")]
+#[test]
+fn test_client_vibrate_pattern_cancel() {
+  async_manager::block_on(async {
+    let (_client, device, command_receiver) = setup_device_with_tx("Massage Demo").await;
+    let pattern = ButtplugClientPattern::new(PatternInterpolation::Linear)
+      .keyframe(Duration::from_millis(0), vec![0.5])
+      .keyframe(Duration::from_millis(50), vec![1.0])
+      .looping(true)
+      .update_interval(Duration::from_millis(10));
+    let handle = device.vibrate_pattern(pattern).unwrap();
+    Delay::new(Duration::from_millis(100)).await;
+    assert!(!handle.is_finished());
+    handle.cancel();
+    assert!(handle.wait().await.is_ok());
+    let writes = drain_writes(&command_receiver);
+    assert_eq!(writes[writes.len() - 2..], [vec![0xF1, 0], vec![0xF2, 0]]);
+  });
+}
+
+#[cfg(feature = "server")]
+#[test]
+fn test_client_pattern_unsupported() {
+  async_manager::block_on(async {
+    let (_client, device, _) = setup_device_with_tx("Massage Demo").await;
+    let pattern = ButtplugClientPattern::ramp(
+      0.0,
+      1.0,
+      Duration::from_millis(100),
+      PatternInterpolation::Linear,
+    );
+    assert!(device.rotate_pattern(pattern).is_err());
+    let too_many_features = ButtplugClientPattern::new(PatternInterpolation::Linear)
+      .keyframe(Duration::from_millis(0), vec![0.5, 0.5, 0.5]);
+    assert!(device.vibrate_pattern(too_many_features).is_err());
+  });
+}
+
+#[cfg(feature = "server")]
+#[test]
+fn test_client_funscript_linear() {
+  async_manager::block_on(async {
+    let (_client, device, command_receiver) = setup_device_with_tx("KEON").await;
+    // Clear out initialization commands.
+    drain_writes(&command_receiver);
+    let script = Funscript::from_json(
+      r#"{"actions": [{"at": 0, "pos": 0}, {"at": 100, "pos": 100}, {"at": 200, "pos": 0}]}"#,
+    )
+    .unwrap();
+    let clock = FunscriptMediaClock::default();
+    clock.play();
+    let handle = device
+      .play_funscript(
+        script,
+        Arc::new(clock.clone()),
+        FunscriptPlayerOptions::default(),
+      )
+      .unwrap();
+    Delay::new(Duration::from_millis(300)).await;
+    let writes = drain_writes(&command_receiver);
+    // Position is the last byte of the command.
+    let positions: Vec<u8> = writes.iter().map(|write| write[3]).collect();
+    assert_eq!(positions, vec![99, 0]);
+    handle.cancel();
+    assert!(handle.wait().await.is_ok());
+  });
+}
+
+#[cfg(feature = "server")]
+#[test]
+fn test_client_funscript_linear_range() {
+  async_manager::block_on(async {
+    let (_client, device, command_receiver) = setup_device_with_tx("KEON").await;
+    // Clear out initialization commands.
+    drain_writes(&command_receiver);
+    // With a range of 90, a position of 90 is a full stroke.
+    let script = Funscript::from_json(
+      r#"{"range": 90, "actions": [{"at": 0, "pos": 0}, {"at": 100, "pos": 90}, {"at": 200, "pos": 0}]}"#,
+    )
+    .unwrap();
+    let clock = FunscriptMediaClock::default();
+    clock.play();
+    let handle = device
+      .play_funscript(
+        script,
+        Arc::new(clock.clone()),
+        FunscriptPlayerOptions::default(),
+      )
+      .unwrap();
+    Delay::new(Duration::from_millis(300)).await;
+    let writes = drain_writes(&command_receiver);
+    // Position is the last byte of the command.
+    let positions: Vec<u8> = writes.iter().map(|write| write[3]).collect();
+    assert_eq!(positions, vec![99, 0]);
+    handle.cancel();
+    assert!(handle.wait().await.is_ok());
+  });
+}
+
+#[cfg(feature = "server")]
+#[test]
+fn test_client_funscript_vibrate() {
+  async_manager::block_on(async {
+    let (_client, device, command_receiver) = setup_device_with_tx("Massage Demo").await;
+    let script =
+      Funscript::from_json(r#"{"actions": [{"at": 0, "pos": 0}, {"at": 1000, "pos": 100}]}"#)
+        .unwrap();
+    let clock = FunscriptMediaClock::default();
+    clock.play();
+    let handle = device
+      .play_funscript(
+        script,
+        Arc::new(clock.clone()),
+        FunscriptPlayerOptions::default(),
+      )
+      .unwrap();
+    Delay::new(Duration::from_millis(100)).await;
+    // One stroke per second, at a max of 4 strokes per second.
+    assert_eq!(
+      drain_writes(&command_receiver),
+      vec![vec![0xF1, 32], vec![0xF2, 32]]
+    );
+    clock.pause();
+    Delay::new(Duration::from_millis(100)).await;
+    assert_eq!(
+      drain_writes(&command_receiver),
+      vec![vec![0xF1, 0], vec![0xF2, 0]]
+    );
+    handle.cancel();
+    assert!(handle.wait().await.is_ok());
+  });
+}
+
+#[cfg(feature = "server")]
+#[test]
+fn test_client_device_capabilities() {
+  async_manager::block_on(async {
+    let (client, device, _) = setup_device_with_tx("Massage Demo").await;
+    assert_eq!(device.vibrator_count(), 2);
+    assert_eq!(device.vibrate_step_counts(), vec![127, 127]);
+    assert_eq!(device.rotator_count(), 0);
+    assert_eq!(device.linear_count(), 0);
+    assert!(device.has_capability(ButtplugClientDeviceCapability::Vibrate));
+    assert!(!device.has_capability(ButtplugClientDeviceCapability::Linear));
+    assert_eq!(
+      client
+        .devices_with(ButtplugClientDeviceCapability::Vibrate)
+        .len(),
+      1
+    );
+    assert!(client
+      .devices_with(ButtplugClientDeviceCapability::Linear)
+      .is_empty());
+  });
+}
+
+#[cfg(feature = "server")]
+#[test]
+fn test_client_device_unknown_identifier() {
+  async_manager::block_on(async {
+    let (_client, device, _) = setup_device_with_tx("Massage Demo").await;
+    assert!(device.unknown_identifier().is_none());
+    // Matches the magic-motion-1 protocol, but isn't one of its identifiers.
+    let (_client, device, _) = setup_device_with_tx("Smart Mini Vibe 7").await;
+    assert_eq!(device.unknown_identifier(), Some("Smart Mini Vibe 7"));
+  });
+}
+
+#[cfg(feature = "server")]
+#[test]
+fn test_client_device_state() {
+  async_manager::block_on(async {
+    let (client, device, _) = setup_device_with_tx("Massage Demo").await;
+    assert_eq!(device.state().vibrate, vec![0.0, 0.0]);
+    device
+      .vibrate(VibrateCommand::SpeedVec(vec![0.5, 0.25]))
+      .await
+      .unwrap();
+    assert_eq!(device.state().vibrate, vec![0.5, 0.25]);
+    // State is shared between handles for the same device.
+    assert_eq!(client.devices()[0].state().vibrate, vec![0.5, 0.25]);
+    // Rejected commands don't change state.
+    assert!(device
+      .vibrate(VibrateCommand::SpeedVec(vec![1.0, 1.0, 1.0]))
+      .await
+      .is_err());
+    assert_eq!(device.state().vibrate, vec![0.5, 0.25]);
+    client.stop_all_devices().await.unwrap();
+    assert_eq!(device.state().vibrate, vec![0.0, 0.0]);
+  });
+}
+
+#[cfg(feature = "server")]
+#[test]
+fn test_client_device_group() {
+  async_manager::block_on(async {
+    let connector = ButtplugInProcessClientConnector::default();
+    let helper = connector.server_ref().add_test_comm_manager().unwrap();
+    helper.add_ble_device("Massage Demo").await;
+    helper.add_ble_device("KEON").await;
+    let (client, mut recv) = ButtplugClient::connect("Test Client", connector)
+      .await
+      .unwrap();
+    let group = client.device_group();
+    let linear_group = client.device_group_with_filter(|device| device.linear_count() > 0);
+    assert!(group.is_empty());
+    client.start_scanning().await.unwrap();
+    let mut added = 0;
+    while added < 2 {
+      if let ButtplugClientEvent::DeviceAdded(_) = recv.next().await.unwrap() {
+        added += 1;
+      }
+    }
+    // Devices join groups as they're added.
+    assert_eq!(group.len(), 2);
+    assert_eq!(linear_group.len(), 1);
+    let state_of = |name: &str| {
+      group
+        .devices()
+        .into_iter()
+        .find(|device| device.name.contains(name))
+        .unwrap()
+        .state()
+    };
+    group.intensity(0.5).await.unwrap();
+    assert_eq!(state_of("Vivi").vibrate, vec![0.5, 0.5]);
+    assert_eq!(state_of("Keon").linear, vec![None]);
+    group
+      .send(ButtplugClientDeviceGroupCommand::Move(100, 1.0))
+      .await
+      .unwrap();
+    assert_eq!(state_of("Vivi").vibrate, vec![1.0, 1.0]);
+    assert_eq!(state_of("Keon").linear, vec![Some(1.0)]);
+    group.stop().await.unwrap();
+    assert_eq!(state_of("Vivi").vibrate, vec![0.0, 0.0]);
+  });
+}
+
+#[cfg(feature = "server")]
+#[test]
+fn test_client_wait_for_device() {
+  async_manager::block_on(async {
+    let connector = ButtplugInProcessClientConnector::default();
+    let helper = connector.server_ref().add_test_comm_manager().unwrap();
+    helper.add_ble_device("Massage Demo").await;
+    let (client, _) = ButtplugClient::connect("Test Client", connector)
+      .await
+      .unwrap();
+    let wait_fut = client.wait_for_device(
+      |device| device.name == "Aneros Vivi",
+      Duration::from_secs(1),
+    );
+    client.start_scanning().await.unwrap();
+    let device = wait_fut.await.unwrap();
+    assert_eq!(device.name, "Aneros Vivi");
+    // Devices we already know about are returned right away.
+    assert!(client
+      .wait_for_device(
+        |device| device.vibrator_count() > 0,
+        Duration::from_millis(1)
+      )
+      .await
+      .is_ok());
+    let timeout = Duration::from_millis(100);
+    match client
+      .wait_for_device(|device| device.linear_count() > 0, timeout)
+      .await
+    {
+      Err(ButtplugClientError::WaitTimeout(t)) => assert_eq!(t, timeout),
+      _ => panic!("Should've timed out!"),
+    }
+  });
+}
+
+#[cfg(feature = "server")]
+#[test]
+fn test_client_wait_for_scanning_finished() {
+  async_manager::block_on(async {
+    let connector = ButtplugInProcessClientConnector::default();
+    let helper = connector.server_ref().add_test_comm_manager().unwrap();
+    helper.add_ble_device("Massage Demo").await;
+    let (client, _) = ButtplugClient::connect("Test Client", connector)
+      .await
+      .unwrap();
+    assert!(!client.scanning());
+    client.start_scanning().await.unwrap();
+    assert!(client
+      .wait_for_scanning_finished(Duration::from_secs(1))
+      .await
+      .is_ok());
+    assert!(!client.scanning());
+    // Not scanning, so this returns right away.
+    assert!(client
+      .wait_for_scanning_finished(Duration::from_millis(1))
+      .await
+      .is_ok());
+  });
+}
+
+/// Like [setup_device_with_tx], but talks JSON to a remote server that only
+/// speaks `spec_version`, the way older server releases do.
+#[cfg(feature = "server")]
+async fn setup_legacy_server_device_with_tx(
+  name: &str,
+  spec_version: ButtplugMessageSpecVersion,
+) -> (
+  ButtplugClient,
+  ButtplugClientDevice,
+  Receiver<DeviceImplCommand>,
+) {
+  let options = ButtplugServerOptions {
+    max_message_spec_version: spec_version,
+    ..Default::default()
+  };
+  let (server, _) = ButtplugRemoteServer::new_with_options(&options).unwrap();
+  let helper = server.add_test_comm_manager().unwrap();
+  let test_device = helper.add_ble_device(name).await;
+  let (client_transport, server_transport) = channel_transport_pair();
+  async_manager::spawn(async move {
+    let connector =
+      ButtplugRemoteServerConnector::<ChannelTransport, ButtplugServerJSONSerializer>::new(
+        server_transport,
+      );
+    server.start(connector).await.unwrap();
+  })
+  .unwrap();
+  let connector = ButtplugRemoteClientConnector::<ChannelTransport>::new(client_transport);
+  let (client, mut recv) = ButtplugClient::connect("Test Client", connector)
+    .await
+    .unwrap();
+  client.start_scanning().await.unwrap();
+  let device = loop {
+    if let ButtplugClientEvent::DeviceAdded(device) = recv.next().await.unwrap() {
+      break device;
+    }
+  };
+  let command_receiver = test_device
+    .get_endpoint_channel(&Endpoint::Tx)
+    .unwrap()
+    .receiver;
+  (client, device, command_receiver)
+}
+
+#[cfg(feature = "server")]
+#[test]
+fn test_client_scanning_selection_remote() {
+  async_manager::block_on(async {
+    let (client, _, _) =
+      setup_legacy_server_device_with_tx("Massage Demo", ButtplugMessageSpecVersion::Version2)
+        .await;
+    // Remote servers never see the selection, so it's an error rather than
+    // quietly scanning with everything.
+    assert!(matches!(
+      client
+        .start_scanning_with_selection(&["TestDeviceCommunicationManager"], &[])
+        .await,
+      Err(ButtplugClientError::ScanningSelectionUnsupported)
+    ));
+  });
+}
+
+#[cfg(feature = "server")]
+#[test]
+fn test_client_legacy_server_v1() {
+  async_manager::block_on(async {
+    let (client, device, command_receiver) =
+      setup_legacy_server_device_with_tx("Massage Demo", ButtplugMessageSpecVersion::Version1)
+        .await;
+    assert_eq!(
+      client.server_spec_version(),
+      ButtplugMessageSpecVersion::Version1
+    );
+    // V1 has feature counts, but no step counts.
+    assert_eq!(device.vibrator_count(), 2);
+    assert!(device.vibrate_step_counts().is_empty());
+    assert!(!device.supports_battery_level());
+    device
+      .vibrate(VibrateCommand::SpeedVec(vec![0.5, 1.0]))
+      .await
+      .unwrap();
+    assert_eq!(
+      drain_writes(&command_receiver),
+      vec![vec![0xF1, 64], vec![0xF2, 127]]
+    );
+  });
+}
+
+#[cfg(feature = "server")]
+#[test]
+fn test_client_legacy_server_v0_vibrate() {
+  async_manager::block_on(async {
+    let (client, device, command_receiver) =
+      setup_legacy_server_device_with_tx("Massage Demo", ButtplugMessageSpecVersion::Version0)
+        .await;
+    assert_eq!(
+      client.server_spec_version(),
+      ButtplugMessageSpecVersion::Version0
+    );
+    // SingleMotorVibrateCmd shows up as a single vibrator.
+    assert_eq!(device.vibrator_count(), 1);
+    assert_eq!(client.devices().len(), 1);
+    device.vibrate(VibrateCommand::Speed(0.5)).await.unwrap();
+    let writes = drain_writes(&command_receiver);
+    assert!(writes.contains(&vec![0xF1, 64]));
+    assert!(writes.contains(&vec![0xF2, 64]));
+    assert!(device.stop().await.is_ok());
+  });
+}
+
+#[cfg(feature = "server")]
+#[test]
+fn test_client_legacy_server_v0_linear() {
+  async_manager::block_on(async {
+    let (_client, device, command_receiver) =
+      setup_legacy_server_device_with_tx("KEON", ButtplugMessageSpecVersion::Version0).await;
+    assert_eq!(device.linear_count(), 1);
+    drain_writes(&command_receiver);
+    device
+      .linear(LinearCommand::Linear(500, 0.5))
+      .await
+      .unwrap();
+    // Sent as FleshlightLaunchFW12Cmd, with the speed worked out from the
+    // distance and duration.
+    assert_eq!(
+      drain_writes(&command_receiver),
+      vec![vec![0x03, 0x00, 19, 49]]
+    );
+  });
+}
@@ -93,3 +93,188 @@ mod websocket_connector_tests {
 
   // fn test_client_ws_client_server_ws_both() {}
 }
+
+#[cfg(feature = "client")]
+mod mock_connector_tests {
+  use buttplug::{
+    client::{device::VibrateCommand, ButtplugClient, ButtplugClientError, ButtplugClientEvent},
+    connector::{ButtplugMockConnector, BUTTPLUG_MOCK_SERVER_NAME},
+    core::{
+      errors::{ButtplugDeviceError, ButtplugError},
+      messages::{
+        ButtplugCurrentSpecClientMessage,
+        ButtplugDeviceMessageType,
+        MessageAttributes,
+        MessageAttributesMap,
+      },
+    },
+    util::async_manager,
+  };
+  use futures::StreamExt;
+
+  fn vibrator_attributes() -> MessageAttributesMap {
+    let mut attributes = MessageAttributesMap::new();
+    attributes.insert(
+      ButtplugDeviceMessageType::VibrateCmd,
+      MessageAttributes {
+        feature_count: Some(1),
+        step_count: Some(vec![20]),
+        ..Default::default()
+      },
+    );
+    attributes
+  }
+
+  #[test]
+  fn test_mock_connector_handshake() {
+    async_manager::block_on(async move {
+      let (connector, handle) = ButtplugMockConnector::new();
+      let (client, _) = ButtplugClient::connect("Test Client", connector)
+        .await
+        .unwrap();
+      assert!(client.connected());
+      assert!(handle.connected());
+      assert_eq!(client.server_name, BUTTPLUG_MOCK_SERVER_NAME);
+      handle
+        .assert_sent(|msg| matches!(msg, ButtplugCurrentSpecClientMessage::RequestServerInfo(_)));
+    });
+  }
+
+  #[test]
+  fn test_mock_connector_device_messages() {
+    async_manager::block_on(async move {
+      let (connector, handle) = ButtplugMockConnector::new();
+      // Devices added before connecting show up in the device list.
+      handle
+        .add_device(0, "Test Vibrator", &vibrator_attributes())
+        .await
+        .unwrap();
+      let (client, mut recv) = ButtplugClient::connect("Test Client", connector)
+        .await
+        .unwrap();
+      assert_eq!(client.devices().len(), 1);
+      // Devices from the initial device list are also sent as events.
+      match recv.next().await.unwrap() {
+        ButtplugClientEvent::DeviceAdded(device) => assert_eq!(device.index(), 0),
+        _ => panic!("Expected DeviceAdded event"),
+      }
+      // Devices added after connecting are sent as events.
+      handle
+        .add_device(1, "Other Vibrator", &vibrator_attributes())
+        .await
+        .unwrap();
+      let device = match recv.next().await.unwrap() {
+        ButtplugClientEvent::DeviceAdded(device) => device,
+        _ => panic!("Expected DeviceAdded event"),
+      };
+      assert_eq!(device.index(), 1);
+      handle.clear_sent_messages();
+      device.vibrate(VibrateCommand::Speed(0.5)).await.unwrap();
+      handle.assert_sent(|msg| match msg {
+        ButtplugCurrentSpecClientMessage::VibrateCmd(cmd) => {
+          cmd.device_index == 1 && (cmd.speeds[0].speed - 0.5).abs() < f64::EPSILON
+        }
+        _ => false,
+      });
+      handle.assert_not_sent(|msg| match msg {
+        ButtplugCurrentSpecClientMessage::VibrateCmd(cmd) => cmd.device_index == 0,
+        _ => false,
+      });
+      handle.remove_device(1).await.unwrap();
+      assert!(matches!(
+        recv.next().await.unwrap(),
+        ButtplugClientEvent::DeviceRemoved(_)
+      ));
+    });
+  }
+
+  #[test]
+  fn test_mock_connector_scripted_error() {
+    async_manager::block_on(async move {
+      let (connector, handle) = ButtplugMockConnector::new();
+      handle.reply_with(|msg| match msg {
+        ButtplugCurrentSpecClientMessage::StartScanning(_) => Some(Err(
+          ButtplugDeviceError::DeviceConnectionError("No dongle".to_owned()).into(),
+        )),
+        _ => None,
+      });
+      let (client, _) = ButtplugClient::connect("Test Client", connector)
+        .await
+        .unwrap();
+      assert!(matches!(
+        client.start_scanning().await,
+        Err(ButtplugClientError::ButtplugError(
+          ButtplugError::ButtplugDeviceError(_)
+        ))
+      ));
+      // Messages without a scripted reply still get the default.
+      assert!(client.stop_scanning().await.is_ok());
+    });
+  }
+
+  #[test]
+  fn test_mock_connector_reply_handler_uses_handle() {
+    async_manager::block_on(async move {
+      let (connector, handle) = ButtplugMockConnector::new();
+      let handler_handle = handle.clone();
+      // Fail the second StartScanning, counting with the handle from inside
+      // the handler.
+      handle.reply_with(move |msg| match msg {
+        ButtplugCurrentSpecClientMessage::StartScanning(_) => {
+          let scan_count = handler_handle
+            .sent_messages()
+            .iter()
+            .filter(|msg| matches!(msg, ButtplugCurrentSpecClientMessage::StartScanning(_)))
+            .count();
+          if scan_count > 1 {
+            Some(Err(
+              ButtplugDeviceError::DeviceConnectionError("No dongle".to_owned()).into(),
+            ))
+          } else {
+            None
+          }
+        }
+        _ => None,
+      });
+      let (client, _) = ButtplugClient::connect("Test Client", connector)
+        .await
+        .unwrap();
+      assert!(client.start_scanning().await.is_ok());
+      assert!(client.start_scanning().await.is_err());
+    });
+  }
+
+  #[test]
+  fn test_mock_connector_wait_for_sent() {
+    async_manager::block_on(async move {
+      let (connector, handle) = ButtplugMockConnector::new();
+      let (client, _) = ButtplugClient::connect("Test Client", connector)
+        .await
+        .unwrap();
+      let waiter = handle
+        .wait_for_sent(|msg| matches!(msg, ButtplugCurrentSpecClientMessage::StopAllDevices(_)));
+      client.stop_all_devices().await.unwrap();
+      assert!(waiter.await.is_some());
+      assert!(matches!(
+        handle.last_sent_message(),
+        Some(ButtplugCurrentSpecClientMessage::StopAllDevices(_))
+      ));
+    });
+  }
+
+  #[test]
+  fn test_mock_connector_server_disconnect() {
+    async_manager::block_on(async move {
+      let (connector, handle) = ButtplugMockConnector::new();
+      let (_client, mut recv) = ButtplugClient::connect("Test Client", connector)
+        .await
+        .unwrap();
+      handle.disconnect();
+      assert!(matches!(
+        recv.next().await.unwrap(),
+        ButtplugClientEvent::ServerDisconnect
+      ));
+      assert!(!handle.connected());
+    });
+  }
+}
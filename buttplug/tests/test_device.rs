@@ -4,17 +4,97 @@ use buttplug::{
     errors::{ButtplugDeviceError, ButtplugError},
     messages::{
       self,
+      ButtplugDeviceCommandMessageUnion,
       ButtplugDeviceMessageType,
       ButtplugServerMessage,
+      MessageAttributesMap,
+      VibrateCmd,
+      VibrateSubcommand,
       BUTTPLUG_CURRENT_MESSAGE_SPEC_VERSION,
     },
   },
-  device::Endpoint,
+  device::{
+    protocol::{ButtplugProtocol, ButtplugProtocolCommandHandler, ButtplugProtocolProperties},
+    ButtplugDeviceResultFuture,
+    DeviceImpl,
+    DeviceImplCommand,
+    DeviceWriteCmd,
+    Endpoint,
+  },
   server::{ButtplugServer, ButtplugServerOptions},
+  test::check_recv_value,
   util::async_manager,
 };
+use buttplug_derive::ButtplugProtocolProperties;
 use futures::StreamExt;
-use std::matches;
+use std::{matches, sync::Arc};
+
+// Protocol implemented outside of the library, to test adding protocols at
+// runtime. Sends vibration speed as [0xaa, speed] with 10 steps.
+#[derive(ButtplugProtocolProperties)]
+struct ExternalTestProtocol {
+  name: String,
+  message_attributes: MessageAttributesMap,
+  stop_commands: Vec<ButtplugDeviceCommandMessageUnion>,
+}
+
+impl ButtplugProtocol for ExternalTestProtocol {
+  fn new_protocol(
+    name: &str,
+    message_attributes: MessageAttributesMap,
+  ) -> Box<dyn ButtplugProtocol> {
+    Box::new(Self {
+      name: name.to_owned(),
+      message_attributes,
+      stop_commands: vec![VibrateCmd::new(0, vec![VibrateSubcommand::new(0, 0.0)]).into()],
+    })
+  }
+}
+
+impl ButtplugProtocolCommandHandler for ExternalTestProtocol {
+  fn handle_vibrate_cmd(
+    &self,
+    device: Arc<Box<dyn DeviceImpl>>,
+    message: VibrateCmd,
+  ) -> ButtplugDeviceResultFuture {
+    Box::pin(async move {
+      let speed = (message.speeds[0].speed * 10f64).ceil() as u8;
+      device
+        .write_value(DeviceWriteCmd::new(Endpoint::Tx, vec![0xaa, speed], false))
+        .await?;
+      Ok(messages::Ok::default().into())
+    })
+  }
+}
+
+static EXTERNAL_PROTOCOL_USER_CONFIG: &str = r#"
+{
+  "protocols": {
+    "external-test": {
+      "btle": {
+        "names": ["External Test"],
+        "services": {
+          "0000fff0-0000-1000-8000-00805f9b34fb": {
+            "tx": "0000fff1-0000-1000-8000-00805f9b34fb"
+          }
+        }
+      },
+      "configurations": [
+        {
+          "identifier": ["External Test"],
+          "name": { "en-us": "External Test Device" },
+          "messages": {
+            "VibrateCmd": {
+              "FeatureCount": 1,
+              "StepCount": [10]
+            }
+          }
+        }
+      ]
+    }
+  }
+}
+"#;
 
 // Test devices that have protocols that support movements not all devices do.
 // For instance, the Onyx+ is part of a protocol that supports vibration, but
@@ -224,8 +304,12 @@ fn test_repeated_address_additions() {
   async_manager::block_on(async {
     let (server, mut recv) = ButtplugServer::default();
     let helper = server.add_test_comm_manager().unwrap();
-    helper.add_ble_device_with_address("Massage Demo", "SameAddress").await;
-    helper.add_ble_device_with_address("Massage Demo", "SameAddress").await;
+    helper
+      .add_ble_device_with_address("Massage Demo", "SameAddress")
+      .await;
+    helper
+      .add_ble_device_with_address("Massage Demo", "SameAddress")
+      .await;
     assert!(server
       .parse_message(
         messages::RequestServerInfo::new("Test Client", BUTTPLUG_CURRENT_MESSAGE_SPEC_VERSION)
@@ -242,7 +326,7 @@ fn test_repeated_address_additions() {
     while let Some(msg) = recv.next().await {
       match msg {
         ButtplugServerMessage::ScanningFinished(_) => continue,
-        ButtplugServerMessage::DeviceAdded(da)  => {
+        ButtplugServerMessage::DeviceAdded(da) => {
           assert_eq!(da.device_name, "Aneros Vivi");
           if device_index.is_none() {
             device_index = Some(da.device_index);
@@ -251,11 +335,11 @@ fn test_repeated_address_additions() {
             assert_eq!(da.device_index, device_index.unwrap());
             return;
           }
-        },
+        }
         ButtplugServerMessage::DeviceRemoved(dr) => {
           assert_eq!(dr.device_index, 0);
           device_removed_called = true;
-        },
+        }
         _ => {
           panic!(format!(
             "Returned message was not a DeviceAdded message or timed out: {:?}",
@@ -265,4 +349,96 @@ fn test_repeated_address_additions() {
       }
     }
   });
-}
\ No newline at end of file
+}
+
+#[test]
+fn test_external_protocol() {
+  async_manager::block_on(async {
+    let mut options = ButtplugServerOptions::default();
+    options.user_device_configuration_json = Some(EXTERNAL_PROTOCOL_USER_CONFIG.to_owned());
+    let (server, mut recv) = ButtplugServer::new_with_options(&options).unwrap();
+    server
+      .add_protocol::<ExternalTestProtocol>("external-test")
+      .unwrap();
+    assert!(matches!(
+      server.add_protocol::<ExternalTestProtocol>("external-test"),
+      Err(ButtplugDeviceError::ProtocolAlreadyAdded(_))
+    ));
+    let helper = server.add_test_comm_manager().unwrap();
+    let device = helper.add_ble_device("External Test").await;
+    server
+      .parse_message(
+        messages::RequestServerInfo::new("Test Client", BUTTPLUG_CURRENT_MESSAGE_SPEC_VERSION)
+          .into(),
+      )
+      .await
+      .unwrap();
+    server
+      .parse_message(messages::StartScanning::default().into())
+      .await
+      .unwrap();
+    while let Some(msg) = recv.next().await {
+      if let ButtplugServerMessage::ScanningFinished(_) = msg {
+        continue;
+      } else if let ButtplugServerMessage::DeviceAdded(da) = msg {
+        assert_eq!(da.device_name, "External Test Device");
+        server
+          .parse_message(
+            VibrateCmd::new(da.device_index, vec![VibrateSubcommand::new(0, 0.5)]).into(),
+          )
+          .await
+          .unwrap();
+        let command_receiver = device.get_endpoint_channel(&Endpoint::Tx).unwrap().receiver;
+        check_recv_value(
+          &command_receiver,
+          DeviceImplCommand::Write(DeviceWriteCmd::new(Endpoint::Tx, vec![0xaa, 0x05], false)),
+        )
+        .await;
+        return;
+      } else {
+        panic!(
+          "Returned message was not a DeviceAdded message or timed out: {:?}",
+          msg
+        );
+      }
+    }
+  });
+}
+
+#[test]
+fn test_external_protocol_from_options() {
+  async_manager::block_on(async {
+    let mut options = ButtplugServerOptions::default();
+    options.user_device_configuration_json = Some(EXTERNAL_PROTOCOL_USER_CONFIG.to_owned());
+    options.add_protocol::<ExternalTestProtocol>("external-test");
+    let (server, mut recv) = ButtplugServer::new_with_options(&options).unwrap();
+    // Protocols from options are registered the same way as add_protocol.
+    assert!(matches!(
+      server.add_protocol::<ExternalTestProtocol>("external-test"),
+      Err(ButtplugDeviceError::ProtocolAlreadyAdded(_))
+    ));
+    let helper = server.add_test_comm_manager().unwrap();
+    helper.add_ble_device("External Test").await;
+    server
+      .parse_message(
+        messages::RequestServerInfo::new("Test Client", BUTTPLUG_CURRENT_MESSAGE_SPEC_VERSION)
+          .into(),
+      )
+      .await
+      .unwrap();
+    server
+      .parse_message(messages::StartScanning::default().into())
+      .await
+      .unwrap();
+    while let Some(msg) = recv.next().await {
+      match msg {
+        ButtplugServerMessage::ScanningFinished(_) => continue,
+        ButtplugServerMessage::DeviceAdded(da) => {
+          assert_eq!(da.device_name, "External Test Device");
+          break;
+        }
+        _ => panic!("Unexpected message {:?}", msg),
+      }
+    }
+  });
+}
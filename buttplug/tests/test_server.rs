@@ -6,19 +6,28 @@ use buttplug::{
     errors::{ButtplugDeviceError, ButtplugError, ButtplugHandshakeError},
     messages::{
       self,
+      ButtplugDeviceMessageType,
       ButtplugMessageSpecVersion,
       ButtplugServerMessage,
       BUTTPLUG_CURRENT_MESSAGE_SPEC_VERSION,
     },
   },
-  device::{DeviceImplCommand, DeviceWriteCmd, Endpoint},
-  server::{ButtplugServer, ButtplugServerOptions},
+  device::{filter::DeviceFilter, DeviceImplCommand, DeviceWriteCmd, Endpoint},
+  server::{
+    comm_managers::DeviceCommunicationManagerStatus,
+    ButtplugServer,
+    ButtplugServerOptions,
+  },
   test::check_recv_value,
   util::async_manager,
 };
-use futures::StreamExt;
+use futures::{select, FutureExt, StreamExt};
 use futures_timer::Delay;
 use std::time::Duration;
+use util::{
+  delay_device_communication_manager::DelayDeviceCommunicationManager,
+  unavailable_device_communication_manager::UnavailableDeviceCommunicationManager,
+};
 
 async fn setup_test_server(
   msg_union: messages::ButtplugClientMessage,
@@ -295,12 +304,653 @@ fn test_server_scanning_finished() {
     }
     assert!(finish_received);
     server
-      .add_comm_manager::<util::DelayDeviceCommunicationManager>()
+      .add_comm_manager::<DelayDeviceCommunicationManager>()
+      .unwrap();
+    helper.add_ble_device("Massage Demo").await;
+    assert!(server
+      .parse_message(messages::StartScanning::default().into())
+      .await
+      .is_ok());
+  });
+}
+
+#[test]
+fn test_unimplemented_protocol_device_error() {
+  async_manager::block_on(async {
+    let (server, mut recv) = ButtplugServer::default();
+    assert!(server
+      .unimplemented_protocols()
+      .contains(&"cueme".to_owned()));
+    let mut unimplemented_receiver = server.unimplemented_protocol_receiver();
+    let helper = server.add_test_comm_manager().unwrap();
+    // Cueme devices are in the device configuration file, but the protocol
+    // isn't implemented yet.
+    helper.add_ble_device("FUNCODE_1234").await;
+    assert!(server
+      .parse_message(
+        messages::RequestServerInfo::new("Test Client", BUTTPLUG_CURRENT_MESSAGE_SPEC_VERSION)
+          .into()
+      )
+      .await
+      .is_ok());
+    assert!(server
+      .parse_message(messages::StartScanning::default().into())
+      .await
+      .is_ok());
+    let device = select! {
+      device = unimplemented_receiver.next().fuse() => device.unwrap(),
+      _ = Delay::new(Duration::from_secs(5)).fuse() => panic!("Timed out waiting for event"),
+    };
+    assert_eq!(device.protocol_name(), "cueme");
+    loop {
+      let msg = select! {
+        msg = recv.next().fuse() => msg.unwrap(),
+        _ = Delay::new(Duration::from_secs(5)).fuse() => panic!("Timed out waiting for error"),
+      };
+      match msg {
+        ButtplugServerMessage::ScanningFinished(_) => continue,
+        ButtplugServerMessage::Error(e) => {
+          assert_eq!(e.error_code, messages::ErrorCode::ErrorDevice);
+          assert!(e.error_message.contains("cueme"));
+          break;
+        }
+        _ => panic!(
+          "Expected an error about an unimplemented protocol, got {:?}",
+          msg
+        ),
+      }
+    }
+  });
+}
+
+#[test]
+fn test_device_filter() {
+  async_manager::block_on(async {
+    let mut options = ButtplugServerOptions::default();
+    options
+      .device_filter
+      .deny
+      .addresses
+      .push("blocked".to_owned());
+    let (server, mut recv) = ButtplugServer::new_with_options(&options).unwrap();
+    let helper = server.add_test_comm_manager().unwrap();
+    helper
+      .add_ble_device_with_address("Massage Demo", "blocked")
+      .await;
+    helper
+      .add_ble_device_with_address("Massage Demo", "allowed")
+      .await;
+    assert!(server
+      .parse_message(
+        messages::RequestServerInfo::new("Test Client", BUTTPLUG_CURRENT_MESSAGE_SPEC_VERSION)
+          .into()
+      )
+      .await
+      .is_ok());
+    assert!(server
+      .parse_message(messages::StartScanning::default().into())
+      .await
+      .is_ok());
+    let mut added = 0u32;
+    let mut finished = false;
+    while added == 0 || !finished {
+      match recv.next().await.unwrap() {
+        ButtplugServerMessage::ScanningFinished(_) => finished = true,
+        ButtplugServerMessage::DeviceAdded(_) => added += 1,
+        msg => panic!("Unexpected message {:?}", msg),
+      }
+    }
+    // Give a blocked device time to show up if the filter didn't work.
+    Delay::new(Duration::from_millis(100)).await;
+    assert!(recv.is_empty());
+
+    // Clearing the filter should let the device through on the next scan.
+    server.set_device_filter(DeviceFilter::default());
+    assert!(server.device_filter().is_empty());
+    helper
+      .add_ble_device_with_address("Massage Demo", "blocked")
+      .await;
+    assert!(server
+      .parse_message(messages::StartScanning::default().into())
+      .await
+      .is_ok());
+    while let Some(msg) = recv.next().await {
+      match msg {
+        ButtplugServerMessage::ScanningFinished(_) => continue,
+        ButtplugServerMessage::DeviceAdded(da) => {
+          assert_eq!(da.device_name, "Aneros Vivi");
+          break;
+        }
+        _ => panic!("Unexpected message {:?}", msg),
+      }
+    }
+  });
+}
+
+#[test]
+fn test_reload_device_configuration() {
+  async_manager::block_on(async {
+    let (server, mut recv) = ButtplugServer::default();
+    let helper = server.add_test_comm_manager().unwrap();
+    helper.add_ble_device("Massage Demo").await;
+    assert!(server
+      .parse_message(
+        messages::RequestServerInfo::new("Test Client", BUTTPLUG_CURRENT_MESSAGE_SPEC_VERSION)
+          .into()
+      )
+      .await
+      .is_ok());
+    assert!(server
+      .parse_message(messages::StartScanning::default().into())
+      .await
+      .is_ok());
+    // Wait for scanning to finish as well, so it doesn't get mixed up with
+    // the reload messages.
+    let mut device_index = None;
+    let mut finished = false;
+    while device_index.is_none() || !finished {
+      match recv.next().await.unwrap() {
+        ButtplugServerMessage::ScanningFinished(_) => finished = true,
+        ButtplugServerMessage::DeviceAdded(da) => {
+          assert_eq!(da.device_name, "Aneros Vivi");
+          device_index = Some(da.device_index);
+        }
+        msg => panic!("Unexpected message {:?}", msg),
+      }
+    }
+    let device_index = device_index.unwrap();
+
+    // Invalid configuration should fail without changing anything.
+    assert!(server
+      .reload_device_configuration(&None, &Some("{".to_owned()), true)
+      .await
+      .is_err());
+
+    let user_config = Some(
+      r#"
+      {
+        "protocols": {
+          "aneros": {
+            "configurations": [
+              {
+                "identifier": ["Massage Demo"],
+                "name": { "en-us": "My Vivi" }
+              }
+            ]
+          }
+        }
+      }
+      "#
+      .to_owned(),
+    );
+    server
+      .reload_device_configuration(&None, &user_config, true)
+      .await
+      .unwrap();
+    match recv.next().await.unwrap() {
+      ButtplugServerMessage::DeviceRemoved(dr) => assert_eq!(dr.device_index, device_index),
+      msg => panic!("Expected DeviceRemoved, got {:?}", msg),
+    }
+    match recv.next().await.unwrap() {
+      ButtplugServerMessage::DeviceAdded(da) => {
+        assert_eq!(da.device_index, device_index);
+        assert_eq!(da.device_name, "My Vivi");
+      }
+      msg => panic!("Expected DeviceAdded, got {:?}", msg),
+    }
+
+    // Reloading the same configuration shouldn't change the device again.
+    server
+      .reload_device_configuration(&None, &user_config, true)
+      .await
+      .unwrap();
+    assert!(recv.is_empty());
+  });
+}
+
+#[test]
+fn test_unknown_device_identifier() {
+  async_manager::block_on(async {
+    let (server, mut recv) = ButtplugServer::default();
+    let helper = server.add_test_comm_manager().unwrap();
+    // Matches the "Smart Mini Vibe*" name of the magic-motion-1 protocol, but
+    // isn't one of its configured identifiers.
+    helper.add_ble_device("Smart Mini Vibe 7").await;
+    assert!(server
+      .parse_message(
+        messages::RequestServerInfo::new("Test Client", BUTTPLUG_CURRENT_MESSAGE_SPEC_VERSION)
+          .into()
+      )
+      .await
+      .is_ok());
+    assert!(server
+      .parse_message(messages::StartScanning::default().into())
+      .await
+      .is_ok());
+    while let Some(msg) = recv.next().await {
+      match msg {
+        ButtplugServerMessage::ScanningFinished(_) => continue,
+        ButtplugServerMessage::DeviceAdded(da) => {
+          assert_eq!(da.device_name, "Generic Device");
+          assert!(da
+            .device_messages
+            .contains_key(&ButtplugDeviceMessageType::VibrateCmd));
+          assert_eq!(da.unknown_identifier.as_deref(), Some("Smart Mini Vibe 7"));
+          break;
+        }
+        _ => panic!("Unexpected message {:?}", msg),
+      }
+    }
+    let reply = server
+      .parse_message(messages::RequestDeviceList::default().into())
+      .await
+      .unwrap();
+    if let ButtplugServerMessage::DeviceList(list) = reply {
+      assert_eq!(
+        list.devices[0].unknown_identifier.as_deref(),
+        Some("Smart Mini Vibe 7")
+      );
+    } else {
+      panic!("Expected DeviceList, got {:?}", reply);
+    }
+  });
+}
+
+#[test]
+fn test_unsupported_device_reporting() {
+  async_manager::block_on(async {
+    let mut options = ButtplugServerOptions::default();
+    options.report_unsupported_devices = true;
+    let (server, mut recv) = ButtplugServer::new_with_options(&options).unwrap();
+    // Every receiver gets every unsupported device.
+    let unsupported_receiver = server.unsupported_device_receiver();
+    let other_unsupported_receiver = server.unsupported_device_receiver();
+    let helper = server.add_test_comm_manager().unwrap();
+    helper
+      .add_ble_device_with_address("Not A Real Toy", "unsupported")
+      .await;
+    helper
+      .add_ble_device_with_address("Massage Demo", "supported")
+      .await;
+    assert!(server
+      .parse_message(
+        messages::RequestServerInfo::new("Test Client", BUTTPLUG_CURRENT_MESSAGE_SPEC_VERSION)
+          .into()
+      )
+      .await
+      .is_ok());
+    assert!(server
+      .parse_message(messages::StartScanning::default().into())
+      .await
+      .is_ok());
+    let device = unsupported_receiver.recv().await.unwrap();
+    assert_eq!(
+      other_unsupported_receiver.recv().await.unwrap().address(),
+      "unsupported"
+    );
+    assert_eq!(device.address(), "unsupported");
+    assert_eq!(device.specifier_type(), "btle");
+    assert_eq!(device.name(), Some("Not A Real Toy".to_owned()));
+    assert!(device.services().is_empty());
+    let unsupported = server.unsupported_devices();
+    assert_eq!(unsupported.len(), 1);
+    assert_eq!(unsupported[0].address(), "unsupported");
+    // Unsupported devices aren't announced to clients.
+    while let Some(msg) = recv.next().await {
+      match msg {
+        ButtplugServerMessage::ScanningFinished(_) => continue,
+        ButtplugServerMessage::DeviceAdded(da) => {
+          assert_eq!(da.device_name, "Aneros Vivi");
+          break;
+        }
+        _ => panic!("Unexpected message {:?}", msg),
+      }
+    }
+  });
+}
+
+#[test]
+fn test_unsupported_device_reporting_disabled() {
+  async_manager::block_on(async {
+    let (server, mut recv) = ButtplugServer::default();
+    let unsupported_receiver = server.unsupported_device_receiver();
+    let helper = server.add_test_comm_manager().unwrap();
+    helper.add_ble_device("Not A Real Toy").await;
+    assert!(server
+      .parse_message(
+        messages::RequestServerInfo::new("Test Client", BUTTPLUG_CURRENT_MESSAGE_SPEC_VERSION)
+          .into()
+      )
+      .await
+      .is_ok());
+    assert!(server
+      .parse_message(messages::StartScanning::default().into())
+      .await
+      .is_ok());
+    match recv.next().await.unwrap() {
+      ButtplugServerMessage::ScanningFinished(_) => {}
+      msg => panic!("Unexpected message {:?}", msg),
+    }
+    // Give the device time to be reported if it was going to be.
+    Delay::new(Duration::from_millis(100)).await;
+    assert!(server.unsupported_devices().is_empty());
+    assert!(unsupported_receiver.is_empty());
+  });
+}
+
+#[test]
+fn test_scanning_finished_waits_for_all_comm_managers() {
+  async_manager::block_on(async {
+    let (server, mut recv) = ButtplugServer::default();
+    let helper = server.add_test_comm_manager().unwrap();
+    server
+      .add_comm_manager::<DelayDeviceCommunicationManager>()
       .unwrap();
     helper.add_ble_device("Massage Demo").await;
+    assert!(server
+      .parse_message(
+        messages::RequestServerInfo::new("Test Client", BUTTPLUG_CURRENT_MESSAGE_SPEC_VERSION)
+          .into()
+      )
+      .await
+      .is_ok());
+    assert!(server
+      .parse_message(messages::StartScanning::default().into())
+      .await
+      .is_ok());
+    // The test comm manager finishes right away, but the delay comm manager
+    // keeps scanning until told to stop.
+    match recv.next().await.unwrap() {
+      ButtplugServerMessage::DeviceAdded(_) => {}
+      msg => panic!("Unexpected message {:?}", msg),
+    }
+    Delay::new(Duration::from_millis(100)).await;
+    assert!(recv.is_empty());
+    assert!(server
+      .parse_message(messages::StopScanning::default().into())
+      .await
+      .is_ok());
+    match recv.next().await.unwrap() {
+      ButtplugServerMessage::ScanningFinished(_) => {}
+      msg => panic!("Unexpected message {:?}", msg),
+    }
+    Delay::new(Duration::from_millis(100)).await;
+    assert!(recv.is_empty());
+  });
+}
+
+#[test]
+fn test_timed_scanning() {
+  async_manager::block_on(async {
+    let mut options = ButtplugServerOptions::default();
+    options.scan_duration = 100;
+    let (server, mut recv) = ButtplugServer::new_with_options(&options).unwrap();
+    server
+      .add_comm_manager::<DelayDeviceCommunicationManager>()
+      .unwrap();
+    assert!(server
+      .parse_message(
+        messages::RequestServerInfo::new("Test Client", BUTTPLUG_CURRENT_MESSAGE_SPEC_VERSION)
+          .into()
+      )
+      .await
+      .is_ok());
+    assert!(server
+      .parse_message(messages::StartScanning::default().into())
+      .await
+      .is_ok());
+    assert!(recv.is_empty());
+    match recv.next().await.unwrap() {
+      ButtplugServerMessage::ScanningFinished(_) => {}
+      msg => panic!("Unexpected message {:?}", msg),
+    }
+    // Scanning has stopped, so there's nothing left to stop.
+    assert!(server
+      .parse_message(messages::StopScanning::default().into())
+      .await
+      .is_err());
+    Delay::new(Duration::from_millis(100)).await;
+    assert!(recv.is_empty());
+  });
+}
+
+#[test]
+fn test_timed_scanning_stopped_early() {
+  async_manager::block_on(async {
+    let mut options = ButtplugServerOptions::default();
+    options.scan_duration = 200;
+    let (server, mut recv) = ButtplugServer::new_with_options(&options).unwrap();
+    server
+      .add_comm_manager::<DelayDeviceCommunicationManager>()
+      .unwrap();
+    assert!(server
+      .parse_message(
+        messages::RequestServerInfo::new("Test Client", BUTTPLUG_CURRENT_MESSAGE_SPEC_VERSION)
+          .into()
+      )
+      .await
+      .is_ok());
     assert!(server
       .parse_message(messages::StartScanning::default().into())
       .await
       .is_ok());
+    assert!(server
+      .parse_message(messages::StopScanning::default().into())
+      .await
+      .is_ok());
+    match recv.next().await.unwrap() {
+      ButtplugServerMessage::ScanningFinished(_) => {}
+      msg => panic!("Unexpected message {:?}", msg),
+    }
+    // Start a new session, which the first session's timer shouldn't stop.
+    assert!(server
+      .parse_message(messages::StartScanning::default().into())
+      .await
+      .is_ok());
+    Delay::new(Duration::from_millis(100)).await;
+    assert!(recv.is_empty());
+    match recv.next().await.unwrap() {
+      ButtplugServerMessage::ScanningFinished(_) => {}
+      msg => panic!("Unexpected message {:?}", msg),
+    }
+  });
+}
+
+#[test]
+fn test_scanning_selected_comm_managers() {
+  async_manager::block_on(async {
+    let (server, mut recv) = ButtplugServer::default();
+    let helper = server.add_test_comm_manager().unwrap();
+    server
+      .add_comm_manager::<DelayDeviceCommunicationManager>()
+      .unwrap();
+    let mut names = server.comm_manager_names();
+    names.sort();
+    assert_eq!(
+      names,
+      vec![
+        "DelayDeviceCommunicationManager".to_owned(),
+        "TestDeviceCommunicationManager".to_owned()
+      ]
+    );
+    helper.add_ble_device("Massage Demo").await;
+    assert!(server
+      .parse_message(
+        messages::RequestServerInfo::new("Test Client", BUTTPLUG_CURRENT_MESSAGE_SPEC_VERSION)
+          .into()
+      )
+      .await
+      .is_ok());
+    assert!(server
+      .parse_message(messages::StartScanning::new_with_selection(&["Bogus"], &[]).into())
+      .await
+      .is_err());
+    assert!(server
+      .parse_message(
+        messages::StartScanning::new_with_selection(&["TestDeviceCommunicationManager"], &[])
+          .into()
+      )
+      .await
+      .is_ok());
+    // The delay comm manager would keep scanning until stopped, so getting
+    // ScanningFinished means it was never started.
+    let mut added = false;
+    let mut finished = false;
+    while !added || !finished {
+      match recv.next().await.unwrap() {
+        ButtplugServerMessage::DeviceAdded(_) => added = true,
+        ButtplugServerMessage::ScanningFinished(_) => finished = true,
+        msg => panic!("Unexpected message {:?}", msg),
+      }
+    }
+  });
+}
+
+#[test]
+fn test_scanning_selected_protocols() {
+  async_manager::block_on(async {
+    let (server, mut recv) = ButtplugServer::default();
+    let helper = server.add_test_comm_manager().unwrap();
+    helper.add_ble_device("Massage Demo").await;
+    helper.add_ble_device("Smart Mini Vibe").await;
+    assert!(server
+      .parse_message(
+        messages::RequestServerInfo::new("Test Client", BUTTPLUG_CURRENT_MESSAGE_SPEC_VERSION)
+          .into()
+      )
+      .await
+      .is_ok());
+    assert!(server
+      .parse_message(messages::StartScanning::new_with_selection(&[], &["aneros"]).into())
+      .await
+      .is_ok());
+    let mut added = false;
+    let mut finished = false;
+    while !added || !finished {
+      match recv.next().await.unwrap() {
+        ButtplugServerMessage::DeviceAdded(da) => {
+          assert_eq!(da.device_name, "Aneros Vivi");
+          added = true;
+        }
+        ButtplugServerMessage::ScanningFinished(_) => finished = true,
+        msg => panic!("Unexpected message {:?}", msg),
+      }
+    }
+    // Give the unselected device time to show up if it wasn't filtered.
+    Delay::new(Duration::from_millis(100)).await;
+    assert!(recv.is_empty());
+  });
+}
+
+#[test]
+fn test_remove_comm_manager() {
+  async_manager::block_on(async {
+    let (server, mut recv) = ButtplugServer::default();
+    server
+      .add_comm_manager::<DelayDeviceCommunicationManager>()
+      .unwrap();
+    assert!(server
+      .parse_message(
+        messages::RequestServerInfo::new("Test Client", BUTTPLUG_CURRENT_MESSAGE_SPEC_VERSION)
+          .into()
+      )
+      .await
+      .is_ok());
+    assert!(server
+      .parse_message(messages::StartScanning::default().into())
+      .await
+      .is_ok());
+    // Removing a comm manager while it scans stops it, which finishes the
+    // scanning session since nothing else is scanning.
+    assert!(server
+      .remove_comm_manager("DelayDeviceCommunicationManager")
+      .await
+      .is_ok());
+    match recv.next().await.unwrap() {
+      ButtplugServerMessage::ScanningFinished(_) => {}
+      msg => panic!("Unexpected message {:?}", msg),
+    }
+    assert!(server.comm_manager_names().is_empty());
+    assert!(server
+      .remove_comm_manager("DelayDeviceCommunicationManager")
+      .await
+      .is_err());
+    // The same comm manager type can be added again once removed.
+    assert!(server
+      .add_comm_manager::<DelayDeviceCommunicationManager>()
+      .is_ok());
+  });
+}
+
+#[test]
+fn test_remove_comm_manager_keeps_devices() {
+  async_manager::block_on(async {
+    let (server, mut recv) = ButtplugServer::default();
+    let helper = server.add_test_comm_manager().unwrap();
+    helper.add_ble_device("Massage Demo").await;
+    assert!(server
+      .parse_message(
+        messages::RequestServerInfo::new("Test Client", BUTTPLUG_CURRENT_MESSAGE_SPEC_VERSION)
+          .into()
+      )
+      .await
+      .is_ok());
+    assert!(server
+      .parse_message(messages::StartScanning::default().into())
+      .await
+      .is_ok());
+    let mut added = false;
+    let mut finished = false;
+    while !added || !finished {
+      match recv.next().await.unwrap() {
+        ButtplugServerMessage::DeviceAdded(_) => added = true,
+        ButtplugServerMessage::ScanningFinished(_) => finished = true,
+        msg => panic!("Unexpected message {:?}", msg),
+      }
+    }
+    assert!(server
+      .remove_comm_manager("TestDeviceCommunicationManager")
+      .await
+      .is_ok());
+    match server
+      .parse_message(messages::RequestDeviceList::default().into())
+      .await
+      .unwrap()
+    {
+      ButtplugServerMessage::DeviceList(list) => assert_eq!(list.devices.len(), 1),
+      msg => panic!("Unexpected message {:?}", msg),
+    }
+  });
+}
+
+#[test]
+fn test_comm_manager_status() {
+  async_manager::block_on(async {
+    let (server, _) = ButtplugServer::default();
+    // Every receiver gets every status change.
+    let mut status_recv = server.comm_manager_status_receiver();
+    let mut other_status_recv = server.comm_manager_status_receiver();
+    server
+      .add_comm_manager::<DelayDeviceCommunicationManager>()
+      .unwrap();
+    server
+      .add_comm_manager::<UnavailableDeviceCommunicationManager>()
+      .unwrap();
+    let expected_status = (
+      "UnavailableDeviceCommunicationManager".to_owned(),
+      DeviceCommunicationManagerStatus::Unavailable,
+    );
+    assert_eq!(status_recv.next().await.unwrap(), expected_status);
+    assert_eq!(other_status_recv.next().await.unwrap(), expected_status);
+    assert_eq!(
+      server.comm_manager_status("UnavailableDeviceCommunicationManager"),
+      Some(DeviceCommunicationManagerStatus::Unavailable)
+    );
+    // Comm managers that don't need hardware are always available.
+    assert_eq!(
+      server.comm_manager_status("DelayDeviceCommunicationManager"),
+      Some(DeviceCommunicationManagerStatus::Available)
+    );
+    assert_eq!(server.comm_manager_status("Bogus"), None);
   });
 }
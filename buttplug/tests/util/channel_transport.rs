@@ -0,0 +1,65 @@
+use async_channel::{unbounded, Receiver, Sender};
+use buttplug::{
+  connector::{
+    transport::{
+      ButtplugConnectorTransport,
+      ButtplugConnectorTransportConnectResult,
+      ButtplugTransportMessage,
+    },
+    ButtplugConnectorResultFuture,
+  },
+  core::messages::serializer::ButtplugSerializedMessage,
+  util::async_manager,
+};
+use futures::{future, StreamExt};
+
+/// Transport that hands serialized messages straight to its paired
+/// transport, for running remote connectors in the same process.
+pub struct ChannelTransport {
+  peer_sender: Sender<ButtplugTransportMessage>,
+  receiver: Receiver<ButtplugTransportMessage>,
+}
+
+/// Creates two transports connected to each other.
+pub fn channel_transport_pair() -> (ChannelTransport, ChannelTransport) {
+  let (first_sender, first_receiver) = unbounded();
+  let (second_sender, second_receiver) = unbounded();
+  (
+    ChannelTransport {
+      peer_sender: second_sender,
+      receiver: first_receiver,
+    },
+    ChannelTransport {
+      peer_sender: first_sender,
+      receiver: second_receiver,
+    },
+  )
+}
+
+impl ButtplugConnectorTransport for ChannelTransport {
+  fn connect(&self) -> ButtplugConnectorTransportConnectResult {
+    let (outgoing_sender, mut outgoing_receiver) = unbounded::<ButtplugSerializedMessage>();
+    let peer_sender = self.peer_sender.clone();
+    async_manager::spawn(async move {
+      while let Some(msg) = outgoing_receiver.next().await {
+        if peer_sender
+          .send(ButtplugTransportMessage::Message(msg))
+          .await
+          .is_err()
+        {
+          return;
+        }
+      }
+      // Our connector went away, so let the other side know.
+      let _ = peer_sender
+        .send(ButtplugTransportMessage::Close("Channel closed".to_owned()))
+        .await;
+    })
+    .unwrap();
+    Box::pin(future::ready(Ok((outgoing_sender, self.receiver.clone()))))
+  }
+
+  fn disconnect(self) -> ButtplugConnectorResultFuture {
+    Box::pin(future::ready(Ok(())))
+  }
+}
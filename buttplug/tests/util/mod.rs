@@ -1,5 +1,11 @@
-mod delay_device_communication_manager;
-pub use delay_device_communication_manager::DelayDeviceCommunicationManager;
+// Only used by some test crates, so reference these by path instead of
+// re-exporting them.
+#[allow(dead_code)]
+pub mod channel_transport;
+#[allow(dead_code)]
+pub mod delay_device_communication_manager;
+#[allow(dead_code)]
+pub mod unavailable_device_communication_manager;
 
 #[allow(dead_code)]
 pub fn setup_logging() {
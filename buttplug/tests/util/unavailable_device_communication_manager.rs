@@ -0,0 +1,53 @@
+use async_channel::Sender;
+use buttplug::{
+  core::{errors::ButtplugDeviceError, ButtplugResultFuture},
+  server::comm_managers::{
+    DeviceCommunicationEvent,
+    DeviceCommunicationManager,
+    DeviceCommunicationManagerCreator,
+    DeviceCommunicationManagerStatus,
+    DeviceCommunicationManagerStatusReporter,
+  },
+  util::async_manager,
+};
+
+// Acts like a comm manager whose radio is missing.
+pub struct UnavailableDeviceCommunicationManager {
+  status_reporter: DeviceCommunicationManagerStatusReporter,
+}
+
+impl DeviceCommunicationManagerCreator for UnavailableDeviceCommunicationManager {
+  fn new(sender: Sender<DeviceCommunicationEvent>) -> Self {
+    let status_reporter = DeviceCommunicationManagerStatusReporter::new(
+      "UnavailableDeviceCommunicationManager",
+      DeviceCommunicationManagerStatus::Unknown,
+      sender,
+    );
+    let reporter_clone = status_reporter.clone();
+    async_manager::spawn(async move {
+      reporter_clone
+        .set_status(DeviceCommunicationManagerStatus::Unavailable)
+        .await;
+    })
+    .unwrap();
+    Self { status_reporter }
+  }
+}
+
+impl DeviceCommunicationManager for UnavailableDeviceCommunicationManager {
+  fn name(&self) -> &'static str {
+    "UnavailableDeviceCommunicationManager"
+  }
+
+  fn start_scanning(&self) -> ButtplugResultFuture {
+    ButtplugDeviceError::UnhandledCommand("No adapter available".to_owned()).into()
+  }
+
+  fn stop_scanning(&self) -> ButtplugResultFuture {
+    ButtplugDeviceError::DeviceScanningAlreadyStopped.into()
+  }
+
+  fn status(&self) -> DeviceCommunicationManagerStatus {
+    self.status_reporter.status()
+  }
+}